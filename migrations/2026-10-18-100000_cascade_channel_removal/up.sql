@@ -0,0 +1,10 @@
+-- Removing a channel takes its members, messages and bans with it
+ALTER TABLE members
+    DROP CONSTRAINT members_channel_id_fkey,
+    ADD CONSTRAINT members_channel_id_fkey FOREIGN KEY (channel_id) REFERENCES channels (id) ON DELETE CASCADE;
+ALTER TABLE messages
+    DROP CONSTRAINT messages_channel_id_fkey,
+    ADD CONSTRAINT messages_channel_id_fkey FOREIGN KEY (channel_id) REFERENCES channels (id) ON DELETE CASCADE;
+ALTER TABLE bans
+    DROP CONSTRAINT bans_channel_id_fkey,
+    ADD CONSTRAINT bans_channel_id_fkey FOREIGN KEY (channel_id) REFERENCES channels (id) ON DELETE CASCADE;
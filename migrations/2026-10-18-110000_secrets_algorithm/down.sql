@@ -0,0 +1,3 @@
+ALTER TABLE secrets
+    DROP COLUMN IF EXISTS algorithm;
+DROP TYPE IF EXISTS password_algorithm;
@@ -0,0 +1,4 @@
+-- Existing rows are salted SHA-256 and get rehashed on the next login
+CREATE TYPE password_algorithm AS ENUM ('sha256', 'argon2id');
+ALTER TABLE secrets
+    ADD COLUMN algorithm password_algorithm NOT NULL DEFAULT 'sha256';
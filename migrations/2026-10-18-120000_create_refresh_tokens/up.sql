@@ -0,0 +1,15 @@
+-- Refresh tokens are single-use, every rotation stays in the family of the login that started it
+CREATE TABLE refresh_tokens
+(
+    id         UUID                     NOT NULL PRIMARY KEY DEFAULT uuid_generate_v4(),
+    family_id  UUID                     NOT NULL,
+    user_id    UUID                     NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    token_hash BYTEA                    NOT NULL UNIQUE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL             DEFAULT NOW(),
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    used_at    TIMESTAMP WITH TIME ZONE,
+    revoked    BOOLEAN                  NOT NULL             DEFAULT FALSE
+);
+
+CREATE INDEX refresh_tokens_family_id_idx ON refresh_tokens (family_id);
+CREATE INDEX refresh_tokens_user_id_idx ON refresh_tokens (user_id);
@@ -0,0 +1,4 @@
+ALTER TABLE users
+    DROP COLUMN IF EXISTS token_generation;
+DROP INDEX IF EXISTS revoked_tokens_expires_at_idx;
+DROP TABLE IF EXISTS revoked_tokens;
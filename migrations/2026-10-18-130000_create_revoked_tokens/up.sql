@@ -0,0 +1,12 @@
+-- Single access tokens are revoked by jti until they would have expired anyway,
+-- bumping token_generation revokes every token a user holds
+CREATE TABLE revoked_tokens
+(
+    jti        UUID                     NOT NULL PRIMARY KEY,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX revoked_tokens_expires_at_idx ON revoked_tokens (expires_at);
+
+ALTER TABLE users
+    ADD COLUMN token_generation INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,11 @@
+-- Ed25519 public keys registered for challenge/response login, each with its current nonce
+CREATE TABLE sessions
+(
+    user_id          UUID                     NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    key              BYTEA                    NOT NULL,
+    nonce            BYTEA                    NOT NULL,
+    nonce_expires_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+ALTER TABLE sessions
+    ADD PRIMARY KEY (user_id, key);
@@ -0,0 +1,19 @@
+-- TOTP secrets stay disabled until the first code is confirmed
+CREATE TABLE totp_secrets
+(
+    user_id        UUID    NOT NULL PRIMARY KEY REFERENCES users (id) ON DELETE CASCADE,
+    secret         BYTEA   NOT NULL,
+    enabled        BOOLEAN NOT NULL DEFAULT FALSE,
+    -- time step of the last accepted code, so a code can't be replayed
+    last_used_step BIGINT
+);
+
+CREATE TABLE recovery_codes
+(
+    id        UUID                     NOT NULL PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id   UUID                     NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    code_hash BYTEA                    NOT NULL,
+    used_at   TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX recovery_codes_user_id_idx ON recovery_codes (user_id);
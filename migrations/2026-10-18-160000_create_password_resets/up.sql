@@ -0,0 +1,12 @@
+-- Reset tokens are single-use, only their hashes are stored
+CREATE TABLE password_resets
+(
+    id         UUID                     NOT NULL PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id    UUID                     NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    token_hash BYTEA                    NOT NULL UNIQUE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL             DEFAULT NOW(),
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    used_at    TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX password_resets_user_id_idx ON password_resets (user_id);
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS login_attempts;
+DROP TYPE IF EXISTS lockout_kind;
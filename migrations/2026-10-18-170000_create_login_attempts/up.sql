@@ -0,0 +1,12 @@
+-- Failed logins are counted per username and per client IP, a row is dropped once it goes quiet
+CREATE TYPE lockout_kind AS ENUM ('username', 'ip');
+
+CREATE TABLE login_attempts
+(
+    kind         lockout_kind             NOT NULL,
+    key          TEXT                     NOT NULL,
+    failures     INTEGER                  NOT NULL DEFAULT 0,
+    last_failure TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    locked_until TIMESTAMP WITH TIME ZONE,
+    PRIMARY KEY (kind, key)
+);
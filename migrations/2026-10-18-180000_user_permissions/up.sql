@@ -0,0 +1,4 @@
+-- Permission bits granted to the user, the developer bit is always taken from users.developer
+-- Everything but developer is granted by default, matching the tokens issued so far
+ALTER TABLE users
+    ADD COLUMN permissions INTEGER NOT NULL DEFAULT 4094;
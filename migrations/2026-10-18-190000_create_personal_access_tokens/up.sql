@@ -0,0 +1,13 @@
+-- Long-lived tokens for automation, each carries a subset of its owner's permissions
+CREATE TABLE personal_access_tokens
+(
+    id           UUID                     NOT NULL PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id      UUID                     NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    name         VARCHAR(64)              NOT NULL,
+    token_hash   BYTEA                    NOT NULL UNIQUE,
+    permissions  INTEGER                  NOT NULL,
+    created_at   TIMESTAMP WITH TIME ZONE NOT NULL             DEFAULT NOW(),
+    expires_at   TIMESTAMP WITH TIME ZONE,
+    last_used_at TIMESTAMP WITH TIME ZONE,
+    UNIQUE (user_id, name)
+);
@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS users_owner_id_idx;
+ALTER TABLE users
+    DROP CONSTRAINT IF EXISTS users_bot_owner,
+    DROP COLUMN IF EXISTS owner_id,
+    DROP COLUMN IF EXISTS bot;
@@ -0,0 +1,7 @@
+-- Bots have no password, they authenticate with a token their owner generates
+ALTER TABLE users
+    ADD COLUMN bot      BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN owner_id UUID REFERENCES users (id) ON DELETE CASCADE,
+    ADD CONSTRAINT users_bot_owner CHECK (bot = (owner_id IS NOT NULL));
+
+CREATE INDEX users_owner_id_idx ON users (owner_id);
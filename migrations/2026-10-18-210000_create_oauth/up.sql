@@ -0,0 +1,31 @@
+-- Third-party applications acting on behalf of users through the OAuth2 authorization code flow
+CREATE TABLE oauth_clients
+(
+    id            UUID                     NOT NULL PRIMARY KEY DEFAULT uuid_generate_v4(),
+    owner_id      UUID                     NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    name          VARCHAR(64)              NOT NULL,
+    redirect_uris TEXT[]                   NOT NULL,
+    -- public clients have no secret, PKCE alone binds their codes
+    secret_hash   BYTEA,
+    created_at    TIMESTAMP WITH TIME ZONE NOT NULL             DEFAULT NOW()
+);
+
+CREATE INDEX oauth_clients_owner_id_idx ON oauth_clients (owner_id);
+
+CREATE TABLE oauth_codes
+(
+    id             UUID                     NOT NULL PRIMARY KEY DEFAULT uuid_generate_v4(),
+    client_id      UUID                     NOT NULL REFERENCES oauth_clients (id) ON DELETE CASCADE,
+    user_id        UUID                     NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    code_hash      BYTEA                    NOT NULL UNIQUE,
+    redirect_uri   TEXT                     NOT NULL,
+    permissions    INTEGER                  NOT NULL,
+    code_challenge TEXT                     NOT NULL,
+    expires_at     TIMESTAMP WITH TIME ZONE NOT NULL,
+    used_at        TIMESTAMP WITH TIME ZONE
+);
+
+-- tokens issued to a client never carry more than the scope the user consented to
+ALTER TABLE refresh_tokens
+    ADD COLUMN client_id   UUID REFERENCES oauth_clients (id) ON DELETE CASCADE,
+    ADD COLUMN permissions INTEGER;
@@ -0,0 +1,21 @@
+-- Accounts of external OpenID Connect providers, the subject is stable while names and emails may change
+CREATE TABLE oidc_identities
+(
+    provider   VARCHAR(64)              NOT NULL,
+    subject    TEXT                     NOT NULL,
+    user_id    UUID                     NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (provider, subject)
+);
+
+CREATE INDEX oidc_identities_user_id_idx ON oidc_identities (user_id);
+
+-- Logins waiting for the provider to redirect back, the state ties the callback to them
+CREATE TABLE oidc_logins
+(
+    state_hash    BYTEA                    NOT NULL PRIMARY KEY,
+    provider      VARCHAR(64)              NOT NULL,
+    nonce         TEXT                     NOT NULL,
+    code_verifier TEXT                     NOT NULL,
+    expires_at    TIMESTAMP WITH TIME ZONE NOT NULL
+);
@@ -0,0 +1,18 @@
+-- A session starts with a login and lives as long as its refresh token family, the family id is the session id
+CREATE TABLE login_sessions
+(
+    id           UUID                     NOT NULL PRIMARY KEY,
+    user_id      UUID                     NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    -- set for sessions an OAuth client holds on behalf of the user
+    client_id    UUID REFERENCES oauth_clients (id) ON DELETE CASCADE,
+    device_name  VARCHAR(64),
+    user_agent   TEXT,
+    ip           TEXT,
+    created_at   TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    last_seen_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    -- moves with every refresh, the session ends when its last refresh token expires
+    expires_at   TIMESTAMP WITH TIME ZONE NOT NULL,
+    revoked_at   TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX login_sessions_user_id_idx ON login_sessions (user_id);
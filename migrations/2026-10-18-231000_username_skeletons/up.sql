@@ -0,0 +1,4 @@
+-- Names that look alike share a skeleton (Unicode TS #39), e.g. `alice` and `аlice` with a Cyrillic `а`.
+-- It's computed by the application, accounts created before this migration have none.
+ALTER TABLE users
+    ADD COLUMN name_skeleton TEXT UNIQUE;
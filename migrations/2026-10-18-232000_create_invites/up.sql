@@ -0,0 +1,25 @@
+-- Codes minted by developers, registration requires one when `auth.registration.invite_only` is set
+CREATE TABLE invite_codes
+(
+    id          UUID                     NOT NULL PRIMARY KEY DEFAULT uuid_generate_v4(),
+    code_hash   BYTEA                    NOT NULL UNIQUE,
+    created_by  UUID REFERENCES users (id) ON DELETE SET NULL,
+    -- the permissions accounts start with, the column default of users.permissions when NULL
+    permissions INTEGER,
+    max_uses    INTEGER                  NOT NULL,
+    uses        INTEGER                  NOT NULL DEFAULT 0,
+    created_at  TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    expires_at  TIMESTAMP WITH TIME ZONE,
+    -- revoked codes are kept, their uses stay visible
+    revoked_at  TIMESTAMP WITH TIME ZONE
+);
+
+-- Which account registered with which code
+CREATE TABLE invite_uses
+(
+    user_id   UUID                     NOT NULL PRIMARY KEY REFERENCES users (id) ON DELETE CASCADE,
+    invite_id UUID                     NOT NULL REFERENCES invite_codes (id) ON DELETE CASCADE,
+    used_at   TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX invite_uses_invite_id_idx ON invite_uses (invite_id);
@@ -0,0 +1,17 @@
+DROP INDEX IF EXISTS messages_user_id_idx;
+
+-- messages of deleted users have no author to go back to
+DELETE FROM messages WHERE user_id IS NULL;
+ALTER TABLE messages
+    ALTER COLUMN user_id SET NOT NULL,
+    DROP CONSTRAINT messages_user_id_fkey,
+    ADD CONSTRAINT messages_user_id_fkey FOREIGN KEY (user_id) REFERENCES users (id);
+ALTER TABLE bans
+    DROP CONSTRAINT bans_user_id_fkey,
+    ADD CONSTRAINT bans_user_id_fkey FOREIGN KEY (user_id) REFERENCES users (id);
+ALTER TABLE members
+    DROP CONSTRAINT members_user_id_fkey,
+    ADD CONSTRAINT members_user_id_fkey FOREIGN KEY (user_id) REFERENCES users (id);
+ALTER TABLE secrets
+    DROP CONSTRAINT secrets_user_id_fkey,
+    ADD CONSTRAINT secrets_user_id_fkey FOREIGN KEY (user_id) REFERENCES users (id);
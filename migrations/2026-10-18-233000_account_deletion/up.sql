@@ -0,0 +1,17 @@
+-- Deleting a user takes their password, memberships and bans with it,
+-- their messages stay in the channels without an author
+ALTER TABLE secrets
+    DROP CONSTRAINT secrets_user_id_fkey,
+    ADD CONSTRAINT secrets_user_id_fkey FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE;
+ALTER TABLE members
+    DROP CONSTRAINT members_user_id_fkey,
+    ADD CONSTRAINT members_user_id_fkey FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE;
+ALTER TABLE bans
+    DROP CONSTRAINT bans_user_id_fkey,
+    ADD CONSTRAINT bans_user_id_fkey FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE;
+ALTER TABLE messages
+    ALTER COLUMN user_id DROP NOT NULL,
+    DROP CONSTRAINT messages_user_id_fkey,
+    ADD CONSTRAINT messages_user_id_fkey FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE SET NULL;
+
+CREATE INDEX messages_user_id_idx ON messages (user_id);
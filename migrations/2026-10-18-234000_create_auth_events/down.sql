@@ -0,0 +1,4 @@
+DROP TABLE IF EXISTS auth_events;
+DROP FUNCTION IF EXISTS anonymize_auth_events;
+DROP FUNCTION IF EXISTS auth_events_append_only;
+DROP TYPE IF EXISTS auth_event_kind;
@@ -0,0 +1,58 @@
+-- Logins, registrations and token issuance and revocation, kept for answering who did what from where
+CREATE TYPE auth_event_kind AS ENUM ('login_succeeded', 'login_failed', 'registered', 'token_issued', 'token_revoked');
+
+CREATE TABLE auth_events
+(
+    id          BIGSERIAL                NOT NULL PRIMARY KEY,
+    kind        auth_event_kind          NOT NULL,
+    -- no foreign keys, events outlive the accounts they are about
+    user_id     UUID,
+    -- the name that was tried, also for names that don't exist
+    username    TEXT,
+    ip          TEXT,
+    user_agent  TEXT,
+    detail      TEXT,
+    occurred_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX auth_events_user_id_idx ON auth_events (user_id);
+CREATE INDEX auth_events_username_idx ON auth_events (username);
+CREATE INDEX auth_events_occurred_at_idx ON auth_events (occurred_at);
+
+-- Append-only, not even spiritbox itself gets to rewrite history.
+-- The one exception is `anonymize_auth_events`, it may only blank out who an event was about.
+CREATE FUNCTION auth_events_append_only() RETURNS trigger AS
+$$
+BEGIN
+    IF TG_OP = 'UPDATE'
+        AND current_setting('spiritbox.anonymizing_auth_events', true) = 'on'
+        AND NEW.user_id IS NULL AND NEW.username IS NULL AND NEW.ip IS NULL AND NEW.user_agent IS NULL
+        AND NEW.id = OLD.id AND NEW.kind = OLD.kind AND NEW.occurred_at = OLD.occurred_at
+        AND NEW.detail IS NOT DISTINCT FROM OLD.detail THEN
+        RETURN NEW;
+    END IF;
+    RAISE EXCEPTION 'auth_events is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+-- Called when an account is deleted, what happened stays on record but not who it happened to
+CREATE FUNCTION anonymize_auth_events(deleted_user UUID) RETURNS void AS
+$$
+BEGIN
+    PERFORM set_config('spiritbox.anonymizing_auth_events', 'on', true);
+    UPDATE auth_events
+    SET user_id    = NULL,
+        username   = NULL,
+        ip         = NULL,
+        user_agent = NULL
+    WHERE user_id = deleted_user;
+    PERFORM set_config('spiritbox.anonymizing_auth_events', 'off', true);
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER auth_events_append_only
+    BEFORE UPDATE OR DELETE ON auth_events
+    FOR EACH ROW EXECUTE PROCEDURE auth_events_append_only();
+CREATE TRIGGER auth_events_no_truncate
+    BEFORE TRUNCATE ON auth_events
+    FOR EACH STATEMENT EXECUTE PROCEDURE auth_events_append_only();
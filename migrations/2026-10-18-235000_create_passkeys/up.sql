@@ -0,0 +1,27 @@
+-- WebAuthn credentials, the public key is kept as DER next to the COSE algorithm it is used with
+CREATE TABLE passkeys
+(
+    id            UUID                     NOT NULL PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id       UUID                     NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    credential_id BYTEA                    NOT NULL UNIQUE,
+    public_key    BYTEA                    NOT NULL,
+    algorithm     INTEGER                  NOT NULL,
+    -- the last counter the authenticator reported, stays 0 for authenticators that don't count
+    sign_count    BIGINT                   NOT NULL DEFAULT 0,
+    name          VARCHAR(64)              NOT NULL,
+    created_at    TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    last_used_at  TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX passkeys_user_id_idx ON passkeys (user_id);
+
+-- Challenges of ceremonies in progress, each is answered at most once
+CREATE TABLE passkey_challenges
+(
+    id           UUID                     NOT NULL PRIMARY KEY DEFAULT uuid_generate_v4(),
+    -- the user adding a passkey, or the one named at login; NULL when any passkey may log in
+    user_id      UUID REFERENCES users (id) ON DELETE CASCADE,
+    challenge    BYTEA                    NOT NULL,
+    registration BOOLEAN                  NOT NULL,
+    expires_at   TIMESTAMP WITH TIME ZONE NOT NULL
+);
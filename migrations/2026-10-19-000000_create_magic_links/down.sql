@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS magic_links_user_id_idx;
+DROP TABLE IF EXISTS magic_links;
@@ -0,0 +1,12 @@
+-- Login links are single-use like reset tokens, only their hashes are stored
+CREATE TABLE magic_links
+(
+    id         UUID                     NOT NULL PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id    UUID                     NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    token_hash BYTEA                    NOT NULL UNIQUE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL             DEFAULT NOW(),
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    used_at    TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX magic_links_user_id_idx ON magic_links (user_id);
@@ -0,0 +1,17 @@
+-- Addresses are stored lowercased and only once verified, an address belongs to whoever verifies it first
+ALTER TABLE users
+    ADD COLUMN email VARCHAR(254) UNIQUE;
+
+-- At most one pending address per user, with the code sent to it; only its hash is stored.
+-- Pending addresses may repeat, `created_at` limits how often codes are sent to an address.
+CREATE TABLE email_verifications
+(
+    user_id    UUID                     NOT NULL PRIMARY KEY REFERENCES users (id) ON DELETE CASCADE,
+    email      VARCHAR(254)             NOT NULL,
+    code_hash  BYTEA                    NOT NULL,
+    attempts   INTEGER                  NOT NULL             DEFAULT 0,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL             DEFAULT NOW(),
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX email_verifications_email_idx ON email_verifications (email);
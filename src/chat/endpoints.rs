@@ -4,18 +4,22 @@ use rocket_db_pools::Connection;
 use crate::database::channels::{Database, DataInsertionError, DataRemovalError, DataRetrievalError, DataSetError};
 use crate::database::Db;
 use crate::models;
-use crate::models::{Channel, ChannelError, Member, MemberInsert, MemberPatch, Message, User, UserRole};
+use crate::models::{AuthClaims, Channel, ChannelError, Member, MemberInsert, MemberPatch, MemberRole, Message, Permission, Require, User};
+use crate::models::required::{AddMembers, GetChannels, ModifyCreateDeleteChannels, ModifyCreateDeleteMessages, ModifyMembers, SeeMessages, SeeOtherUsers};
 
-#[get("/channel/<id>")]
-pub async fn get_channel_by_id(id: models::UUIDWrapper, user: User, mut db: Connection<Db>) -> Result<Channel, ChannelError> {
-    let _ = db.get_member(id.into(), user.id)
+async fn get_membership(db: &mut Connection<Db>, channel_id: uuid::Uuid, user_id: uuid::Uuid) -> Result<Member, ChannelError> {
+    // non-members get 404 so channel ids can't be probed
+    db.get_member(channel_id, user_id)
         .await
         .map_err(|e| match e {
             DataRetrievalError::NotFound => ChannelError::NotFound,
             DataRetrievalError::InternalError => ChannelError::InternalServerError,
-        })?;
+        })
+}
 
-    db.get_channel(id.into())
+#[get("/user/<id>")]
+pub async fn get_user_by_id(id: models::UUIDWrapper, _claims: Require<SeeOtherUsers>, mut db: Connection<Db>) -> Result<User, ChannelError> {
+    db.get_user(id.into())
         .await
         .map_err(|e| match e {
             DataRetrievalError::NotFound => ChannelError::NotFound,
@@ -23,66 +27,66 @@ pub async fn get_channel_by_id(id: models::UUIDWrapper, user: User, mut db: Conn
         })
 }
 
-#[patch("/channel/<id>", format = "json", data = "<patch>")]
-pub async fn patch_channel_by_id(id: models::UUIDWrapper, user: User, patch: models::ChannelPatch, mut db: Connection<Db>) -> Result<Channel, ChannelError> {
-    db.get_member(id.into(), user.id)
+#[get("/channel/<id>")]
+pub async fn get_channel_by_id(id: models::UUIDWrapper, claims: Require<GetChannels>, mut db: Connection<Db>) -> Result<Channel, ChannelError> {
+    get_membership(&mut db, id.into(), claims.sub).await?;
+
+    db.get_channel(id.into())
         .await
         .map_err(|e| match e {
             DataRetrievalError::NotFound => ChannelError::NotFound,
             DataRetrievalError::InternalError => ChannelError::InternalServerError,
         })
-        .and_then(|member| if member.role == UserRole::Admin { Ok(()) } else { Err(ChannelError::Unauthorized) })?;
+}
+
+#[patch("/channel/<id>", format = "json", data = "<patch>")]
+pub async fn patch_channel_by_id(id: models::UUIDWrapper, claims: Require<ModifyCreateDeleteChannels>, patch: models::ChannelPatch, mut db: Connection<Db>) -> Result<Channel, ChannelError> {
+    let myself = get_membership(&mut db, id.into(), claims.sub).await?;
+    if !myself.role.is_moderator() {
+        return Err(ChannelError::Forbidden);
+    }
 
     db.patch_channel(id.into(), patch)
         .await
         .map_err(|e| match e {
+            DataSetError::NotFound => ChannelError::NotFound,
             DataSetError::InternalError => ChannelError::InternalServerError,
         })
 }
 
 #[delete("/channel/<id>")]
-pub async fn remove_channel_by_id(id: models::UUIDWrapper, user: User, mut db: Connection<Db>) -> Result<Channel, ChannelError> {
-    db.get_member(id.into(), user.id)
-        .await
-        .map_err(|e| match e {
-            DataRetrievalError::NotFound => ChannelError::NotFound,
-            DataRetrievalError::InternalError => ChannelError::InternalServerError,
-        })
-        .and_then(|member| if member.role == UserRole::Admin { Ok(()) } else { Err(ChannelError::Unauthorized) })?;
+pub async fn remove_channel_by_id(id: models::UUIDWrapper, claims: Require<ModifyCreateDeleteChannels>, mut db: Connection<Db>) -> Result<Channel, ChannelError> {
+    let myself = get_membership(&mut db, id.into(), claims.sub).await?;
+    if myself.role != MemberRole::Owner {
+        return Err(ChannelError::Forbidden);
+    }
 
     db.remove_channel(id.into())
         .await
         .map_err(|e| match e {
+            DataRemovalError::NotFound => ChannelError::NotFound,
             DataRemovalError::InternalError => ChannelError::InternalServerError,
         })
 }
 
 #[post("/channel", format = "json", data = "<channel>")]
-pub async fn create_channel(channel: models::ChannelInsert, user: User, mut db: Connection<Db>) -> Result<Channel, ChannelError> {
+pub async fn create_channel(channel: models::ChannelInsert, claims: Require<ModifyCreateDeleteChannels>, mut db: Connection<Db>) -> Result<Channel, ChannelError> {
     let new_channel = db.insert_channel(channel)
         .await
         .map_err(|e| match e {
             DataInsertionError::AlreadyExists => ChannelError::Conflict,
-            DataInsertionError::InternalError => ChannelError::InternalServerError,
+            DataInsertionError::NotFound | DataInsertionError::InternalError => ChannelError::InternalServerError,
         })?;
 
-    println!("Hello");
-    db.insert_member(new_channel.id, MemberInsert { user_id: user.id, role: UserRole::Admin })
+    db.insert_member(new_channel.id, MemberInsert { user_id: claims.sub, role: Some(MemberRole::Owner) })
         .await
-        .map_err(|e| match e {
-            _ => ChannelError::InternalServerError,
-        })?;
+        .map_err(|_| ChannelError::InternalServerError)?;
     Ok(new_channel)
 }
 
 #[get("/channel/<id>/members")]
-pub async fn get_channel_members(id: models::UUIDWrapper, user: User, mut db: Connection<Db>) -> Result<Json<Vec<Member>>, ChannelError> {
-    db.get_member(id.into(), user.id)
-        .await
-        .map_err(|e| match e {
-            DataRetrievalError::NotFound => ChannelError::NotFound,
-            DataRetrievalError::InternalError => ChannelError::InternalServerError,
-        })?;
+pub async fn get_channel_members(id: models::UUIDWrapper, claims: Require<SeeOtherUsers>, mut db: Connection<Db>) -> Result<Json<Vec<Member>>, ChannelError> {
+    get_membership(&mut db, id.into(), claims.sub).await?;
 
     db.get_members(id.into())
         .await
@@ -94,13 +98,8 @@ pub async fn get_channel_members(id: models::UUIDWrapper, user: User, mut db: Co
 }
 
 #[get("/channel/<channel_id>/members/<user_id>")]
-pub async fn get_channel_member(channel_id: models::UUIDWrapper, user_id: models::UUIDWrapper, user: User, mut db: Connection<Db>) -> Result<Member, ChannelError> {
-    db.get_member(channel_id.into(), user.id)
-        .await
-        .map_err(|e| match e {
-            DataRetrievalError::NotFound => ChannelError::NotFound,
-            DataRetrievalError::InternalError => ChannelError::InternalServerError,
-        })?;
+pub async fn get_channel_member(channel_id: models::UUIDWrapper, user_id: models::UUIDWrapper, claims: Require<SeeOtherUsers>, mut db: Connection<Db>) -> Result<Member, ChannelError> {
+    get_membership(&mut db, channel_id.into(), claims.sub).await?;
 
     db.get_member(channel_id.into(), user_id.into())
         .await
@@ -111,71 +110,68 @@ pub async fn get_channel_member(channel_id: models::UUIDWrapper, user_id: models
 }
 
 #[post("/channel/<channel_id>/members", format = "json", data = "<member>")]
-pub async fn add_channel_member(channel_id: models::UUIDWrapper, member: MemberInsert, user: User, mut db: Connection<Db>) -> Result<Member, ChannelError> {
-    let myself = db.get_member(channel_id.into(), user.id)
-        .await
-        .map_err(|e| match e {
-            DataRetrievalError::NotFound => ChannelError::NotFound,
-            DataRetrievalError::InternalError => ChannelError::InternalServerError,
-        })?;
+pub async fn add_channel_member(channel_id: models::UUIDWrapper, member: MemberInsert, claims: Require<AddMembers>, mut db: Connection<Db>) -> Result<Member, ChannelError> {
+    let myself = get_membership(&mut db, channel_id.into(), claims.sub).await?;
 
-    if myself.role != UserRole::Admin && member.role == UserRole::Admin {
-        return Err(ChannelError::Unauthorized);
+    match member.role {
+        Some(MemberRole::Owner) => return Err(ChannelError::Forbidden),
+        Some(MemberRole::Admin) if !myself.role.is_moderator() => return Err(ChannelError::Forbidden),
+        _ => {}
     }
 
     db.insert_member(channel_id.into(), member)
         .await
         .map_err(|e| match e {
             DataInsertionError::AlreadyExists => ChannelError::Conflict,
+            DataInsertionError::NotFound => ChannelError::NotFound,
             DataInsertionError::InternalError => ChannelError::InternalServerError,
         })
 }
 
 #[patch("/channel/<channel_id>/members/<user_id>", format = "json", data = "<member>")]
-pub async fn update_channel_member(channel_id: models::UUIDWrapper, user_id: models::UUIDWrapper, member: MemberPatch, user: User, mut db: Connection<Db>) -> Result<Member, ChannelError> {
-    let myself = db.get_member(channel_id.into(), user.id)
-        .await
-        .map_err(|e| match e {
-            DataRetrievalError::NotFound => ChannelError::NotFound,
-            DataRetrievalError::InternalError => ChannelError::InternalServerError,
-        })?;
+pub async fn update_channel_member(channel_id: models::UUIDWrapper, user_id: models::UUIDWrapper, member: MemberPatch, claims: Require<ModifyMembers>, mut db: Connection<Db>) -> Result<Member, ChannelError> {
+    let myself = get_membership(&mut db, channel_id.into(), claims.sub).await?;
+    if !myself.role.is_moderator() || member.role == Some(MemberRole::Owner) {
+        return Err(ChannelError::Forbidden);
+    }
 
-    if myself.role != UserRole::Admin && member.role == Some(UserRole::Admin) {
-        return Err(ChannelError::Unauthorized);
+    let target = get_membership(&mut db, channel_id.into(), user_id.into()).await?;
+    if target.role == MemberRole::Owner {
+        return Err(ChannelError::Forbidden);
     }
+
     db.patch_member(channel_id.into(), user_id.into(), member)
         .await
         .map_err(|e| match e {
+            DataSetError::NotFound => ChannelError::NotFound,
             DataSetError::InternalError => ChannelError::InternalServerError,
         })
 }
 
 #[delete("/channel/<channel_id>/members/<user_id>")]
-pub async fn remove_channel_member(channel_id: models::UUIDWrapper, user_id: models::UUIDWrapper, user: User, mut db: Connection<Db>) -> Result<Member, ChannelError> {
-    db.get_member(channel_id.into(), user.id)
-        .await
-        .map_err(|e| match e {
-            DataRetrievalError::NotFound => ChannelError::NotFound,
-            DataRetrievalError::InternalError => ChannelError::InternalServerError,
-        })
-        .and_then(|member| if member.role == UserRole::Admin { Ok(()) } else { Err(ChannelError::Unauthorized) })?;
+pub async fn remove_channel_member(channel_id: models::UUIDWrapper, user_id: models::UUIDWrapper, claims: AuthClaims, mut db: Connection<Db>) -> Result<Member, ChannelError> {
+    let myself = get_membership(&mut db, channel_id.into(), claims.sub).await?;
+    let target = get_membership(&mut db, channel_id.into(), user_id.into()).await?;
+
+    // anyone but the owner may leave, moderators may kick everyone else
+    let leaving = target.user_id == myself.user_id;
+    claims.require(if leaving { Permission::JoinLeaveChannels } else { Permission::KickMembers })?;
+    if target.role == MemberRole::Owner || !(leaving || myself.role.is_moderator()) {
+        return Err(ChannelError::Forbidden);
+    }
 
     db.remove_member(channel_id.into(), user_id.into())
         .await
         .map_err(|e| match e {
+            DataRemovalError::NotFound => ChannelError::NotFound,
             DataRemovalError::InternalError => ChannelError::InternalServerError,
         })
 }
 
 
 #[get("/channel/<id>/messages")]
-pub async fn get_channel_messages(id: models::UUIDWrapper, user: User, mut db: Connection<Db>) -> Result<Json<Vec<Message>>, ChannelError> {
-    db.get_member(id.into(), user.id)
-        .await
-        .map_err(|e| match e {
-            DataRetrievalError::NotFound => ChannelError::NotFound,
-            DataRetrievalError::InternalError => ChannelError::InternalServerError,
-        })?;
+pub async fn get_channel_messages(id: models::UUIDWrapper, claims: Require<SeeMessages>, mut db: Connection<Db>) -> Result<Json<Vec<Message>>, ChannelError> {
+    get_membership(&mut db, id.into(), claims.sub).await?;
 
     db.get_messages(id.into())
         .await
@@ -187,13 +183,8 @@ pub async fn get_channel_messages(id: models::UUIDWrapper, user: User, mut db: C
 }
 
 #[get("/channel/<channel_id>/messages/<message_id>")]
-pub async fn get_channel_message(channel_id: models::UUIDWrapper, message_id: models::UUIDWrapper, user: User, mut db: Connection<Db>) -> Result<Message, ChannelError> {
-    db.get_member(channel_id.into(), user.id)
-        .await
-        .map_err(|e| match e {
-            DataRetrievalError::NotFound => ChannelError::NotFound,
-            DataRetrievalError::InternalError => ChannelError::InternalServerError,
-        })?;
+pub async fn get_channel_message(channel_id: models::UUIDWrapper, message_id: models::UUIDWrapper, claims: Require<SeeMessages>, mut db: Connection<Db>) -> Result<Message, ChannelError> {
+    get_membership(&mut db, channel_id.into(), claims.sub).await?;
 
     db.get_message(channel_id.into(), message_id.into())
         .await
@@ -204,25 +195,32 @@ pub async fn get_channel_message(channel_id: models::UUIDWrapper, message_id: mo
 }
 
 #[post("/channel/<channel_id>/messages", format = "json", data = "<message>")]
-pub async fn create_channel_message(channel_id: models::UUIDWrapper, message: models::MessageInsert, user: User, mut db: Connection<Db>) -> Result<Message, ChannelError> {
-    db.get_member(channel_id.into(), user.id)
+pub async fn create_channel_message(channel_id: models::UUIDWrapper, message: models::MessageInsert, claims: Require<ModifyCreateDeleteMessages>, mut db: Connection<Db>) -> Result<Message, ChannelError> {
+    get_membership(&mut db, channel_id.into(), claims.sub).await?;
+
+    db.insert_message(channel_id.into(), claims.sub, message)
+        .await
+        .map_err(|_| ChannelError::InternalServerError)
+}
+
+#[delete("/channel/<channel_id>/messages/<message_id>")]
+pub async fn remove_channel_message(channel_id: models::UUIDWrapper, message_id: models::UUIDWrapper, claims: Require<ModifyCreateDeleteMessages>, mut db: Connection<Db>) -> Result<Message, ChannelError> {
+    let myself = get_membership(&mut db, channel_id.into(), claims.sub).await?;
+
+    let message = db.get_message(channel_id.into(), message_id.into())
         .await
         .map_err(|e| match e {
             DataRetrievalError::NotFound => ChannelError::NotFound,
             DataRetrievalError::InternalError => ChannelError::InternalServerError,
         })?;
+    if message.user_id != Some(myself.user_id) && !myself.role.is_moderator() {
+        return Err(ChannelError::Forbidden);
+    }
 
-    let message = Message {
-        id: uuid::Uuid::new_v4(),
-        user_id: user.id,
-        channel_id: channel_id.into(),
-        content: message.content,
-    };
-
-
-    db.insert_message(message)
+    db.remove_message(channel_id.into(), message_id.into())
         .await
         .map_err(|e| match e {
-            _ => ChannelError::InternalServerError,
+            DataRemovalError::NotFound => ChannelError::NotFound,
+            DataRemovalError::InternalError => ChannelError::InternalServerError,
         })
-}
\ No newline at end of file
+}
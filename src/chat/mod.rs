@@ -1,3 +1,5 @@
+mod endpoints;
+
 use std::fmt::Display;
 
 use rocket::http::uri::Origin;
@@ -13,16 +15,22 @@ impl ChatService for rocket::Rocket<rocket::Build> {
     fn mount_chat_service<'a, B>(self, base: B) -> Self
         where B: TryInto<Origin<'a>> + Clone + Display, B::Error: Display
     {
-        self.mount(base, routes![
-/*            endpoints::get_channel_by_id,
-            endpoints::patch_channel_by_id,
-            endpoints::create_channel,
-            endpoints::remove_channel_by_id,
-            endpoints::get_channel_members,
-            endpoints::get_channel_member,
-            endpoints::add_channel_member,
-            endpoints::update_channel_member,
-            endpoints::remove_channel_member*/
-        ])
+        self.register(base.clone(), catchers![crate::models::forbidden])
+            .mount(base, routes![
+                endpoints::get_user_by_id,
+                endpoints::get_channel_by_id,
+                endpoints::patch_channel_by_id,
+                endpoints::create_channel,
+                endpoints::remove_channel_by_id,
+                endpoints::get_channel_members,
+                endpoints::get_channel_member,
+                endpoints::add_channel_member,
+                endpoints::update_channel_member,
+                endpoints::remove_channel_member,
+                endpoints::get_channel_messages,
+                endpoints::get_channel_message,
+                endpoints::create_channel_message,
+                endpoints::remove_channel_message,
+            ])
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,230 @@
+use rocket_db_pools::diesel::prelude::*;
+
+use crate::database::permissions::user_permissions;
+use crate::database::{hash_secret, random_token, Db};
+use crate::models::{AccessTokenError, AuthClaims, NewPersonalAccessToken, Permission, PersonalAccessToken, PersonalAccessTokenRequest, Permissions};
+use crate::schema::{personal_access_tokens, users};
+
+// tells personal access tokens apart from JWTs in the Authorization header
+pub(crate) const ACCESS_TOKEN_PREFIX: &str = "spiritbox_pat_";
+
+// writing on every request would turn each read into a write, once a minute is precise enough
+const LAST_USED_RESOLUTION_SECONDS: i64 = 60;
+
+pub(crate) trait AccessTokenDatabase {
+    async fn create_access_token(&mut self, user_id: uuid::Uuid, request: &PersonalAccessTokenRequest<'_>) -> Result<NewPersonalAccessToken, AccessTokenError>;
+    async fn get_access_tokens(&mut self, user_id: uuid::Uuid) -> Result<Vec<PersonalAccessToken>, AccessTokenError>;
+    async fn revoke_access_token(&mut self, user_id: uuid::Uuid, id: uuid::Uuid) -> Result<(), AccessTokenError>;
+    // None when the token is unknown or expired
+    async fn authenticate_access_token(&mut self, token: &str) -> Result<Option<AuthClaims>, ()>;
+}
+
+impl AccessTokenDatabase for rocket_db_pools::Connection<Db> {
+    async fn create_access_token(&mut self, user_id: uuid::Uuid, request: &PersonalAccessTokenRequest<'_>) -> Result<NewPersonalAccessToken, AccessTokenError> {
+        if request.name.is_empty() || request.name.chars().count() > 64 {
+            return Err(AccessTokenError::InvalidName);
+        }
+
+        let (bits, developer) = users::table
+            .select((users::permissions, users::developer))
+            .filter(users::id.eq(user_id))
+            .first::<(i32, bool)>(self)
+            .await
+            .map_err(|_| AccessTokenError::InternalServerError)?;
+        let owner_permissions = user_permissions(bits, developer);
+
+        let permissions = request.permissions.iter().copied().collect::<Permissions>();
+        if permissions.intersection(owner_permissions) != permissions {
+            return Err(AccessTokenError::ExceedsPermissions);
+        }
+
+        let expires_at = match request.expires_in {
+            Some(expires_in) => Some(
+                chrono::Utc::now() + chrono::Duration::try_seconds(expires_in.into()).ok_or(AccessTokenError::InternalServerError)?
+            ),
+            None => None,
+        };
+
+        let (token, id, created_at) = insert_access_token(self, user_id, request.name, permissions, expires_at)
+            .await
+            .map_err(|err| match err {
+                Some(diesel::result::Error::DatabaseError(diesel::result::DatabaseErrorKind::UniqueViolation, _)) => AccessTokenError::Conflict,
+                _ => AccessTokenError::InternalServerError,
+            })?;
+
+        Ok(NewPersonalAccessToken {
+            token,
+            details: PersonalAccessToken {
+                id,
+                name: request.name.to_string(),
+                permissions: permissions.granted(),
+                created_at,
+                expires_at,
+                last_used_at: None,
+            },
+        })
+    }
+
+    async fn get_access_tokens(&mut self, user_id: uuid::Uuid) -> Result<Vec<PersonalAccessToken>, AccessTokenError> {
+        let tokens = personal_access_tokens::table
+            .select((
+                personal_access_tokens::id,
+                personal_access_tokens::name,
+                personal_access_tokens::permissions,
+                personal_access_tokens::created_at,
+                personal_access_tokens::expires_at,
+                personal_access_tokens::last_used_at,
+            ))
+            .filter(personal_access_tokens::user_id.eq(user_id))
+            .order(personal_access_tokens::created_at.asc())
+            .load::<(uuid::Uuid, String, i32, chrono::DateTime<chrono::Utc>, Option<chrono::DateTime<chrono::Utc>>, Option<chrono::DateTime<chrono::Utc>>)>(self)
+            .await
+            .map_err(|_| AccessTokenError::InternalServerError)?;
+
+        Ok(tokens.into_iter()
+            .map(|(id, name, permissions, created_at, expires_at, last_used_at)| PersonalAccessToken {
+                id,
+                name,
+                permissions: Permissions::from_bits(permissions).granted(),
+                created_at,
+                expires_at,
+                last_used_at,
+            })
+            .collect())
+    }
+
+    async fn revoke_access_token(&mut self, user_id: uuid::Uuid, id: uuid::Uuid) -> Result<(), AccessTokenError> {
+        let removed = diesel::delete(personal_access_tokens::table)
+            .filter(personal_access_tokens::id.eq(id))
+            .filter(personal_access_tokens::user_id.eq(user_id))
+            .execute(self)
+            .await
+            .map_err(|_| AccessTokenError::InternalServerError)?;
+        if removed == 0 {
+            return Err(AccessTokenError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn authenticate_access_token(&mut self, token: &str) -> Result<Option<AuthClaims>, ()> {
+        let now = chrono::Utc::now();
+
+        let found = personal_access_tokens::table
+            .inner_join(users::table)
+            .select((
+                personal_access_tokens::id,
+                personal_access_tokens::user_id,
+                personal_access_tokens::permissions,
+                personal_access_tokens::expires_at,
+                users::permissions,
+                users::developer,
+                users::token_generation,
+                users::owner_id,
+            ))
+            .filter(personal_access_tokens::token_hash.eq(hash_secret(token)?))
+            .filter(personal_access_tokens::expires_at.is_null().or(personal_access_tokens::expires_at.gt(now)))
+            .first::<(uuid::Uuid, uuid::Uuid, i32, Option<chrono::DateTime<chrono::Utc>>, i32, bool, i32, Option<uuid::Uuid>)>(self)
+            .await
+            .optional()
+            .map_err(|_| ())?;
+        let Some((id, user_id, permissions, expires_at, user_bits, developer, generation, bot_owner)) = found else {
+            return Ok(None);
+        };
+
+        let bot_owner_permissions = match bot_owner {
+            Some(bot_owner) => {
+                let (bits, developer) = users::table
+                    .select((users::permissions, users::developer))
+                    .filter(users::id.eq(bot_owner))
+                    .first::<(i32, bool)>(self)
+                    .await
+                    .map_err(|_| ())?;
+                Some(user_permissions(bits, developer))
+            }
+            None => None,
+        };
+
+        let stale = now - chrono::Duration::try_seconds(LAST_USED_RESOLUTION_SECONDS).ok_or(())?;
+        diesel::update(personal_access_tokens::table)
+            .filter(personal_access_tokens::id.eq(id))
+            .filter(personal_access_tokens::last_used_at.is_null().or(personal_access_tokens::last_used_at.lt(stale)))
+            .set(personal_access_tokens::last_used_at.eq(now))
+            .execute(self)
+            .await
+            .map_err(|_| ())?;
+
+        Ok(Some(AuthClaims {
+            sub: user_id,
+            perms: token_permissions(Permissions::from_bits(permissions), user_permissions(user_bits, developer), bot_owner_permissions),
+            exp: expires_at.map_or(usize::MAX, |expires_at| expires_at.timestamp() as usize),
+            jti: id,
+            gen: generation,
+            access_token: Some(id),
+            cid: None,
+            sid: None,
+        }))
+    }
+}
+
+// Permissions the user lost since the token was created are gone from the token too,
+// and a bot never gets to do more than the human who owns it
+fn token_permissions(token: Permissions, user: Permissions, bot_owner: Option<Permissions>) -> Permissions {
+    let user = match bot_owner {
+        Some(owner) => user.intersection(owner.without(Permission::Developer)),
+        None => user,
+    };
+    token.intersection(user)
+}
+
+// Returns the token and its id, the error is None when generating the token itself failed
+pub(crate) async fn insert_access_token(
+    db: &mut rocket_db_pools::Connection<Db>,
+    user_id: uuid::Uuid,
+    name: &str,
+    permissions: Permissions,
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+) -> Result<(String, uuid::Uuid, chrono::DateTime<chrono::Utc>), Option<diesel::result::Error>> {
+    let token = format!("{}{}", ACCESS_TOKEN_PREFIX, random_token().map_err(|_| None)?);
+
+    let (id, created_at) = diesel::insert_into(personal_access_tokens::table)
+        .values((
+            personal_access_tokens::user_id.eq(user_id),
+            personal_access_tokens::name.eq(name),
+            personal_access_tokens::token_hash.eq(hash_secret(&token).map_err(|_| None)?),
+            personal_access_tokens::permissions.eq(permissions.bits()),
+            personal_access_tokens::expires_at.eq(expires_at),
+        ))
+        .returning((personal_access_tokens::id, personal_access_tokens::created_at))
+        .get_result::<(uuid::Uuid, chrono::DateTime<chrono::Utc>)>(db)
+        .await
+        .map_err(Some)?;
+    Ok((token, id, created_at))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn permissions(granted: &[Permission]) -> Permissions {
+        granted.iter().copied().collect()
+    }
+
+    #[test]
+    fn access_tokens_lose_what_their_user_lost() {
+        let token = permissions(&[Permission::GetChannels, Permission::SeeMessages]);
+        let user = permissions(&[Permission::GetChannels, Permission::AddMembers]);
+        assert_eq!(token_permissions(token, user, None).granted(), vec![Permission::GetChannels]);
+        // a token can't pick up what the user was granted after it was created either
+        assert_eq!(token_permissions(token, user.with(Permission::BanMembers), None), token_permissions(token, user, None));
+    }
+    #[test]
+    fn bots_are_capped_by_their_owner_without_developer_access() {
+        let token = Permissions::from_bits(!0);
+        let bot = permissions(&[Permission::GetChannels, Permission::SeeMessages, Permission::AddMembers]);
+        let owner = permissions(&[Permission::Developer, Permission::GetChannels, Permission::SeeMessages]);
+        assert_eq!(token_permissions(token, bot, Some(owner)).granted(), vec![Permission::GetChannels, Permission::SeeMessages]);
+        // not even when the bot's own row has the bit
+        let developer_bot = bot.with(Permission::Developer);
+        assert!(!token_permissions(token, developer_bot, Some(owner)).has(Permission::Developer));
+    }
+}
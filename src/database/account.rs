@@ -0,0 +1,237 @@
+use rocket_db_pools::diesel::prelude::*;
+use rocket_db_pools::diesel::scoped_futures::ScopedFutureExt;
+
+use crate::database::access_tokens::AccessTokenDatabase;
+use crate::database::auth::{check_reauthentication, reauthentication_failed, verify_password};
+use crate::database::bots::BotDatabase;
+use crate::database::mfa::MfaDatabase;
+use crate::database::oauth::OAuthDatabase;
+use crate::database::permissions::user_permissions;
+use crate::database::passkeys::PasskeyDatabase;
+use crate::database::sessions::SessionDatabase;
+use crate::database::token::Database as _;
+use crate::database::Db;
+use crate::models::{AccountError, AuthConfig, DeviceInfo, AccountExport, AuthEvent, ExportedIdentity, ExportedMembership, ExportedUser, LockoutKind, MemberRole, Message, PasswordAlgorithm};
+use crate::schema::{auth_events, bans, channels, email_verifications, invite_uses, login_attempts, login_sessions, members, messages, oidc_identities, secrets, users};
+
+pub(crate) trait AccountDatabase {
+    // `current` is the session the export was requested with
+    async fn export_account(&mut self, user_id: uuid::Uuid, current: Option<uuid::Uuid>) -> Result<AccountExport, AccountError>;
+    // the password has to match when the account has one, otherwise the session has to be a fresh login;
+    // authored messages stay, without an author
+    async fn delete_account(&mut self, config: &AuthConfig, user_id: uuid::Uuid, session_id: Option<uuid::Uuid>, password: Option<&str>, device: &DeviceInfo) -> Result<(), AccountError>;
+}
+
+impl AccountDatabase for rocket_db_pools::Connection<Db> {
+    async fn export_account(&mut self, user_id: uuid::Uuid, current: Option<uuid::Uuid>) -> Result<AccountExport, AccountError> {
+        use base64::Engine;
+
+        let (name, bits, developer, email) = users::table
+            .select((users::name, users::permissions, users::developer, users::email))
+            .filter(users::id.eq(user_id))
+            .first::<(String, i32, bool, Option<String>)>(self)
+            .await
+            .map_err(|_| AccountError::InternalServerError)?;
+        let pending_email = email_verifications::table
+            .select(email_verifications::email)
+            .filter(email_verifications::user_id.eq(user_id))
+            .first::<String>(self)
+            .await
+            .optional()
+            .map_err(|_| AccountError::InternalServerError)?;
+
+        let has_password = diesel::select(diesel::dsl::exists(secrets::table.filter(secrets::user_id.eq(user_id))))
+            .get_result::<bool>(self)
+            .await
+            .map_err(|_| AccountError::InternalServerError)?;
+        let totp_enabled = self.is_totp_enabled(user_id).await.map_err(|_| AccountError::InternalServerError)?;
+        let invite_id = invite_uses::table
+            .select(invite_uses::invite_id)
+            .filter(invite_uses::user_id.eq(user_id))
+            .first::<uuid::Uuid>(self)
+            .await
+            .optional()
+            .map_err(|_| AccountError::InternalServerError)?;
+
+        let memberships = members::table
+            .inner_join(channels::table)
+            .select((members::channel_id, channels::name, members::role))
+            .filter(members::user_id.eq(user_id))
+            .order(channels::name.asc())
+            .load::<(uuid::Uuid, String, MemberRole)>(self)
+            .await
+            .map_err(|_| AccountError::InternalServerError)?
+            .into_iter()
+            .map(|(channel_id, channel_name, role)| ExportedMembership { channel_id, channel_name, role })
+            .collect();
+
+        let bans = bans::table
+            .select(bans::channel_id)
+            .filter(bans::user_id.eq(user_id))
+            .load::<uuid::Uuid>(self)
+            .await
+            .map_err(|_| AccountError::InternalServerError)?;
+
+        let messages = messages::table
+            .filter(messages::user_id.eq(user_id))
+            .order(messages::created_at.asc())
+            .load::<Message>(self)
+            .await
+            .map_err(|_| AccountError::InternalServerError)?;
+
+        let identities = oidc_identities::table
+            .select((oidc_identities::provider, oidc_identities::subject, oidc_identities::created_at))
+            .filter(oidc_identities::user_id.eq(user_id))
+            .load::<(String, String, chrono::DateTime<chrono::Utc>)>(self)
+            .await
+            .map_err(|_| AccountError::InternalServerError)?
+            .into_iter()
+            .map(|(provider, subject, created_at)| ExportedIdentity { provider, subject, created_at })
+            .collect();
+
+        let auth_events = auth_events::table
+            .filter(auth_events::user_id.eq(user_id))
+            .order(auth_events::id.asc())
+            .load::<AuthEvent>(self)
+            .await
+            .map_err(|_| AccountError::InternalServerError)?;
+
+        let public_keys = self.get_public_keys(user_id)
+            .await
+            .map_err(|_| AccountError::InternalServerError)?
+            .iter()
+            .map(|key| base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(key))
+            .collect();
+
+        Ok(AccountExport {
+            exported_at: chrono::Utc::now(),
+            user: ExportedUser {
+                id: user_id,
+                name,
+                permissions: user_permissions(bits, developer).granted(),
+                has_password,
+                totp_enabled,
+                email,
+                pending_email,
+                invite_id,
+            },
+            memberships,
+            bans,
+            messages,
+            sessions: self.get_sessions(user_id, current).await.map_err(|_| AccountError::InternalServerError)?,
+            personal_access_tokens: self.get_access_tokens(user_id).await.map_err(|_| AccountError::InternalServerError)?,
+            public_keys,
+            passkeys: self.get_passkeys(user_id).await.map_err(|_| AccountError::InternalServerError)?,
+            bots: self.get_bots(user_id).await.map_err(|_| AccountError::InternalServerError)?,
+            oauth_clients: self.get_clients(user_id).await.map_err(|_| AccountError::InternalServerError)?,
+            identities,
+            auth_events,
+        })
+    }
+
+    async fn delete_account(&mut self, config: &AuthConfig, user_id: uuid::Uuid, session_id: Option<uuid::Uuid>, password: Option<&str>, device: &DeviceInfo) -> Result<(), AccountError> {
+        let secret = secrets::table
+            .select((secrets::salted_hash, secrets::algorithm))
+            .filter(secrets::user_id.eq(user_id))
+            .first::<(Vec<u8>, PasswordAlgorithm)>(self)
+            .await
+            .optional()
+            .map_err(|_| AccountError::InternalServerError)?;
+        if let Some((salted_hash, algorithm)) = secret {
+            let password = password.ok_or(AccountError::Unauthorized)?;
+            let login = check_reauthentication(self, user_id, device).await?;
+            if !verify_password(algorithm, &salted_hash, password.as_bytes()).await.map_err(|_| AccountError::InternalServerError)? {
+                reauthentication_failed(self, config, user_id, &login, device, "account_deletion").await;
+                return Err(AccountError::Unauthorized);
+            }
+        } else {
+            // there's nothing to re-enter, the login at the identity provider or by link has to be recent instead
+            let session_id = session_id.ok_or(AccountError::ReauthenticationRequired)?;
+            let window = chrono::Duration::try_seconds(config.reauthentication_window.into()).ok_or(AccountError::InternalServerError)?;
+            let fresh = diesel::select(diesel::dsl::exists(
+                login_sessions::table
+                    .filter(login_sessions::id.eq(session_id))
+                    .filter(login_sessions::user_id.eq(user_id))
+                    .filter(login_sessions::revoked_at.is_null())
+                    .filter(login_sessions::created_at.gt(chrono::Utc::now() - window))
+            ))
+                .get_result::<bool>(self)
+                .await
+                .map_err(|_| AccountError::InternalServerError)?;
+            if !fresh {
+                return Err(AccountError::ReauthenticationRequired);
+            }
+        }
+
+        // the checks and deletions are one step, a channel can't gain members or the account new channels in between
+        let name = self.transaction::<_, AccountError, _>(|db| async move {
+            // the bots go with the account, so their channels and events are handled here too
+            let accounts = users::table
+                .select(users::id)
+                .filter(users::id.eq(user_id).or(users::owner_id.eq(user_id)))
+                .for_update()
+                .load::<uuid::Uuid>(db)
+                .await?;
+            if !accounts.contains(&user_id) {
+                return Err(AccountError::InternalServerError);
+            }
+
+            let owned = channels::table
+                .select(channels::id)
+                .filter(channels::id.eq_any(
+                    members::table
+                        .select(members::channel_id)
+                        .filter(members::user_id.eq_any(&accounts))
+                        .filter(members::role.eq(MemberRole::Owner))
+                ))
+                // joining takes a key share lock on the channel, so nobody can join until the deletion is done
+                .for_update()
+                .load::<uuid::Uuid>(db)
+                .await?;
+            // a channel nobody else is in would be left unreachable, one with members would be left without an owner
+            let shared = diesel::select(diesel::dsl::exists(
+                members::table
+                    .filter(members::channel_id.eq_any(&owned))
+                    .filter(members::user_id.ne_all(&accounts))
+            ))
+                .get_result::<bool>(db)
+                .await?;
+            if shared {
+                return Err(AccountError::OwnsChannels);
+            }
+            diesel::delete(channels::table)
+                .filter(channels::id.eq_any(&owned))
+                .execute(db)
+                .await?;
+
+            // the events stay for the audit log, without anything that would tell who the user was
+            for account in &accounts {
+                diesel::sql_query("SELECT anonymize_auth_events($1)")
+                    .bind::<diesel::sql_types::Uuid, _>(account)
+                    .execute(db)
+                    .await?;
+            }
+
+            // the rest goes with the row: secrets, pending email codes, memberships, bans, tokens, sessions, passkeys, bots and OAuth clients
+            let name = diesel::delete(users::table)
+                .filter(users::id.eq(user_id))
+                .returning(users::name)
+                .get_result::<String>(db)
+                .await?;
+            Ok(name)
+        }.scope_boxed()).await?;
+
+        // failed logins are counted by name, the name is free to be registered again
+        let cleared = diesel::delete(login_attempts::table)
+            .filter(login_attempts::kind.eq(LockoutKind::Username))
+            .filter(login_attempts::key.eq(&name))
+            .execute(self)
+            .await;
+        if cleared.is_err() {
+            warn!("Could not clear the failed logins of deleted user {}", user_id);
+        }
+
+        info!("Deleted user {}", user_id);
+        Ok(())
+    }
+}
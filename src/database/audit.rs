@@ -0,0 +1,78 @@
+use rocket_db_pools::diesel::prelude::*;
+
+use crate::database::Db;
+use crate::models::{AuditError, AuthEvent, AuthEventKind, AuthEventQuery, DeviceInfo};
+use crate::schema::auth_events;
+
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 500;
+
+pub(crate) trait AuditDatabase {
+    async fn get_auth_events(&mut self, query: &AuthEventQuery<'_>) -> Result<Vec<AuthEvent>, AuditError>;
+}
+
+impl AuditDatabase for rocket_db_pools::Connection<Db> {
+    async fn get_auth_events(&mut self, query: &AuthEventQuery<'_>) -> Result<Vec<AuthEvent>, AuditError> {
+        let mut events = auth_events::table.into_boxed();
+        if let Some(kind) = query.kind {
+            events = events.filter(auth_events::kind.eq(kind));
+        }
+        if let Some(user_id) = query.user_id {
+            let user_id = uuid::Uuid::parse_str(user_id).map_err(|_| AuditError::InvalidQuery)?;
+            events = events.filter(auth_events::user_id.eq(user_id));
+        }
+        if let Some(username) = query.username {
+            events = events.filter(auth_events::username.eq(username));
+        }
+        if let Some(ip) = query.ip {
+            events = events.filter(auth_events::ip.eq(ip));
+        }
+        if let Some(since) = query.since {
+            events = events.filter(auth_events::occurred_at.ge(parse_timestamp(since)?));
+        }
+        if let Some(until) = query.until {
+            events = events.filter(auth_events::occurred_at.le(parse_timestamp(until)?));
+        }
+        if let Some(before) = query.before {
+            events = events.filter(auth_events::id.lt(before));
+        }
+
+        events
+            .order(auth_events::id.desc())
+            .limit(query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE).into())
+            .load::<AuthEvent>(self)
+            .await
+            .map_err(|_| AuditError::InternalServerError)
+    }
+}
+
+fn parse_timestamp(timestamp: &str) -> Result<chrono::DateTime<chrono::Utc>, AuditError> {
+    chrono::DateTime::parse_from_rfc3339(timestamp)
+        .map(|timestamp| timestamp.with_timezone(&chrono::Utc))
+        .map_err(|_| AuditError::InvalidQuery)
+}
+
+// Never fails what is being recorded, a lost entry is only logged
+pub(crate) async fn record_auth_event(
+    db: &mut rocket_db_pools::Connection<Db>,
+    kind: AuthEventKind,
+    user_id: Option<uuid::Uuid>,
+    username: Option<&str>,
+    device: Option<&DeviceInfo>,
+    detail: Option<&str>,
+) {
+    let recorded = diesel::insert_into(auth_events::table)
+        .values((
+            auth_events::kind.eq(kind),
+            auth_events::user_id.eq(user_id),
+            auth_events::username.eq(username),
+            auth_events::ip.eq(device.and_then(|device| device.ip).map(|ip| ip.to_string())),
+            auth_events::user_agent.eq(device.and_then(|device| device.user_agent.as_deref())),
+            auth_events::detail.eq(detail),
+        ))
+        .execute(db)
+        .await;
+    if recorded.is_err() {
+        warn!("Could not record {:?} event of user {:?} ({:?})", kind, user_id, username);
+    }
+}
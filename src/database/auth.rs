@@ -1,144 +1,907 @@
-use diesel::result::Error;
-use rocket::serde::{Deserialize, Serialize};
-use crate::database::Db;
-use crate::models::{AuthClaims, LoginError, Permissions, RegisterError, Token};
+use std::collections::HashMap;
+use std::net::IpAddr;
 
-const JWT_SECRET: &[u8] = b"Szechuan Sauce Recipe";
+use diesel::result::Error;
+use rocket_db_pools::diesel::AsyncPgConnection;
+use crate::database::{hash_secret, random_token, Db};
+use crate::database::audit::record_auth_event;
+use crate::database::invites::{record_invite_use, redeem_invite, release_invite, RedeemedInvite};
+use crate::database::lockout::LockoutDatabase;
+use crate::database::mfa::MfaDatabase;
+use crate::database::permissions::user_permissions;
+use crate::database::sessions::{end_session, is_session_revoked, record_session};
+use crate::models::{Argon2Config, AuthClaims, AuthConfig, AuthEventKind, DeviceInfo, JwtAlgorithm, JwtConfig, JwtKeyConfig, LoginError, LoginResponse, MfaError, PasswordAlgorithm, Permissions, PolicyViolation, RefreshError, RegisterError, RevocationError, Token};
+use crate::policy::{username_skeleton, RegistrationPolicy};
 
+// a different name that looks the same is taken, as opposed to the name itself
+pub(crate) const NAME_SKELETON_CONSTRAINT: &str = "users_name_skeleton_key";
 
 pub(crate) trait AuthDatabase {
-    async fn login(&mut self, login: &str, password: &str) -> Result<Token, LoginError>;
-    async fn register(&mut self, login: &str, password: &str) -> Result<(), RegisterError>;
+    // failures count towards the lockout of the username and, when known, the client IP;
+    // a successful login starts a session on `device`
+    async fn login(&mut self, config: &AuthConfig, keys: &JwtKeys, login: &str, password: &str, client_ip: Option<IpAddr>, device: &DeviceInfo) -> Result<LoginResponse, LoginError>;
+    async fn mfa_login(&mut self, config: &AuthConfig, keys: &JwtKeys, mfa_token: &str, code: &str, client_ip: Option<IpAddr>, device: &DeviceInfo) -> Result<Token, LoginError>;
+    // returns the username as it was stored, normalized by the policy;
+    // the invite code is required when registration is invite-only, its permissions replace the defaults
+    async fn register(&mut self, config: &AuthConfig, policy: &RegistrationPolicy, login: &str, password: &str, invite_code: Option<&str>, device: &DeviceInfo) -> Result<String, RegisterError>;
+    // client_id is None for the tokens of a login, a client can only refresh the tokens issued to it;
+    // `device` only goes into the audit log, the session keeps the device it was started on
+    async fn refresh(&mut self, config: &AuthConfig, keys: &JwtKeys, refresh_token: &str, client_id: Option<uuid::Uuid>, device: &DeviceInfo) -> Result<Token, RefreshError>;
+    async fn is_token_revoked(&mut self, claims: &AuthClaims) -> Result<bool, ()>;
+    async fn revoke_token(&mut self, claims: &AuthClaims) -> Result<(), RevocationError>;
+    async fn revoke_refresh_token(&mut self, user_id: uuid::Uuid, refresh_token: &str) -> Result<(), RevocationError>;
+    async fn revoke_user_tokens(&mut self, user_id: uuid::Uuid) -> Result<(), RevocationError>;
+    async fn key_challenge(&mut self, login: &str, public_key: [u8; 32]) -> Result<[u8; 32], LoginError>;
+    async fn key_login(&mut self, config: &AuthConfig, keys: &JwtKeys, login: &str, public_key: [u8; 32], signature: &[u8], device: &DeviceInfo) -> Result<Token, LoginError>;
 }
 
 
 impl AuthDatabase for rocket_db_pools::Connection<Db> {
-    async fn login(&mut self, login: &str, password: &str) -> Result<Token, LoginError> {
+    async fn login(&mut self, config: &AuthConfig, keys: &JwtKeys, login: &str, password: &str, client_ip: Option<IpAddr>, device: &DeviceInfo) -> Result<LoginResponse, LoginError> {
         use rocket_db_pools::diesel::prelude::*;
         use crate::schema::{users, secrets};
 
-        let (user_salted_hash, user_id) = users::table
+        check_lockout(self, login, client_ip, device).await?;
+
+        let user = users::table
             .inner_join(secrets::table)
-            .select((secrets::salted_hash, users::id))
+            .select((secrets::salted_hash, secrets::algorithm, users::id))
             .filter(users::name.eq(login))
-            .first::<(Vec<u8>, uuid::Uuid)>(self)
+            // bots have no password, they authenticate with their token only
+            .filter(users::bot.eq(false))
+            .first::<(Vec<u8>, PasswordAlgorithm, uuid::Uuid)>(self)
+            .await
+            .optional()
+            .map_err(|_| LoginError::InternalServerError)?;
+        let Some((user_salted_hash, algorithm, user_id)) = user else {
+            // as slow as a wrong password, so response times don't tell which usernames exist
+            let dummy_hash = dummy_password_hash(&config.argon2).await.map_err(|_| LoginError::InternalServerError)?;
+            let _ = verify_password(PasswordAlgorithm::Argon2id, dummy_hash, password.as_bytes()).await;
+            return Err(login_failed(self, config, None, login, client_ip, device, "password").await);
+        };
+
+        if !verify_password(algorithm, &user_salted_hash, password.as_bytes()).await.map_err(|_| LoginError::InternalServerError)? {
+            return Err(login_failed(self, config, Some(user_id), login, client_ip, device, "password").await);
+        }
+
+        if needs_rehash(&config.argon2, algorithm, &user_salted_hash) {
+            // the password is known to be correct only now, a failed upgrade must not block the login
+            match hash_password(&config.argon2, password.as_bytes()).await {
+                Ok(salted_hash) => {
+                    let updated = diesel::update(secrets::table)
+                        .filter(secrets::user_id.eq(user_id))
+                        .set((
+                            secrets::salted_hash.eq(salted_hash),
+                            secrets::algorithm.eq(PasswordAlgorithm::Argon2id),
+                        ))
+                        .execute(self)
+                        .await;
+                    if updated.is_err() {
+                        warn!("Could not store rehashed password of user {}", user_id);
+                    }
+                }
+                Err(_) => warn!("Could not rehash password of user {}", user_id),
+            }
+        }
+
+        if self.is_totp_enabled(user_id).await.map_err(|_| LoginError::InternalServerError)? {
+            let mfa_token = generate_mfa_token(keys, user_id, config.mfa_token_ttl)
+                .map_err(|_| LoginError::InternalServerError)?;
+            return Ok(LoginResponse::MfaRequired { mfa_token, expires_in: config.mfa_token_ttl });
+        }
+
+        // with 2FA on, only a correct second factor clears the counter, otherwise retyping the password would reset it
+        login_succeeded(self, user_id, login, device, "password").await;
+        issue_login_token(self, config, keys, user_id, login, device, "password")
+            .await
+            .map(LoginResponse::Token)
+            .map_err(|_| LoginError::InternalServerError)
+    }
+
+    async fn mfa_login(&mut self, config: &AuthConfig, keys: &JwtKeys, mfa_token: &str, code: &str, client_ip: Option<IpAddr>, device: &DeviceInfo) -> Result<Token, LoginError> {
+        use rocket_db_pools::diesel::prelude::*;
+        use crate::schema::users;
+
+        let user_id = verify_mfa_token(keys, mfa_token).map_err(|_| LoginError::Unauthorized)?;
+
+        // codes are guessed against the same counter as passwords
+        let login = users::table
+            .select(users::name)
+            .filter(users::id.eq(user_id))
+            .first::<String>(self)
             .await
             .map_err(|err| match err {
                 Error::NotFound => LoginError::Unauthorized,
                 _ => LoginError::InternalServerError,
             })?;
+        check_lockout(self, &login, client_ip, device).await?;
 
-        if user_salted_hash.len() <= 16 { return Err(LoginError::InternalServerError) }
-        let (salt, hash) = user_salted_hash
-            .split_at(16);
-
-        if verify_password(salt, password.as_bytes(), hash).map_err(|_| LoginError::InternalServerError)? {
-            generate_token(user_id)
-                .map(|access_token| Token {access_token})
-                .map_err(|_| LoginError::InternalServerError)
-        } else {
-            Err(LoginError::Unauthorized)
+        match self.verify_second_factor(user_id, code).await {
+            Ok(()) => {}
+            Err(MfaError::InternalServerError) => return Err(LoginError::InternalServerError),
+            Err(_) => return Err(login_failed(self, config, Some(user_id), &login, client_ip, device, "2fa").await),
         }
+        login_succeeded(self, user_id, &login, device, "2fa").await;
+
+        issue_login_token(self, config, keys, user_id, &login, device, "2fa")
+            .await
+            .map_err(|_| LoginError::InternalServerError)
     }
 
-    async fn register(&mut self, login: &str, password: &str) -> Result<(), RegisterError> {
+    async fn register(&mut self, config: &AuthConfig, policy: &RegistrationPolicy, login: &str, password: &str, invite_code: Option<&str>, device: &DeviceInfo) -> Result<String, RegisterError> {
         use rocket_db_pools::diesel::prelude::*;
         use crate::schema::{users, secrets};
 
-        let salt_hash = hash_password(password.as_bytes()).map_err(|_| RegisterError::InternalServerError)?;
+        if config.registration.invite_only && invite_code.is_none() {
+            return Err(RegisterError::InviteRequired);
+        }
 
-        let user_uuid = diesel::insert_into(users::table)
+        let login = policy.check(login, password).map_err(RegisterError::Rejected)?;
+
+        let salt_hash = hash_password(&config.argon2, password.as_bytes()).await.map_err(|_| RegisterError::InternalServerError)?;
+
+        let invite = match invite_code {
+            Some(code) => Some(
+                redeem_invite(self, code)
+                    .await
+                    .map_err(|_| RegisterError::InternalServerError)?
+                    .ok_or(RegisterError::InvalidInvite)?
+            ),
+            None => None,
+        };
+        let permissions = invite.as_ref().and_then(RedeemedInvite::user_permissions);
+
+        let inserted = diesel::insert_into(users::table)
             .values((
-                users::name.eq(login),
+                users::name.eq(&login),
+                users::name_skeleton.eq(username_skeleton(&login)),
+                permissions.map(|(bits, _)| users::permissions.eq(bits)),
+                permissions.map(|(_, developer)| users::developer.eq(developer)),
             ))
             .returning(users::id)
             .get_result::<uuid::Uuid>(self)
             .await
             .map_err(|err| match err {
+                Error::DatabaseError(diesel::result::DatabaseErrorKind::UniqueViolation, info) if info.constraint_name() == Some(NAME_SKELETON_CONSTRAINT) => {
+                    RegisterError::Rejected(vec![PolicyViolation::UsernameConfusable])
+                }
                 Error::DatabaseError(diesel::result::DatabaseErrorKind::UniqueViolation, _) => RegisterError::Conflict,
                 _ => RegisterError::InternalServerError
-            })?;;
+            });
+        let user_uuid = match (inserted, &invite) {
+            (Ok(user_uuid), _) => user_uuid,
+            (Err(err), Some(invite)) => {
+                // the name was taken, the use goes back to the invite
+                release_invite(self, invite.id).await;
+                return Err(err);
+            }
+            (Err(err), None) => return Err(err),
+        };
+
+        if let Some(invite) = &invite {
+            // the account exists at this point, failing the registration would only leave it without a password
+            if record_invite_use(self, invite.id, user_uuid).await.is_err() {
+                warn!("Could not record that user {} registered with invite {}", user_uuid, invite.id);
+            }
+        }
 
         diesel::insert_into(secrets::table)
             .values((
                 secrets::user_id.eq(user_uuid),
                 secrets::salted_hash.eq(salt_hash),
+                secrets::algorithm.eq(PasswordAlgorithm::Argon2id),
             ))
             .execute(self)
             .await
             .map_err(|_| RegisterError::InternalServerError)?;
+
+        let detail = invite.as_ref().map(|_| "invite");
+        record_auth_event(self, AuthEventKind::Registered, Some(user_uuid), Some(login.as_str()), Some(device), detail).await;
+        Ok(login)
+    }
+
+    async fn refresh(&mut self, config: &AuthConfig, keys: &JwtKeys, refresh_token: &str, client_id: Option<uuid::Uuid>, device: &DeviceInfo) -> Result<Token, RefreshError> {
+        use rocket_db_pools::diesel::prelude::*;
+        use crate::schema::refresh_tokens;
+
+        let token_hash = hash_secret(refresh_token).map_err(|_| RefreshError::InternalServerError)?;
+        let now = chrono::Utc::now();
+
+        // marking the token as used and reading it back is a single statement, so two concurrent
+        // refreshes with the same token can't both succeed
+        let rotated = diesel::update(refresh_tokens::table)
+            .filter(refresh_tokens::token_hash.eq(&token_hash))
+            .filter(refresh_tokens::used_at.is_null())
+            .filter(refresh_tokens::revoked.eq(false))
+            .filter(refresh_tokens::expires_at.gt(now))
+            .filter(refresh_tokens::client_id.is_not_distinct_from(client_id))
+            .set(refresh_tokens::used_at.eq(now))
+            .returning((refresh_tokens::user_id, refresh_tokens::family_id, refresh_tokens::permissions))
+            .get_result::<(uuid::Uuid, uuid::Uuid, Option<i32>)>(self)
+            .await
+            .optional()
+            .map_err(|_| RefreshError::InternalServerError)?;
+
+        if let Some((user_id, family_id, scope)) = rotated {
+            // the family may have been revoked through its session while the token was being rotated
+            if is_session_revoked(self, family_id).await.map_err(|_| RefreshError::InternalServerError)? {
+                return Err(RefreshError::Unauthorized);
+            }
+            // the consented scope stays with the family, refreshing never widens it
+            let grant = client_id.zip(scope).map(|(client_id, scope)| ClientGrant { client_id, scope: Permissions::from_bits(scope) });
+            let token = issue_token(self, config, keys, user_id, family_id, None, grant)
+                .await
+                .map_err(|_| RefreshError::InternalServerError)?;
+            record_auth_event(self, AuthEventKind::TokenIssued, Some(user_id), None, Some(device), Some("refresh")).await;
+            return Ok(token);
+        }
+
+        // a token that has already been used is being replayed, whoever holds the family is not trusted anymore
+        let replayed_family = refresh_tokens::table
+            .select((refresh_tokens::family_id, refresh_tokens::user_id))
+            .filter(refresh_tokens::token_hash.eq(&token_hash))
+            .filter(refresh_tokens::used_at.is_not_null())
+            .first::<(uuid::Uuid, uuid::Uuid)>(self)
+            .await
+            .optional()
+            .map_err(|_| RefreshError::InternalServerError)?;
+
+        if let Some((family_id, user_id)) = replayed_family {
+            warn!("Refresh token reuse detected, revoking session {}", family_id);
+            end_session(self, family_id).await.map_err(|_| RefreshError::InternalServerError)?;
+            record_auth_event(self, AuthEventKind::TokenRevoked, Some(user_id), None, Some(device), Some("refresh_token_reuse")).await;
+        }
+
+        Err(RefreshError::Unauthorized)
+    }
+
+    async fn is_token_revoked(&mut self, claims: &AuthClaims) -> Result<bool, ()> {
+        use rocket_db_pools::diesel::prelude::*;
+        use crate::schema::{revoked_tokens, users};
+
+        let generation = users::table
+            .select(users::token_generation)
+            .filter(users::id.eq(claims.sub))
+            .first::<i32>(self)
+            .await
+            .optional()
+            .map_err(|_| ())?;
+        // tokens of deleted users are as good as revoked
+        if generation != Some(claims.gen) {
+            return Ok(true);
+        }
+
+        diesel::select(diesel::dsl::exists(
+            revoked_tokens::table.filter(revoked_tokens::jti.eq(claims.jti))
+        ))
+            .get_result::<bool>(self)
+            .await
+            .map_err(|_| ())
+    }
+
+    async fn revoke_token(&mut self, claims: &AuthClaims) -> Result<(), RevocationError> {
+        use rocket_db_pools::diesel::prelude::*;
+        use crate::schema::revoked_tokens;
+
+        let expires_at = chrono::DateTime::from_timestamp(claims.exp as i64, 0)
+            .ok_or(RevocationError::InternalServerError)?;
+
+        diesel::insert_into(revoked_tokens::table)
+            .values((
+                revoked_tokens::jti.eq(claims.jti),
+                revoked_tokens::expires_at.eq(expires_at),
+            ))
+            .on_conflict_do_nothing()
+            .execute(self)
+            .await
+            .map_err(|_| RevocationError::InternalServerError)?;
+
+        // entries of tokens that expired by now are dead weight, verification rejects those anyway
+        diesel::delete(revoked_tokens::table)
+            .filter(revoked_tokens::expires_at.lt(chrono::Utc::now()))
+            .execute(self)
+            .await
+            .map_err(|_| RevocationError::InternalServerError)?;
         Ok(())
     }
+
+    async fn revoke_refresh_token(&mut self, user_id: uuid::Uuid, refresh_token: &str) -> Result<(), RevocationError> {
+        use rocket_db_pools::diesel::prelude::*;
+        use crate::schema::refresh_tokens;
+
+        let token_hash = hash_secret(refresh_token).map_err(|_| RevocationError::InternalServerError)?;
+
+        let family_id = refresh_tokens::table
+            .select(refresh_tokens::family_id)
+            .filter(refresh_tokens::token_hash.eq(token_hash))
+            .filter(refresh_tokens::user_id.eq(user_id))
+            .first::<uuid::Uuid>(self)
+            .await
+            .optional()
+            .map_err(|_| RevocationError::InternalServerError)?;
+
+        if let Some(family_id) = family_id {
+            end_session(self, family_id).await.map_err(|_| RevocationError::InternalServerError)?;
+        }
+        Ok(())
+    }
+
+    async fn revoke_user_tokens(&mut self, user_id: uuid::Uuid) -> Result<(), RevocationError> {
+        revoke_all_tokens(self, user_id).await.map_err(|_| RevocationError::InternalServerError)
+    }
+
+    async fn key_challenge(&mut self, login: &str, public_key: [u8; 32]) -> Result<[u8; 32], LoginError> {
+        use crate::database::token::{Database, DataSetError};
+
+        let user_id = user_id_by_name(self, login).await?;
+
+        let mut nonce = [0; 32];
+        openssl::rand::rand_bytes(&mut nonce).map_err(|_| LoginError::InternalServerError)?;
+
+        self.set_session_nonce(user_id, public_key, nonce)
+            .await
+            .map_err(|e| match e {
+                DataSetError::InvalidSession => LoginError::Unauthorized,
+                DataSetError::InternalError => LoginError::InternalServerError,
+            })?;
+        Ok(nonce)
+    }
+
+    async fn key_login(&mut self, config: &AuthConfig, keys: &JwtKeys, login: &str, public_key: [u8; 32], signature: &[u8], device: &DeviceInfo) -> Result<Token, LoginError> {
+        use crate::database::token::{Database, DataRetrievalError, DataSetError};
+
+        let user_id = user_id_by_name(self, login).await?;
+
+        let nonce = self.get_session_nonce(user_id, public_key)
+            .await
+            .map_err(|e| match e {
+                DataRetrievalError::NotFound => LoginError::Unauthorized,
+                DataRetrievalError::InternalError => LoginError::InternalServerError,
+            })?;
+
+        if !verify_key_signature(&public_key, &nonce, signature).map_err(|_| LoginError::InternalServerError)? {
+            record_auth_event(self, AuthEventKind::LoginFailed, Some(user_id), Some(login), Some(device), Some("key")).await;
+            return Err(LoginError::Unauthorized);
+        }
+
+        // a nonce signs in exactly once, a concurrent request with the same signature loses here
+        self.consume_session_nonce(user_id, public_key, nonce)
+            .await
+            .map_err(|e| match e {
+                DataSetError::InvalidSession => LoginError::Unauthorized,
+                DataSetError::InternalError => LoginError::InternalServerError,
+            })?;
+
+        record_auth_event(self, AuthEventKind::LoginSucceeded, Some(user_id), Some(login), Some(device), Some("key")).await;
+        issue_login_token(self, config, keys, user_id, login, device, "key")
+            .await
+            .map_err(|_| LoginError::InternalServerError)
+    }
+}
+
+// `AuthDatabase::revoke_user_tokens` for callers that have to do it inside their own transaction
+pub(crate) async fn revoke_all_tokens(db: &mut AsyncPgConnection, user_id: uuid::Uuid) -> Result<(), Error> {
+    use rocket_db_pools::diesel::prelude::*;
+    use crate::schema::{login_sessions, personal_access_tokens, refresh_tokens, users};
+
+    diesel::update(users::table)
+        .filter(users::id.eq(user_id))
+        .set(users::token_generation.eq(users::token_generation + 1))
+        .execute(db)
+        .await?;
+
+    // access tokens don't carry a generation, so they and the tokens of the user's bots are removed outright
+    diesel::delete(personal_access_tokens::table)
+        .filter(personal_access_tokens::user_id.eq(user_id)
+            .or(personal_access_tokens::user_id.eq_any(
+                users::table.select(users::id).filter(users::owner_id.eq(user_id))
+            )))
+        .execute(db)
+        .await?;
+
+    diesel::update(refresh_tokens::table)
+        .filter(refresh_tokens::user_id.eq(user_id))
+        .set(refresh_tokens::revoked.eq(true))
+        .execute(db)
+        .await?;
+
+    diesel::update(login_sessions::table)
+        .filter(login_sessions::user_id.eq(user_id))
+        .filter(login_sessions::revoked_at.is_null())
+        .set(login_sessions::revoked_at.eq(chrono::Utc::now()))
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+async fn check_lockout(db: &mut rocket_db_pools::Connection<Db>, login: &str, client_ip: Option<IpAddr>, device: &DeviceInfo) -> Result<(), LoginError> {
+    match db.login_lockout(login, client_ip).await {
+        Ok(None) => Ok(()),
+        Ok(Some(retry_after)) => {
+            record_auth_event(db, AuthEventKind::LoginFailed, None, Some(login), Some(device), Some("locked_out")).await;
+            Err(LoginError::TooManyAttempts { retry_after })
+        }
+        Err(_) => Err(LoginError::InternalServerError),
+    }
+}
+
+// user_id is None when the username doesn't exist, the name that was tried is recorded either way
+async fn login_failed(db: &mut rocket_db_pools::Connection<Db>, config: &AuthConfig, user_id: Option<uuid::Uuid>, login: &str, client_ip: Option<IpAddr>, device: &DeviceInfo, method: &str) -> LoginError {
+    if db.record_login_failure(&config.lockout, login, client_ip).await.is_err() {
+        warn!("Could not record failed login of {}", login);
+    }
+    record_auth_event(db, AuthEventKind::LoginFailed, user_id, Some(login), Some(device), Some(method)).await;
+    LoginError::Unauthorized
+}
+
+pub(crate) async fn login_succeeded(db: &mut rocket_db_pools::Connection<Db>, user_id: uuid::Uuid, login: &str, device: &DeviceInfo, method: &str) {
+    if db.clear_login_failures(login).await.is_err() {
+        warn!("Could not clear failed logins of {}", login);
+    }
+    record_auth_event(db, AuthEventKind::LoginSucceeded, Some(user_id), Some(login), Some(device), Some(method)).await;
+}
+
+// Logged-in users re-entering a password or second factor, e.g. to turn 2FA off, are held to the lockout of their
+// username, otherwise a stolen token would be a way to guess both without limit; returns the username
+pub(crate) async fn check_reauthentication(db: &mut rocket_db_pools::Connection<Db>, user_id: uuid::Uuid, device: &DeviceInfo) -> Result<String, LoginError> {
+    use rocket_db_pools::diesel::prelude::*;
+    use crate::schema::users;
+
+    let login = users::table
+        .select(users::name)
+        .filter(users::id.eq(user_id))
+        .first::<String>(db)
+        .await
+        .map_err(|err| match err {
+            Error::NotFound => LoginError::Unauthorized,
+            _ => LoginError::InternalServerError,
+        })?;
+    check_lockout(db, &login, device.ip, device).await?;
+    Ok(login)
+}
+
+// A wrong answer after `check_reauthentication`, counted like a failed login; a right one leaves the counter alone
+pub(crate) async fn reauthentication_failed(db: &mut rocket_db_pools::Connection<Db>, config: &AuthConfig, user_id: uuid::Uuid, login: &str, device: &DeviceInfo, method: &str) {
+    login_failed(db, config, Some(user_id), login, device.ip, device, method).await;
+}
+
+// Starts a new session for a login that went through
+pub(crate) async fn issue_login_token(db: &mut rocket_db_pools::Connection<Db>, config: &AuthConfig, keys: &JwtKeys, user_id: uuid::Uuid, login: &str, device: &DeviceInfo, method: &str) -> Result<Token, ()> {
+    let token = issue_token(db, config, keys, user_id, uuid::Uuid::new_v4(), Some(device), None).await?;
+    record_auth_event(db, AuthEventKind::TokenIssued, Some(user_id), Some(login), Some(device), Some(method)).await;
+    Ok(token)
+}
+
+// Bots can't use key login, just like password login
+async fn user_id_by_name(db: &mut rocket_db_pools::Connection<Db>, login: &str) -> Result<uuid::Uuid, LoginError> {
+    use rocket_db_pools::diesel::prelude::*;
+    use crate::schema::users;
+
+    users::table
+        .select(users::id)
+        .filter(users::name.eq(login))
+        .filter(users::bot.eq(false))
+        .first::<uuid::Uuid>(db)
+        .await
+        .map_err(|err| match err {
+            Error::NotFound => LoginError::Unauthorized,
+            _ => LoginError::InternalServerError,
+        })
+}
+
+pub(crate) fn verify_key_signature(public_key: &[u8; 32], message: &[u8], signature: &[u8]) -> Result<bool, ()> {
+    use openssl::pkey::{Id, PKey};
+    use openssl::sign::Verifier;
+
+    let public_key = PKey::public_key_from_raw_bytes(public_key, Id::ED25519).map_err(|_| ())?;
+    let mut verifier = Verifier::new_without_digest(&public_key).map_err(|_| ())?;
+    // malformed signatures are just wrong signatures
+    Ok(verifier.verify_oneshot(signature, message).unwrap_or(false))
 }
 
-pub(crate) fn verify_password(salt: &[u8], password: &[u8], hash: &[u8]) -> Result<bool, openssl::error::ErrorStack> {
+// What a user consented to hand to an OAuth client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ClientGrant {
+    pub client_id: uuid::Uuid,
+    pub scope: Permissions,
+}
+
+// Signs a new access token and stores the next refresh token of the session's family,
+// `device` is only recorded when the token starts the session
+pub(crate) async fn issue_token(
+    db: &mut rocket_db_pools::Connection<Db>,
+    config: &AuthConfig,
+    keys: &JwtKeys,
+    user_id: uuid::Uuid,
+    session_id: uuid::Uuid,
+    device: Option<&DeviceInfo>,
+    grant: Option<ClientGrant>,
+) -> Result<Token, ()> {
+    use rocket_db_pools::diesel::prelude::*;
+    use crate::schema::{refresh_tokens, users};
+
+    // read on every issue and refresh, so changed permissions show up in the next token
+    let (generation, permissions, developer) = users::table
+        .select((users::token_generation, users::permissions, users::developer))
+        .filter(users::id.eq(user_id))
+        .first::<(i32, i32, bool)>(db)
+        .await
+        .map_err(|_| ())?;
+
+    let mut permissions = user_permissions(permissions, developer);
+    if let Some(grant) = grant {
+        permissions = permissions.intersection(grant.scope);
+    }
+    let client_id = grant.map(|grant| grant.client_id);
+    let access_token = generate_token(keys, user_id, permissions, generation, config.access_token_ttl, client_id, session_id)?;
+
+    let refresh_token = random_token().map_err(|_| ())?;
+
+    let expires_at = chrono::Utc::now() + chrono::Duration::try_seconds(config.refresh_token_ttl.into()).ok_or(())?;
+
+    record_session(db, session_id, user_id, client_id, device, expires_at).await?;
+
+    diesel::insert_into(refresh_tokens::table)
+        .values((
+            refresh_tokens::family_id.eq(session_id),
+            refresh_tokens::user_id.eq(user_id),
+            refresh_tokens::token_hash.eq(hash_secret(&refresh_token)?),
+            refresh_tokens::expires_at.eq(expires_at),
+            refresh_tokens::client_id.eq(client_id),
+            refresh_tokens::permissions.eq(grant.map(|grant| grant.scope.bits())),
+        ))
+        .execute(db)
+        .await
+        .map_err(|_| ())?;
+
+    Ok(Token {
+        access_token,
+        refresh_token,
+        expires_in: config.access_token_ttl,
+    })
+}
+
+fn argon2_hasher(config: &Argon2Config) -> Result<argon2::Argon2<'static>, argon2::Error> {
+    use argon2::{Algorithm, Argon2, Params, Version};
+
+    let params = Params::new(config.memory_cost, config.time_cost, config.parallelism, None)?;
+    Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+}
+
+// Hashing is deliberately slow, so it runs on the blocking pool instead of stalling the async workers
+pub(crate) async fn verify_password(algorithm: PasswordAlgorithm, salted_hash: &[u8], password: &[u8]) -> Result<bool, ()> {
+    let salted_hash = salted_hash.to_vec();
+    let password = password.to_vec();
+
+    rocket::tokio::task::spawn_blocking(move || match algorithm {
+        PasswordAlgorithm::Sha256 => verify_password_sha256(&salted_hash, &password),
+        PasswordAlgorithm::Argon2id => verify_password_argon2(&salted_hash, &password),
+    })
+        .await
+        .map_err(|_| ())?
+}
+
+fn verify_password_sha256(salted_hash: &[u8], password: &[u8]) -> Result<bool, ()> {
+    if salted_hash.len() <= 16 { return Err(()) }
+    let (salt, hash) = salted_hash.split_at(16);
+
     let salt_password = [salt, password].concat();
-    let request_hash = openssl::hash::hash(openssl::hash::MessageDigest::sha256(), salt_password.as_slice())?;
+    let request_hash = openssl::hash::hash(openssl::hash::MessageDigest::sha256(), salt_password.as_slice())
+        .map_err(|_| ())?;
+
+    Ok(request_hash.len() == hash.len() && openssl::memcmp::eq(request_hash.as_ref(), hash))
+}
+
+fn verify_password_argon2(salted_hash: &[u8], password: &[u8]) -> Result<bool, ()> {
+    use argon2::password_hash::{PasswordHash, PasswordVerifier};
+
+    let phc = std::str::from_utf8(salted_hash).map_err(|_| ())?;
+    let parsed = PasswordHash::new(phc).map_err(|_| ())?;
+
+    // parameters are read back from the PHC string, the verification itself is constant-time
+    match argon2::Argon2::default().verify_password(password, &parsed) {
+        Ok(()) => Ok(true),
+        Err(argon2::password_hash::Error::Password) => Ok(false),
+        Err(_) => Err(()),
+    }
+}
+
+pub(crate) async fn hash_password(config: &Argon2Config, password: &[u8]) -> Result<Vec<u8>, ()> {
+    use argon2::password_hash::{PasswordHasher, SaltString};
+
+    let config = *config;
+    let password = password.to_vec();
+
+    rocket::tokio::task::spawn_blocking(move || {
+        let mut salt = [0; 16];
+        openssl::rand::rand_bytes(&mut salt).map_err(|_| ())?;
+        let salt = SaltString::encode_b64(&salt).map_err(|_| ())?;
+
+        let hash = argon2_hasher(&config)
+            .map_err(|_| ())?
+            .hash_password(&password, &salt)
+            .map_err(|_| ())?;
+
+        Ok(hash.to_string().into_bytes())
+    })
+        .await
+        .map_err(|_| ())?
+}
+
+// Hash of a password nobody knows, made with the configured cost parameters the first time it's needed
+async fn dummy_password_hash(config: &Argon2Config) -> Result<&'static [u8], ()> {
+    static DUMMY_HASH: rocket::tokio::sync::OnceCell<Vec<u8>> = rocket::tokio::sync::OnceCell::const_new();
+
+    DUMMY_HASH
+        .get_or_try_init(|| async {
+            let mut password = [0; 32];
+            openssl::rand::rand_bytes(&mut password).map_err(|_| ())?;
+            hash_password(config, &password).await
+        })
+        .await
+        .map(Vec::as_slice)
+}
+
+// Legacy hashes and hashes made with outdated cost parameters get replaced on the next login
+pub(crate) fn needs_rehash(config: &Argon2Config, algorithm: PasswordAlgorithm, salted_hash: &[u8]) -> bool {
+    use argon2::password_hash::PasswordHash;
 
-    Ok(request_hash.as_ref() == hash)
+    if algorithm != PasswordAlgorithm::Argon2id {
+        return true;
+    }
+
+    std::str::from_utf8(salted_hash)
+        .ok()
+        .and_then(|phc| PasswordHash::new(phc).ok())
+        .and_then(|parsed| argon2::Params::try_from(&parsed).ok())
+        .map(|params| {
+            params.m_cost() != config.memory_cost
+                || params.t_cost() != config.time_cost
+                || params.p_cost() != config.parallelism
+        })
+        .unwrap_or(true)
 }
 
-pub(crate) fn hash_password(password: &[u8]) -> Result<Vec<u8>, openssl::error::ErrorStack> {
-    let mut salt = [0; 16];
-    openssl::rand::rand_bytes(&mut salt)?;
 
-    let salt_password = [&salt, password].concat();
-    let request_hashed = openssl::hash::hash(openssl::hash::MessageDigest::sha256(), salt_password.as_slice())?;
 
-    let salted_hash = [salt.as_slice(), request_hashed.as_ref()].concat();;
+pub(crate) struct JwtKeys {
+    signing_kid: String,
+    signing_algorithm: jsonwebtoken::Algorithm,
+    encoding_key: jsonwebtoken::EncodingKey,
+    decoding_keys: HashMap<String, (jsonwebtoken::Algorithm, jsonwebtoken::DecodingKey)>,
+    // public halves of the asymmetric keys, HMAC secrets are never published
+    jwks: jsonwebtoken::jwk::JwkSet,
+}
+
+impl JwtKeys {
+    pub(crate) fn from_config(config: &JwtConfig) -> Result<Self, String> {
+        let mut decoding_keys = HashMap::new();
+        let mut jwks = Vec::new();
+        for key in &config.keys {
+            let (algorithm, decoding_key, jwk) = load_decoding_key(key)?;
+            if decoding_keys.insert(key.kid.clone(), (algorithm, decoding_key)).is_some() {
+                return Err(format!("JWT key `{}` is defined twice", key.kid));
+            }
+            jwks.extend(jwk);
+        }
+
+        let signing_key = match &config.signing_kid {
+            Some(kid) => config.keys.iter().find(|key| &key.kid == kid)
+                .ok_or_else(|| format!("JWT signing key `{}` is not defined", kid))?,
+            None => config.keys.first()
+                .ok_or_else(|| "no JWT keys are configured".to_string())?,
+        };
+
+        Ok(Self {
+            signing_kid: signing_key.kid.clone(),
+            signing_algorithm: jwt_algorithm(signing_key.algorithm),
+            encoding_key: load_encoding_key(signing_key)?,
+            decoding_keys,
+            jwks: jsonwebtoken::jwk::JwkSet { keys: jwks },
+        })
+    }
+
+    pub(crate) fn jwks(&self) -> &jsonwebtoken::jwk::JwkSet {
+        &self.jwks
+    }
 
-    Ok(salted_hash)
+    // signs with the current signing key, its kid tells verifiers which key to use
+    pub(crate) fn sign<T: rocket::serde::Serialize>(&self, claims: &T) -> Result<String, ()> {
+        let mut header = jsonwebtoken::Header::new(self.signing_algorithm);
+        header.kid = Some(self.signing_kid.clone());
+        jsonwebtoken::encode(&header, claims, &self.encoding_key).map_err(|_| ())
+    }
 }
 
+fn jwt_algorithm(algorithm: JwtAlgorithm) -> jsonwebtoken::Algorithm {
+    match algorithm {
+        JwtAlgorithm::HS512 => jsonwebtoken::Algorithm::HS512,
+        JwtAlgorithm::EdDSA => jsonwebtoken::Algorithm::EdDSA,
+        JwtAlgorithm::RS256 => jsonwebtoken::Algorithm::RS256,
+    }
+}
 
+fn load_encoding_key(key: &JwtKeyConfig) -> Result<jsonwebtoken::EncodingKey, String> {
+    use jsonwebtoken::EncodingKey;
+    use openssl::pkey::{Id, PKey};
 
-pub(crate) fn generate_token(user_id: uuid::Uuid) -> Result<String, ()> {
-    use jsonwebtoken::{Algorithm, encode, Header};
+    let invalid = |_| format!("JWT key `{}` has an invalid private key", key.kid);
 
+    if key.algorithm == JwtAlgorithm::HS512 {
+        let secret = key.secret.as_ref()
+            .ok_or_else(|| format!("JWT key `{}` has no secret", key.kid))?;
+        return Ok(EncodingKey::from_secret(secret.as_bytes()));
+    }
+
+    let pem = key.private_key.as_ref()
+        .ok_or_else(|| format!("JWT key `{}` has no private key and can't sign", key.kid))?;
+    let private_key = PKey::private_key_from_pem(pem.as_bytes()).map_err(invalid)?;
+
+    // ring wants PKCS#8 for Ed25519 and PKCS#1 for RSA, openssl takes care of whatever PEM flavour was configured
+    match (key.algorithm, private_key.id()) {
+        (JwtAlgorithm::EdDSA, Id::ED25519) => private_key.private_key_to_pkcs8()
+            .map(|der| EncodingKey::from_ed_der(&der))
+            .map_err(invalid),
+        (JwtAlgorithm::RS256, Id::RSA) => private_key.rsa()
+            .and_then(|rsa| rsa.private_key_to_der())
+            .map(|der| EncodingKey::from_rsa_der(&der))
+            .map_err(invalid),
+        _ => Err(format!("JWT key `{}` doesn't match its algorithm", key.kid)),
+    }
+}
+
+fn load_decoding_key(key: &JwtKeyConfig) -> Result<(jsonwebtoken::Algorithm, jsonwebtoken::DecodingKey, Option<jsonwebtoken::jwk::Jwk>), String> {
+    use base64::Engine;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use jsonwebtoken::DecodingKey;
+    use jsonwebtoken::jwk::{AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, KeyAlgorithm, OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType};
+    use openssl::pkey::{Id, PKey};
+
+    let algorithm = jwt_algorithm(key.algorithm);
+    let invalid = |_| format!("JWT key `{}` has an invalid public key", key.kid);
+
+    if key.algorithm == JwtAlgorithm::HS512 {
+        let secret = key.secret.as_ref()
+            .ok_or_else(|| format!("JWT key `{}` has no secret", key.kid))?;
+        if secret.len() < 32 {
+            return Err(format!("JWT key `{}` must be at least 32 bytes long", key.kid));
+        }
+        return Ok((algorithm, DecodingKey::from_secret(secret.as_bytes()), None));
+    }
+
+    let public_key = match (&key.public_key, &key.private_key) {
+        (Some(pem), _) => PKey::public_key_from_pem(pem.as_bytes()).map_err(invalid)?,
+        (None, Some(pem)) => PKey::private_key_from_pem(pem.as_bytes())
+            .and_then(|private_key| private_key.public_key_to_der())
+            .and_then(|der| PKey::public_key_from_der(&der))
+            .map_err(invalid)?,
+        (None, None) => return Err(format!("JWT key `{}` has neither a public nor a private key", key.kid)),
+    };
+
+    let common = |key_algorithm| CommonParameters {
+        public_key_use: Some(PublicKeyUse::Signature),
+        key_algorithm: Some(key_algorithm),
+        key_id: Some(key.kid.clone()),
+        ..Default::default()
+    };
+
+    let jwk = match (key.algorithm, public_key.id()) {
+        (JwtAlgorithm::EdDSA, Id::ED25519) => Jwk {
+            common: common(KeyAlgorithm::EdDSA),
+            algorithm: AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                key_type: OctetKeyPairType::OctetKeyPair,
+                curve: EllipticCurve::Ed25519,
+                x: URL_SAFE_NO_PAD.encode(public_key.raw_public_key().map_err(invalid)?),
+            }),
+        },
+        (JwtAlgorithm::RS256, Id::RSA) => {
+            let rsa = public_key.rsa().map_err(invalid)?;
+            Jwk {
+                common: common(KeyAlgorithm::RS256),
+                algorithm: AlgorithmParameters::RSA(RSAKeyParameters {
+                    key_type: RSAKeyType::RSA,
+                    n: URL_SAFE_NO_PAD.encode(rsa.n().to_vec()),
+                    e: URL_SAFE_NO_PAD.encode(rsa.e().to_vec()),
+                }),
+            }
+        }
+        _ => return Err(format!("JWT key `{}` doesn't match its algorithm", key.kid)),
+    };
+
+    let decoding_key = DecodingKey::from_jwk(&jwk)
+        .map_err(|_| format!("JWT key `{}` has an invalid public key", key.kid))?;
+    Ok((algorithm, decoding_key, Some(jwk)))
+}
+
+pub(crate) fn generate_token(keys: &JwtKeys, user_id: uuid::Uuid, permissions: Permissions, generation: i32, ttl: u32, client_id: Option<uuid::Uuid>, session_id: uuid::Uuid) -> Result<String, ()> {
     let expiration = chrono::Utc::now()
-        .checked_add_signed(chrono::Duration::seconds(3600))
-        .expect("") // TODO
+        .checked_add_signed(chrono::Duration::try_seconds(ttl.into()).ok_or(())?)
+        .ok_or(())?
         .timestamp();
 
     let claims = AuthClaims {
-        sub: user_id.to_string(),
-        perms: Permissions::new(
-            false,
-            true,
-            true,
-            true,
-            true,
-            true,
-            true,
-            true,
-            true,
-            true,
-            true,
-            true,
-
-        ),
+        sub: user_id,
+        perms: permissions,
         exp: expiration as usize,
+        jti: uuid::Uuid::new_v4(),
+        gen: generation,
+        access_token: None,
+        cid: client_id,
+        sid: Some(session_id),
     };
-    let header = Header::new(Algorithm::HS512);
-    let jwt = encode(&header, &claims, &jsonwebtoken::EncodingKey::from_secret(JWT_SECRET))
-        .map_err(|_| ())?;
+    keys.sign(&claims)
+}
+
+// Issued after a correct password when 2FA is on, it only proves the first factor
+#[derive(rocket::serde::Serialize, rocket::serde::Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct MfaClaims {
+    sub: uuid::Uuid,
+    exp: usize,
+    aud: String,
+}
+
+const MFA_AUDIENCE: &str = "mfa";
+
+pub(crate) fn generate_mfa_token(keys: &JwtKeys, user_id: uuid::Uuid, ttl: u32) -> Result<String, ()> {
+    let expiration = chrono::Utc::now()
+        .checked_add_signed(chrono::Duration::try_seconds(ttl.into()).ok_or(())?)
+        .ok_or(())?
+        .timestamp();
 
-    Ok(jwt)
+    let claims = MfaClaims {
+        sub: user_id,
+        exp: expiration as usize,
+        aud: MFA_AUDIENCE.to_string(),
+    };
+    keys.sign(&claims)
+}
+
+fn verify_mfa_token(keys: &JwtKeys, token: &str) -> Result<uuid::Uuid, ()> {
+    let (key, mut validation) = token_validation(keys, token)?;
+    validation.set_audience(&[MFA_AUDIENCE]);
+    validation.set_required_spec_claims(&["exp", "aud"]);
+
+    let claims = jsonwebtoken::decode::<MfaClaims>(token, key, &validation)
+        .map_err(|_| ())?;
+    Ok(claims.claims.sub)
 }
 
-pub(crate) fn verify_login_token(token: &str) -> Result<AuthClaims, ()> {
-    use jsonwebtoken::{Algorithm, decode, Validation};
+fn token_validation<'a>(keys: &'a JwtKeys, token: &str) -> Result<(&'a jsonwebtoken::DecodingKey, jsonwebtoken::Validation), ()> {
+    // tokens without a known kid were not issued by any of the active keys
+    let kid = jsonwebtoken::decode_header(token).map_err(|_| ())?.kid.ok_or(())?;
+    let (algorithm, key) = keys.decoding_keys.get(&kid).ok_or(())?;
 
+    // the algorithm is pinned by the key, never taken from the token header
+    Ok((key, jsonwebtoken::Validation::new(*algorithm)))
+}
+
+pub(crate) fn verify_login_token(keys: &JwtKeys, token: &str) -> Result<AuthClaims, ()> {
     let now = chrono::Utc::now();
 
-    let claims = decode::<AuthClaims>(token, &jsonwebtoken::DecodingKey::from_secret(JWT_SECRET), &Validation::new(Algorithm::HS512))
-        .map_err(|_| ()).unwrap();
+    // without an expected audience any aud claim is rejected, so mfa tokens can't be used here
+    let (key, validation) = token_validation(keys, token)?;
+    let claims = jsonwebtoken::decode::<AuthClaims>(token, key, &validation)
+        .map_err(|_| ())?;
 
     if now.timestamp() as usize > claims.claims.exp {
         Err(())
     } else {
         Ok(claims.claims)
     }
+}
 
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-}
\ No newline at end of file
+    #[rocket::async_test]
+    async fn dummy_hash_costs_as_much_as_a_real_one() {
+        let config = Argon2Config::default();
+        let dummy_hash = dummy_password_hash(&config).await.unwrap();
+        assert!(!needs_rehash(&config, PasswordAlgorithm::Argon2id, dummy_hash));
+        assert_eq!(verify_password(PasswordAlgorithm::Argon2id, dummy_hash, b"").await, Ok(false));
+        // made once, every unknown username is checked against the same hash
+        assert_eq!(dummy_password_hash(&config).await.unwrap().as_ptr(), dummy_hash.as_ptr());
+    }
+}
@@ -0,0 +1,151 @@
+use rocket_db_pools::diesel::prelude::*;
+
+use crate::database::access_tokens::insert_access_token;
+use crate::database::auth::NAME_SKELETON_CONSTRAINT;
+use crate::database::permissions::user_permissions;
+use crate::database::Db;
+use crate::models::{Bot, BotError, BotRequest, NewBot, Permission, Permissions, PolicyViolation};
+use crate::policy::{username_skeleton, RegistrationPolicy};
+use crate::schema::{personal_access_tokens, users};
+
+const BOT_TOKEN_NAME: &str = "bot";
+
+pub(crate) trait BotDatabase {
+    // bot names follow the same rules as registered usernames
+    async fn create_bot(&mut self, policy: &RegistrationPolicy, owner_id: uuid::Uuid, request: &BotRequest<'_>) -> Result<NewBot, BotError>;
+    async fn get_bots(&mut self, owner_id: uuid::Uuid) -> Result<Vec<Bot>, BotError>;
+    async fn set_bot_permissions(&mut self, owner_id: uuid::Uuid, bot_id: uuid::Uuid, permissions: &[Permission]) -> Result<Bot, BotError>;
+    // the previous token stops working
+    async fn regenerate_bot_token(&mut self, owner_id: uuid::Uuid, bot_id: uuid::Uuid) -> Result<NewBot, BotError>;
+}
+
+impl BotDatabase for rocket_db_pools::Connection<Db> {
+    async fn create_bot(&mut self, policy: &RegistrationPolicy, owner_id: uuid::Uuid, request: &BotRequest<'_>) -> Result<NewBot, BotError> {
+        let name = policy.check_username(request.name).map_err(BotError::Rejected)?;
+        let permissions = capped_permissions(self, owner_id, &request.permissions).await?;
+
+        let bot_id = diesel::insert_into(users::table)
+            .values((
+                users::name.eq(&name),
+                users::name_skeleton.eq(username_skeleton(&name)),
+                users::bot.eq(true),
+                users::owner_id.eq(owner_id),
+                users::permissions.eq(permissions.bits()),
+            ))
+            .returning(users::id)
+            .get_result::<uuid::Uuid>(self)
+            .await
+            .map_err(|err| match err {
+                diesel::result::Error::DatabaseError(diesel::result::DatabaseErrorKind::UniqueViolation, info) if info.constraint_name() == Some(NAME_SKELETON_CONSTRAINT) => {
+                    BotError::Rejected(vec![PolicyViolation::UsernameConfusable])
+                }
+                diesel::result::Error::DatabaseError(diesel::result::DatabaseErrorKind::UniqueViolation, _) => BotError::Conflict,
+                _ => BotError::InternalServerError,
+            })?;
+
+        let token = insert_bot_token(self, bot_id).await?;
+        Ok(NewBot {
+            token,
+            bot: Bot {
+                id: bot_id,
+                name,
+                permissions: permissions.granted(),
+            },
+        })
+    }
+
+    async fn get_bots(&mut self, owner_id: uuid::Uuid) -> Result<Vec<Bot>, BotError> {
+        let bots = users::table
+            .select((users::id, users::name, users::permissions))
+            .filter(users::owner_id.eq(owner_id))
+            .order(users::name.asc())
+            .load::<(uuid::Uuid, String, i32)>(self)
+            .await
+            .map_err(|_| BotError::InternalServerError)?;
+
+        Ok(bots.into_iter()
+            .map(|(id, name, permissions)| Bot {
+                id,
+                name,
+                permissions: user_permissions(permissions, false).granted(),
+            })
+            .collect())
+    }
+
+    async fn set_bot_permissions(&mut self, owner_id: uuid::Uuid, bot_id: uuid::Uuid, permissions: &[Permission]) -> Result<Bot, BotError> {
+        let permissions = capped_permissions(self, owner_id, permissions).await?;
+
+        let name = diesel::update(users::table)
+            .filter(users::id.eq(bot_id))
+            .filter(users::owner_id.eq(owner_id))
+            .set(users::permissions.eq(permissions.bits()))
+            .returning(users::name)
+            .get_result::<String>(self)
+            .await
+            .optional()
+            .map_err(|_| BotError::InternalServerError)?
+            .ok_or(BotError::NotFound)?;
+
+        Ok(Bot {
+            id: bot_id,
+            name,
+            permissions: permissions.granted(),
+        })
+    }
+
+    async fn regenerate_bot_token(&mut self, owner_id: uuid::Uuid, bot_id: uuid::Uuid) -> Result<NewBot, BotError> {
+        let (name, permissions) = users::table
+            .select((users::name, users::permissions))
+            .filter(users::id.eq(bot_id))
+            .filter(users::owner_id.eq(owner_id))
+            .first::<(String, i32)>(self)
+            .await
+            .optional()
+            .map_err(|_| BotError::InternalServerError)?
+            .ok_or(BotError::NotFound)?;
+
+        diesel::delete(personal_access_tokens::table)
+            .filter(personal_access_tokens::user_id.eq(bot_id))
+            .execute(self)
+            .await
+            .map_err(|_| BotError::InternalServerError)?;
+
+        let token = insert_bot_token(self, bot_id).await?;
+        Ok(NewBot {
+            token,
+            bot: Bot {
+                id: bot_id,
+                name,
+                permissions: user_permissions(permissions, false).granted(),
+            },
+        })
+    }
+}
+
+// Bots never get the developer bit, and nothing else their owner doesn't hold
+async fn capped_permissions(db: &mut rocket_db_pools::Connection<Db>, owner_id: uuid::Uuid, requested: &[Permission]) -> Result<Permissions, BotError> {
+    let (bits, developer, bot) = users::table
+        .select((users::permissions, users::developer, users::bot))
+        .filter(users::id.eq(owner_id))
+        .first::<(i32, bool, bool)>(db)
+        .await
+        .map_err(|_| BotError::InternalServerError)?;
+    if bot {
+        return Err(BotError::Forbidden);
+    }
+
+    let allowed = user_permissions(bits, developer).without(Permission::Developer);
+    let permissions = requested.iter().copied().collect::<Permissions>();
+    if permissions.intersection(allowed) != permissions {
+        return Err(BotError::ExceedsPermissions);
+    }
+    Ok(permissions)
+}
+
+// The token itself is unrestricted, the bot's own permissions limit it when it's used
+async fn insert_bot_token(db: &mut rocket_db_pools::Connection<Db>, bot_id: uuid::Uuid) -> Result<String, BotError> {
+    insert_access_token(db, bot_id, BOT_TOKEN_NAME, Permissions::from_bits(!0), None)
+        .await
+        .map(|(token, _, _)| token)
+        .map_err(|_| BotError::InternalServerError)
+}
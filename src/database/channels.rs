@@ -1,8 +1,8 @@
-use diesel::result::Error;
+use diesel::result::{DatabaseErrorKind, Error};
 use rocket_db_pools::diesel::prelude::*;
 
-use crate::{models, schema};
-use crate::schema::channels;
+use crate::models;
+use crate::schema::{channels, members, messages, users};
 
 pub(crate) enum DataRetrievalError {
     NotFound,
@@ -11,18 +11,21 @@ pub(crate) enum DataRetrievalError {
 
 pub(crate) enum DataInsertionError {
     AlreadyExists,
+    NotFound,
     InternalError,
 }
 
 pub(crate) enum DataRemovalError {
+    NotFound,
     InternalError,
 }
 
 pub(crate) enum DataSetError {
+    NotFound,
     InternalError,
 }
 
-pub trait Database {
+pub(crate) trait Database {
     type Id<'a>;
     type UserID<'a>;
     type Member;
@@ -32,6 +35,10 @@ pub trait Database {
     type MemberPatch;
     type MemberInsert;
     type Message;
+    type MessageInsert;
+    type User;
+
+    async fn get_user(&mut self, user_id: Self::UserID<'_>) -> Result<Self::User, DataRetrievalError>;
 
     async fn get_channel(&mut self, channel_id: Self::Id<'_>) -> Result<Self::Channel, DataRetrievalError>;
 
@@ -42,7 +49,7 @@ pub trait Database {
 
     async fn patch_channel(&mut self, channel_id: Self::Id<'_>, patch: Self::ChannelPatch) -> Result<Self::Channel, DataSetError>;
 
-    async fn remove_channel(&mut self, user: Self::Id<'_>) -> Result<Self::Channel, DataRemovalError>;
+    async fn remove_channel(&mut self, channel_id: Self::Id<'_>) -> Result<Self::Channel, DataRemovalError>;
 
     async fn get_members(&mut self, channel_id: Self::Id<'_>) -> Result<Vec<Self::Member>, DataRetrievalError>;
 
@@ -54,11 +61,11 @@ pub trait Database {
     async fn remove_member(&mut self, channel_id: Self::Id<'_>, user_id: Self::UserID<'_>) -> Result<Self::Member, DataRemovalError>;
 
     async fn get_messages(&mut self, channel_id: Self::Id<'_>) -> Result<Vec<Self::Message>, DataRetrievalError>;
-    async fn get_message(&mut self, channels_id: Self::Id<'_>, message_id: Self::Id<'_>) -> Result<Self::Message, DataRetrievalError>;
+    async fn get_message(&mut self, channel_id: Self::Id<'_>, message_id: Self::Id<'_>) -> Result<Self::Message, DataRetrievalError>;
 
-    async fn insert_message(&mut self, message: Self::Message) -> Result<Self::Message, DataInsertionError>;
+    async fn insert_message(&mut self, channel_id: Self::Id<'_>, user_id: Self::UserID<'_>, message: Self::MessageInsert) -> Result<Self::Message, DataInsertionError>;
 
-    async fn remove_message(&mut self, message_id: Self::Id<'_>) -> Result<Self::Message, DataRemovalError>;
+    async fn remove_message(&mut self, channel_id: Self::Id<'_>, message_id: Self::Id<'_>) -> Result<Self::Message, DataRemovalError>;
 }
 
 impl Database for rocket_db_pools::Connection<crate::database::Db> {
@@ -75,6 +82,21 @@ impl Database for rocket_db_pools::Connection<crate::database::Db> {
     type MemberInsert = models::MemberInsert;
 
     type Message = models::Message;
+    type MessageInsert = models::MessageInsert;
+
+    type User = models::User;
+
+    async fn get_user(&mut self, user_id: Self::UserID<'_>) -> Result<Self::User, DataRetrievalError> {
+        users::table
+            .select((users::id, users::name, users::bot))
+            .filter(users::id.eq(user_id))
+            .get_result(self)
+            .await
+            .map_err(|e| match e {
+                Error::NotFound => DataRetrievalError::NotFound,
+                _ => DataRetrievalError::InternalError,
+            })
+    }
 
     async fn get_channel(&mut self, channel_id: Self::Id<'_>) -> Result<Self::Channel, DataRetrievalError> {
         channels::table
@@ -96,8 +118,7 @@ impl Database for rocket_db_pools::Connection<crate::database::Db> {
             .map_err(|_| DataInsertionError::InternalError)
     }
 
-
-    async fn patch_channel(&mut self, channel_id: Self::Id<'_>, mut patch: Self::ChannelPatch) -> Result<Self::Channel, DataSetError> {
+    async fn patch_channel(&mut self, channel_id: Self::Id<'_>, patch: Self::ChannelPatch) -> Result<Self::Channel, DataSetError> {
         diesel::update(channels::table)
             .set(patch)
             .filter(channels::id.eq(channel_id))
@@ -105,7 +126,7 @@ impl Database for rocket_db_pools::Connection<crate::database::Db> {
             .get_result(self)
             .await
             .map_err(|e| match e {
-                Error::DatabaseError(_, _) => DataSetError::InternalError,
+                Error::NotFound => DataSetError::NotFound,
                 _ => DataSetError::InternalError,
             })
     }
@@ -117,13 +138,14 @@ impl Database for rocket_db_pools::Connection<crate::database::Db> {
             .get_result(self)
             .await
             .map_err(|e| match e {
+                Error::NotFound => DataRemovalError::NotFound,
                 _ => DataRemovalError::InternalError,
             })
     }
 
     async fn get_members(&mut self, channel_id: Self::Id<'_>) -> Result<Vec<Self::Member>, DataRetrievalError> {
-        schema::user_channel::table
-            .filter(schema::user_channel::channel_id.eq(channel_id))
+        members::table
+            .filter(members::channel_id.eq(channel_id))
             .get_results(self)
             .await
             .map_err(|e| match e {
@@ -133,9 +155,9 @@ impl Database for rocket_db_pools::Connection<crate::database::Db> {
     }
 
     async fn get_member(&mut self, channel_id: Self::Id<'_>, user_id: Self::UserID<'_>) -> Result<Self::Member, DataRetrievalError> {
-        schema::user_channel::table
-            .filter(schema::user_channel::user_id.eq(user_id))
-            .filter(schema::user_channel::channel_id.eq(channel_id))
+        members::table
+            .filter(members::user_id.eq(user_id))
+            .filter(members::channel_id.eq(channel_id))
             .get_result(self)
             .await
             .map_err(|e| match e {
@@ -145,45 +167,49 @@ impl Database for rocket_db_pools::Connection<crate::database::Db> {
     }
 
     async fn insert_member(&mut self, channel_id: Self::Id<'_>, member: Self::MemberInsert) -> Result<Self::Member, DataInsertionError> {
-        diesel::insert_into(schema::user_channel::table)
-            .values(member)
-            .returning(schema::user_channel::all_columns)
+        diesel::insert_into(members::table)
+            .values((members::channel_id.eq(channel_id), member))
+            .returning(members::all_columns)
             .get_result(self)
             .await
-            .map_err(|e| {
-                println!("{:?}", e);
-                DataInsertionError::InternalError
+            .map_err(|e| match e {
+                Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => DataInsertionError::AlreadyExists,
+                Error::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _) => DataInsertionError::NotFound,
+                _ => DataInsertionError::InternalError,
             })
     }
 
     async fn patch_member(&mut self, channel_id: Self::Id<'_>, user_id: Self::UserID<'_>, member: Self::MemberPatch) -> Result<Self::Member, DataSetError> {
-        diesel::update(schema::user_channel::table)
+        diesel::update(members::table)
             .set(member)
-            .filter(schema::user_channel::channel_id.eq(channel_id))
-            .returning(schema::user_channel::all_columns)
+            .filter(members::channel_id.eq(channel_id))
+            .filter(members::user_id.eq(user_id))
+            .returning(members::all_columns)
             .get_result(self)
             .await
             .map_err(|e| match e {
-                Error::DatabaseError(_, _) => DataSetError::InternalError,
+                Error::NotFound => DataSetError::NotFound,
                 _ => DataSetError::InternalError,
             })
     }
 
     async fn remove_member(&mut self, channel_id: Self::Id<'_>, user_id: Self::UserID<'_>) -> Result<Self::Member, DataRemovalError> {
-        diesel::delete(schema::user_channel::table)
-            .filter(schema::user_channel::channel_id.eq(channel_id))
-            .filter(schema::user_channel::user_id.eq(user_id))
-            .returning(schema::user_channel::all_columns)
+        diesel::delete(members::table)
+            .filter(members::channel_id.eq(channel_id))
+            .filter(members::user_id.eq(user_id))
+            .returning(members::all_columns)
             .get_result(self)
             .await
             .map_err(|e| match e {
+                Error::NotFound => DataRemovalError::NotFound,
                 _ => DataRemovalError::InternalError,
             })
     }
 
     async fn get_messages(&mut self, channel_id: Self::Id<'_>) -> Result<Vec<Self::Message>, DataRetrievalError> {
-        schema::messages::table
-            .filter(schema::messages::channel_id.eq(channel_id))
+        messages::table
+            .filter(messages::channel_id.eq(channel_id))
+            .order(messages::created_at.desc())
             .limit(50)
             .get_results(self)
             .await
@@ -193,10 +219,10 @@ impl Database for rocket_db_pools::Connection<crate::database::Db> {
             })
     }
 
-    async fn get_message(&mut self, channels_id: Self::Id<'_>, message_id: Self::Id<'_>) -> Result<Self::Message, DataRetrievalError> {
-        schema::messages::table
-            .filter(schema::messages::id.eq(message_id))
-            .filter(schema::messages::channel_id.eq(channels_id))
+    async fn get_message(&mut self, channel_id: Self::Id<'_>, message_id: Self::Id<'_>) -> Result<Self::Message, DataRetrievalError> {
+        messages::table
+            .filter(messages::id.eq(message_id))
+            .filter(messages::channel_id.eq(channel_id))
             .get_result(self)
             .await
             .map_err(|e| match e {
@@ -205,22 +231,28 @@ impl Database for rocket_db_pools::Connection<crate::database::Db> {
             })
     }
 
-    async fn insert_message(&mut self, message: Self::Message) -> Result<Self::Message, DataInsertionError> {
-        diesel::insert_into(schema::messages::table)
-            .values(message)
-            .returning(schema::messages::all_columns)
+    async fn insert_message(&mut self, channel_id: Self::Id<'_>, user_id: Self::UserID<'_>, message: Self::MessageInsert) -> Result<Self::Message, DataInsertionError> {
+        diesel::insert_into(messages::table)
+            .values((
+                messages::channel_id.eq(channel_id),
+                messages::user_id.eq(user_id),
+                message,
+            ))
+            .returning(messages::all_columns)
             .get_result(self)
             .await
-            .map_err(|e| DataInsertionError::InternalError)
+            .map_err(|_| DataInsertionError::InternalError)
     }
 
-    async fn remove_message(&mut self, message_id: Self::Id<'_>) -> Result<Self::Message, DataRemovalError> {
-        diesel::delete(schema::messages::table)
-            .filter(schema::messages::id.eq(message_id))
-            .returning(schema::messages::all_columns)
+    async fn remove_message(&mut self, channel_id: Self::Id<'_>, message_id: Self::Id<'_>) -> Result<Self::Message, DataRemovalError> {
+        diesel::delete(messages::table)
+            .filter(messages::id.eq(message_id))
+            .filter(messages::channel_id.eq(channel_id))
+            .returning(messages::all_columns)
             .get_result(self)
             .await
             .map_err(|e| match e {
+                Error::NotFound => DataRemovalError::NotFound,
                 _ => DataRemovalError::InternalError,
             })
     }
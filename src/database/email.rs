@@ -0,0 +1,277 @@
+use diesel::result::{DatabaseErrorKind, Error};
+use rocket_db_pools::diesel::prelude::*;
+
+use crate::database::Db;
+use crate::models::{AuthConfig, EmailError, UserEmail};
+use crate::schema::{email_verifications, users};
+
+// wrong guesses a code takes before a new one has to be requested
+const MAX_CODE_ATTEMPTS: i32 = 5;
+const MAX_EMAIL_LENGTH: usize = 254;
+
+// What `set_email` hands back to be sent to the new address
+pub(crate) struct PendingVerification {
+    pub username: String,
+    pub email: String,
+    pub code: String,
+}
+
+pub(crate) trait EmailDatabase {
+    async fn get_email(&mut self, user_id: uuid::Uuid) -> Result<UserEmail, EmailError>;
+    // None when the address is already the user's; the current address stays until the new one's code comes back
+    async fn set_email(&mut self, config: &AuthConfig, user_id: uuid::Uuid, email: &str) -> Result<Option<PendingVerification>, EmailError>;
+    async fn verify_email(&mut self, user_id: uuid::Uuid, code: &str) -> Result<(), EmailError>;
+    async fn remove_email(&mut self, user_id: uuid::Uuid) -> Result<(), EmailError>;
+}
+
+impl EmailDatabase for rocket_db_pools::Connection<Db> {
+    async fn get_email(&mut self, user_id: uuid::Uuid) -> Result<UserEmail, EmailError> {
+        let email = users::table
+            .select(users::email)
+            .filter(users::id.eq(user_id))
+            .first::<Option<String>>(self)
+            .await
+            .map_err(|err| match err {
+                Error::NotFound => EmailError::NotFound,
+                _ => EmailError::InternalServerError,
+            })?;
+        let pending = email_verifications::table
+            .select(email_verifications::email)
+            .filter(email_verifications::user_id.eq(user_id))
+            .filter(email_verifications::expires_at.gt(chrono::Utc::now()))
+            .first::<String>(self)
+            .await
+            .optional()
+            .map_err(|_| EmailError::InternalServerError)?;
+        Ok(UserEmail { email, pending })
+    }
+
+    async fn set_email(&mut self, config: &AuthConfig, user_id: uuid::Uuid, email: &str) -> Result<Option<PendingVerification>, EmailError> {
+        let email = normalize_email(email).ok_or(EmailError::InvalidEmail)?;
+
+        let (username, current) = users::table
+            .select((users::name, users::email))
+            .filter(users::id.eq(user_id))
+            .first::<(String, Option<String>)>(self)
+            .await
+            .map_err(|err| match err {
+                Error::NotFound => EmailError::NotFound,
+                _ => EmailError::InternalServerError,
+            })?;
+        if current.as_deref() == Some(email.as_str()) {
+            // going back to the current address drops a pending change
+            diesel::delete(email_verifications::table)
+                .filter(email_verifications::user_id.eq(user_id))
+                .execute(self)
+                .await
+                .map_err(|_| EmailError::InternalServerError)?;
+            return Ok(None);
+        }
+        // checked again when the code comes back, this just saves sending one that can't succeed
+        let taken = diesel::select(diesel::dsl::exists(users::table.filter(users::email.eq(&email))))
+            .get_result::<bool>(self)
+            .await
+            .map_err(|_| EmailError::InternalServerError)?;
+        if taken {
+            return Err(EmailError::Conflict);
+        }
+
+        let now = chrono::Utc::now();
+        let cooldown = chrono::Duration::try_seconds(config.email_verification_cooldown.into()).ok_or(EmailError::InternalServerError)?;
+
+        let pending = email_verifications::table
+            .select((email_verifications::attempts, email_verifications::created_at, email_verifications::expires_at))
+            .filter(email_verifications::user_id.eq(user_id))
+            .first::<(i32, chrono::DateTime<chrono::Utc>, chrono::DateTime<chrono::Utc>)>(self)
+            .await
+            .optional()
+            .map_err(|_| EmailError::InternalServerError)?;
+        // a new code doesn't bring back the guesses spent on the previous one, only its expiry does
+        let mut attempts = 0;
+        if let Some((used, created_at, expires_at)) = pending.filter(|(_, _, expires_at)| *expires_at > now) {
+            if created_at + cooldown > now {
+                return Err(too_many_requests(created_at + cooldown - now));
+            }
+            if used >= MAX_CODE_ATTEMPTS {
+                return Err(too_many_requests(expires_at - now));
+            }
+            attempts = used;
+        }
+
+        // anyone can enter any address, this keeps them from flooding someone else's inbox
+        let last_sent = email_verifications::table
+            .select(diesel::dsl::max(email_verifications::created_at))
+            .filter(email_verifications::email.eq(&email))
+            .get_result::<Option<chrono::DateTime<chrono::Utc>>>(self)
+            .await
+            .map_err(|_| EmailError::InternalServerError)?;
+        if let Some(last_sent) = last_sent.filter(|last_sent| *last_sent + cooldown > now) {
+            return Err(too_many_requests(last_sent + cooldown - now));
+        }
+
+        let code = generate_code().map_err(|_| EmailError::InternalServerError)?;
+        let code_hash = hash_code(user_id, &code).map_err(|_| EmailError::InternalServerError)?;
+        let expires_at = now
+            + chrono::Duration::try_seconds(config.email_verification_ttl.into()).ok_or(EmailError::InternalServerError)?;
+
+        // asking again replaces the pending code, only the latest one works
+        diesel::insert_into(email_verifications::table)
+            .values((
+                email_verifications::user_id.eq(user_id),
+                email_verifications::email.eq(&email),
+                email_verifications::code_hash.eq(&code_hash),
+                email_verifications::attempts.eq(attempts),
+                email_verifications::created_at.eq(now),
+                email_verifications::expires_at.eq(expires_at),
+            ))
+            .on_conflict(email_verifications::user_id)
+            .do_update()
+            .set((
+                email_verifications::email.eq(&email),
+                email_verifications::code_hash.eq(&code_hash),
+                email_verifications::attempts.eq(attempts),
+                email_verifications::created_at.eq(now),
+                email_verifications::expires_at.eq(expires_at),
+            ))
+            .execute(self)
+            .await
+            .map_err(|_| EmailError::InternalServerError)?;
+
+        Ok(Some(PendingVerification { username, email, code }))
+    }
+
+    async fn verify_email(&mut self, user_id: uuid::Uuid, code: &str) -> Result<(), EmailError> {
+        // the attempt is counted before the code is compared, so concurrent guesses can't get around the limit
+        let (email, code_hash) = diesel::update(email_verifications::table)
+            .filter(email_verifications::user_id.eq(user_id))
+            .filter(email_verifications::expires_at.gt(chrono::Utc::now()))
+            .filter(email_verifications::attempts.lt(MAX_CODE_ATTEMPTS))
+            .set(email_verifications::attempts.eq(email_verifications::attempts + 1))
+            .returning((email_verifications::email, email_verifications::code_hash))
+            .get_result::<(String, Vec<u8>)>(self)
+            .await
+            .optional()
+            .map_err(|_| EmailError::InternalServerError)?
+            .ok_or(EmailError::InvalidCode)?;
+
+        let sent_hash = hash_code(user_id, code.trim()).map_err(|_| EmailError::InternalServerError)?;
+        if sent_hash.len() != code_hash.len() || !openssl::memcmp::eq(&sent_hash, &code_hash) {
+            return Err(EmailError::InvalidCode);
+        }
+
+        diesel::update(users::table)
+            .filter(users::id.eq(user_id))
+            .set(users::email.eq(&email))
+            .execute(self)
+            .await
+            .map_err(|err| match err {
+                Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => EmailError::Conflict,
+                _ => EmailError::InternalServerError,
+            })?;
+
+        diesel::delete(email_verifications::table)
+            .filter(email_verifications::user_id.eq(user_id))
+            .execute(self)
+            .await
+            .map_err(|_| EmailError::InternalServerError)?;
+        Ok(())
+    }
+
+    async fn remove_email(&mut self, user_id: uuid::Uuid) -> Result<(), EmailError> {
+        diesel::update(users::table)
+            .filter(users::id.eq(user_id))
+            .set(users::email.eq(None::<String>))
+            .execute(self)
+            .await
+            .map_err(|_| EmailError::InternalServerError)?;
+
+        diesel::delete(email_verifications::table)
+            .filter(email_verifications::user_id.eq(user_id))
+            .execute(self)
+            .await
+            .map_err(|_| EmailError::InternalServerError)?;
+        Ok(())
+    }
+}
+
+fn too_many_requests(wait: chrono::Duration) -> EmailError {
+    // rounded up, retrying right at the given second must work
+    let retry_after = (wait.num_milliseconds().max(0) as u64).div_ceil(1000);
+    EmailError::TooManyRequests { retry_after }
+}
+
+// Where a user's notifications are mailed, None without a verified address
+pub(crate) async fn verified_email(db: &mut rocket_db_pools::Connection<Db>, user_id: uuid::Uuid) -> Option<String> {
+    let email = users::table
+        .select(users::email)
+        .filter(users::id.eq(user_id))
+        .first::<Option<String>>(db)
+        .await
+        .optional();
+    match email {
+        Ok(email) => email.flatten(),
+        Err(_) => {
+            warn!("Could not read the email address of user {}", user_id);
+            None
+        }
+    }
+}
+
+// Deliberately loose, the code sent to the address is the real check
+fn normalize_email(email: &str) -> Option<String> {
+    let email = email.trim().to_lowercase();
+    let (local, domain) = email.rsplit_once('@')?;
+    let valid = email.len() <= MAX_EMAIL_LENGTH
+        && !local.is_empty()
+        && local.len() <= 64
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && !email.chars().any(|c| c.is_whitespace() || c.is_control());
+    valid.then_some(email)
+}
+
+// six digits, rejection sampling keeps every code equally likely
+fn generate_code() -> Result<String, ()> {
+    const CODES: u32 = 1_000_000;
+    loop {
+        let mut bytes = [0; 4];
+        openssl::rand::rand_bytes(&mut bytes).map_err(|_| ())?;
+        let value = u32::from_be_bytes(bytes);
+        if value < u32::MAX - u32::MAX % CODES {
+            return Ok(format!("{:06}", value % CODES));
+        }
+    }
+}
+
+// Codes are short-lived and capped at a few guesses, the user id keeps equal codes of different users apart
+fn hash_code(user_id: uuid::Uuid, code: &str) -> Result<Vec<u8>, ()> {
+    let message = [user_id.as_bytes().as_slice(), code.as_bytes()].concat();
+    openssl::hash::hash(openssl::hash::MessageDigest::sha256(), &message)
+        .map(|digest| digest.to_vec())
+        .map_err(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses_are_normalized_loosely() {
+        assert_eq!(normalize_email("  Alice@Example.ORG "), Some("alice@example.org".to_string()));
+        assert_eq!(normalize_email("alice@localhost"), None);
+        assert_eq!(normalize_email("@example.org"), None);
+        assert_eq!(normalize_email("al ice@example.org"), None);
+    }
+
+    #[test]
+    fn retry_after_is_rounded_up() {
+        let retry_after = |milliseconds| match too_many_requests(chrono::Duration::try_milliseconds(milliseconds).unwrap()) {
+            EmailError::TooManyRequests { retry_after } => retry_after,
+            _ => unreachable!(),
+        };
+        assert_eq!(retry_after(59_001), 60);
+        assert_eq!(retry_after(60_000), 60);
+        assert_eq!(retry_after(-5), 0);
+    }
+}
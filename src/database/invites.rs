@@ -0,0 +1,191 @@
+use rocket_db_pools::diesel::prelude::*;
+
+use crate::database::permissions::user_permissions;
+use crate::database::{hash_secret, random_token, Db};
+use crate::models::{Invite, InviteError, InviteRequest, InviteUse, NewInvite, Permissions};
+use crate::schema::{invite_codes, invite_uses, users};
+
+pub(crate) trait InviteDatabase {
+    async fn create_invite(&mut self, created_by: uuid::Uuid, request: &InviteRequest) -> Result<NewInvite, InviteError>;
+    // revoked and used up invites included, newest first
+    async fn get_invites(&mut self) -> Result<Vec<Invite>, InviteError>;
+    // accounts that already registered with it are kept
+    async fn revoke_invite(&mut self, id: uuid::Uuid) -> Result<(), InviteError>;
+    async fn get_invite_uses(&mut self, id: uuid::Uuid) -> Result<Vec<InviteUse>, InviteError>;
+}
+
+impl InviteDatabase for rocket_db_pools::Connection<Db> {
+    async fn create_invite(&mut self, created_by: uuid::Uuid, request: &InviteRequest) -> Result<NewInvite, InviteError> {
+        let max_uses = i32::try_from(request.max_uses).ok()
+            .filter(|max_uses| *max_uses > 0)
+            .ok_or(InviteError::InvalidMaxUses)?;
+        let permissions = request.permissions.as_ref()
+            .map(|permissions| permissions.iter().copied().collect::<Permissions>());
+
+        let expires_at = match request.expires_in {
+            Some(expires_in) => Some(
+                chrono::Utc::now() + chrono::Duration::try_seconds(expires_in.into()).ok_or(InviteError::InternalServerError)?
+            ),
+            None => None,
+        };
+
+        let code = random_token().map_err(|_| InviteError::InternalServerError)?;
+
+        let (id, created_at) = diesel::insert_into(invite_codes::table)
+            .values((
+                invite_codes::code_hash.eq(hash_secret(&code).map_err(|_| InviteError::InternalServerError)?),
+                invite_codes::created_by.eq(created_by),
+                invite_codes::permissions.eq(permissions.map(|permissions| permissions.bits())),
+                invite_codes::max_uses.eq(max_uses),
+                invite_codes::expires_at.eq(expires_at),
+            ))
+            .returning((invite_codes::id, invite_codes::created_at))
+            .get_result::<(uuid::Uuid, chrono::DateTime<chrono::Utc>)>(self)
+            .await
+            .map_err(|_| InviteError::InternalServerError)?;
+
+        Ok(NewInvite {
+            code,
+            details: Invite {
+                id,
+                created_by: Some(created_by),
+                permissions: permissions.map(|permissions| permissions.granted()),
+                max_uses: request.max_uses,
+                uses: 0,
+                created_at,
+                expires_at,
+                revoked_at: None,
+            },
+        })
+    }
+
+    async fn get_invites(&mut self) -> Result<Vec<Invite>, InviteError> {
+        let invites = invite_codes::table
+            .select((
+                invite_codes::id,
+                invite_codes::created_by,
+                invite_codes::permissions,
+                invite_codes::max_uses,
+                invite_codes::uses,
+                invite_codes::created_at,
+                invite_codes::expires_at,
+                invite_codes::revoked_at,
+            ))
+            .order(invite_codes::created_at.desc())
+            .load::<(uuid::Uuid, Option<uuid::Uuid>, Option<i32>, i32, i32, chrono::DateTime<chrono::Utc>, Option<chrono::DateTime<chrono::Utc>>, Option<chrono::DateTime<chrono::Utc>>)>(self)
+            .await
+            .map_err(|_| InviteError::InternalServerError)?;
+
+        Ok(invites.into_iter()
+            .map(|(id, created_by, permissions, max_uses, uses, created_at, expires_at, revoked_at)| Invite {
+                id,
+                created_by,
+                permissions: permissions.map(|bits| Permissions::from_bits(bits).granted()),
+                max_uses: max_uses.try_into().unwrap_or_default(),
+                uses: uses.try_into().unwrap_or_default(),
+                created_at,
+                expires_at,
+                revoked_at,
+            })
+            .collect())
+    }
+
+    async fn revoke_invite(&mut self, id: uuid::Uuid) -> Result<(), InviteError> {
+        let revoked = diesel::update(invite_codes::table)
+            .filter(invite_codes::id.eq(id))
+            .filter(invite_codes::revoked_at.is_null())
+            .set(invite_codes::revoked_at.eq(chrono::Utc::now()))
+            .execute(self)
+            .await
+            .map_err(|_| InviteError::InternalServerError)?;
+        if revoked == 0 {
+            return Err(InviteError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn get_invite_uses(&mut self, id: uuid::Uuid) -> Result<Vec<InviteUse>, InviteError> {
+        let exists = diesel::select(diesel::dsl::exists(invite_codes::table.filter(invite_codes::id.eq(id))))
+            .get_result::<bool>(self)
+            .await
+            .map_err(|_| InviteError::InternalServerError)?;
+        if !exists {
+            return Err(InviteError::NotFound);
+        }
+
+        let uses = invite_uses::table
+            .inner_join(users::table)
+            .select((invite_uses::user_id, users::name, invite_uses::used_at))
+            .filter(invite_uses::invite_id.eq(id))
+            .order(invite_uses::used_at.asc())
+            .load::<(uuid::Uuid, String, chrono::DateTime<chrono::Utc>)>(self)
+            .await
+            .map_err(|_| InviteError::InternalServerError)?;
+
+        Ok(uses.into_iter()
+            .map(|(user_id, username, used_at)| InviteUse { user_id, username, used_at })
+            .collect())
+    }
+}
+
+// An invite that was redeemed for a registration
+pub(crate) struct RedeemedInvite {
+    pub id: uuid::Uuid,
+    // what the account starts with, the column defaults when None
+    pub permissions: Option<Permissions>,
+}
+
+impl RedeemedInvite {
+    // users.permissions and users.developer for the new account
+    pub(crate) fn user_permissions(&self) -> Option<(i32, bool)> {
+        self.permissions.map(|permissions| {
+            let developer = permissions.developer();
+            (user_permissions(permissions.bits(), false).bits(), developer)
+        })
+    }
+}
+
+// Takes one use of the code, None when it's unknown, revoked, expired or used up.
+// The use is taken before the account exists, two registrations can't both get the last one.
+pub(crate) async fn redeem_invite(db: &mut rocket_db_pools::Connection<Db>, code: &str) -> Result<Option<RedeemedInvite>, ()> {
+    diesel::update(invite_codes::table)
+        .filter(invite_codes::code_hash.eq(hash_secret(code)?))
+        .filter(invite_codes::revoked_at.is_null())
+        .filter(invite_codes::expires_at.is_null().or(invite_codes::expires_at.gt(chrono::Utc::now())))
+        .filter(invite_codes::uses.lt(invite_codes::max_uses))
+        .set(invite_codes::uses.eq(invite_codes::uses + 1))
+        .returning((invite_codes::id, invite_codes::permissions))
+        .get_result::<(uuid::Uuid, Option<i32>)>(db)
+        .await
+        .optional()
+        .map(|redeemed| redeemed.map(|(id, permissions)| RedeemedInvite {
+            id,
+            permissions: permissions.map(Permissions::from_bits),
+        }))
+        .map_err(|_| ())
+}
+
+// Gives the use back when the registration it was taken for failed
+pub(crate) async fn release_invite(db: &mut rocket_db_pools::Connection<Db>, invite_id: uuid::Uuid) {
+    let released = diesel::update(invite_codes::table)
+        .filter(invite_codes::id.eq(invite_id))
+        .filter(invite_codes::uses.gt(0))
+        .set(invite_codes::uses.eq(invite_codes::uses - 1))
+        .execute(db)
+        .await;
+    if released.is_err() {
+        warn!("Could not release a use of invite {}", invite_id);
+    }
+}
+
+pub(crate) async fn record_invite_use(db: &mut rocket_db_pools::Connection<Db>, invite_id: uuid::Uuid, user_id: uuid::Uuid) -> Result<(), ()> {
+    diesel::insert_into(invite_uses::table)
+        .values((
+            invite_uses::user_id.eq(user_id),
+            invite_uses::invite_id.eq(invite_id),
+        ))
+        .execute(db)
+        .await
+        .map(|_| ())
+        .map_err(|_| ())
+}
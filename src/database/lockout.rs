@@ -0,0 +1,174 @@
+use std::net::IpAddr;
+
+use rocket_db_pools::diesel::prelude::*;
+
+use crate::database::Db;
+use crate::models::{Lockout, LockoutConfig, LockoutError, LockoutKind};
+use crate::schema::login_attempts;
+
+pub(crate) trait LockoutDatabase {
+    // seconds until the next attempt is allowed, None when neither the username nor the IP is locked
+    async fn login_lockout(&mut self, login: &str, client_ip: Option<IpAddr>) -> Result<Option<u64>, ()>;
+    async fn record_login_failure(&mut self, config: &LockoutConfig, login: &str, client_ip: Option<IpAddr>) -> Result<(), ()>;
+    async fn clear_login_failures(&mut self, login: &str) -> Result<(), ()>;
+    async fn get_lockouts(&mut self) -> Result<Vec<Lockout>, LockoutError>;
+    async fn remove_lockout(&mut self, kind: LockoutKind, key: &str) -> Result<(), LockoutError>;
+}
+
+impl LockoutDatabase for rocket_db_pools::Connection<Db> {
+    async fn login_lockout(&mut self, login: &str, client_ip: Option<IpAddr>) -> Result<Option<u64>, ()> {
+        let now = chrono::Utc::now();
+
+        let mut query = login_attempts::table
+            .select(login_attempts::locked_until)
+            .filter(login_attempts::locked_until.gt(now))
+            .filter(login_attempts::kind.eq(LockoutKind::Username).and(login_attempts::key.eq(login)))
+            .into_boxed();
+        if let Some(client_ip) = client_ip {
+            query = query.or_filter(
+                login_attempts::locked_until.gt(now)
+                    .and(login_attempts::kind.eq(LockoutKind::Ip))
+                    .and(login_attempts::key.eq(client_ip.to_string()))
+            );
+        }
+
+        let locked_until = query
+            .load::<Option<chrono::DateTime<chrono::Utc>>>(self)
+            .await
+            .map_err(|_| ())?
+            .into_iter()
+            .flatten()
+            .max();
+
+        // rounded up, retrying right at the reported second must not hit the lockout again
+        Ok(locked_until.map(|locked_until| {
+            let milliseconds = (locked_until - now).num_milliseconds().max(0) as u64;
+            milliseconds.div_ceil(1000)
+        }))
+    }
+
+    async fn record_login_failure(&mut self, config: &LockoutConfig, login: &str, client_ip: Option<IpAddr>) -> Result<(), ()> {
+        let now = chrono::Utc::now();
+        let stale = now - chrono::Duration::try_seconds(config.reset_after.into()).ok_or(())?;
+
+        // counters that went quiet and aren't holding a lockout start over
+        diesel::delete(login_attempts::table)
+            .filter(login_attempts::last_failure.lt(stale))
+            .filter(login_attempts::locked_until.is_null().or(login_attempts::locked_until.lt(now)))
+            .execute(self)
+            .await
+            .map_err(|_| ())?;
+
+        record_failure(self, config, LockoutKind::Username, login, config.max_attempts).await?;
+        if let Some(client_ip) = client_ip {
+            record_failure(self, config, LockoutKind::Ip, &client_ip.to_string(), config.ip_max_attempts).await?;
+        }
+        Ok(())
+    }
+
+    // Only the username is cleared, a single valid account must not unlock an IP that guesses others
+    async fn clear_login_failures(&mut self, login: &str) -> Result<(), ()> {
+        diesel::delete(login_attempts::table)
+            .filter(login_attempts::kind.eq(LockoutKind::Username))
+            .filter(login_attempts::key.eq(login))
+            .execute(self)
+            .await
+            .map_err(|_| ())?;
+        Ok(())
+    }
+
+    async fn get_lockouts(&mut self) -> Result<Vec<Lockout>, LockoutError> {
+        login_attempts::table
+            .select((
+                login_attempts::kind,
+                login_attempts::key,
+                login_attempts::failures,
+                login_attempts::last_failure,
+                login_attempts::locked_until,
+            ))
+            .order(login_attempts::last_failure.desc())
+            .load::<Lockout>(self)
+            .await
+            .map_err(|_| LockoutError::InternalServerError)
+    }
+
+    async fn remove_lockout(&mut self, kind: LockoutKind, key: &str) -> Result<(), LockoutError> {
+        let removed = diesel::delete(login_attempts::table)
+            .filter(login_attempts::kind.eq(kind))
+            .filter(login_attempts::key.eq(key))
+            .execute(self)
+            .await
+            .map_err(|_| LockoutError::InternalServerError)?;
+        if removed == 0 {
+            return Err(LockoutError::NotFound);
+        }
+        Ok(())
+    }
+}
+
+async fn record_failure(
+    db: &mut rocket_db_pools::Connection<Db>,
+    config: &LockoutConfig,
+    kind: LockoutKind,
+    key: &str,
+    max_attempts: u32,
+) -> Result<(), ()> {
+    let now = chrono::Utc::now();
+
+    let failures = diesel::insert_into(login_attempts::table)
+        .values((
+            login_attempts::kind.eq(kind),
+            login_attempts::key.eq(key),
+            login_attempts::failures.eq(1),
+            login_attempts::last_failure.eq(now),
+        ))
+        .on_conflict((login_attempts::kind, login_attempts::key))
+        .do_update()
+        .set((
+            login_attempts::failures.eq(login_attempts::failures + 1),
+            login_attempts::last_failure.eq(now),
+        ))
+        .returning(login_attempts::failures)
+        .get_result::<i32>(db)
+        .await
+        .map_err(|_| ())?;
+
+    let failures = failures.max(0) as u32;
+    if failures < max_attempts {
+        return Ok(());
+    }
+
+    let lockout = lockout_seconds(config, failures - max_attempts);
+    let locked_until = now + chrono::Duration::try_seconds(lockout.into()).ok_or(())?;
+    diesel::update(login_attempts::table)
+        .filter(login_attempts::kind.eq(kind))
+        .filter(login_attempts::key.eq(key))
+        .set(login_attempts::locked_until.eq(locked_until))
+        .execute(db)
+        .await
+        .map_err(|_| ())?;
+    Ok(())
+}
+
+// base_lockout * 2^excess, capped at max_lockout
+fn lockout_seconds(config: &LockoutConfig, excess: u32) -> u32 {
+    config.base_lockout
+        .saturating_mul(2u32.saturating_pow(excess))
+        .min(config.max_lockout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lockouts_double_up_to_the_maximum() {
+        let config = LockoutConfig::default();
+        assert_eq!(lockout_seconds(&config, 0), 30);
+        assert_eq!(lockout_seconds(&config, 1), 60);
+        assert_eq!(lockout_seconds(&config, 3), 4 * 60);
+        assert_eq!(lockout_seconds(&config, 7), 60 * 60);
+        // 2^40 doesn't fit, it saturates instead of wrapping around to a short lockout
+        assert_eq!(lockout_seconds(&config, 40), 60 * 60);
+    }
+}
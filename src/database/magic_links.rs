@@ -0,0 +1,107 @@
+use rocket_db_pools::diesel::prelude::*;
+
+use crate::database::auth::{generate_mfa_token, issue_login_token, login_succeeded, JwtKeys};
+use crate::database::mfa::MfaDatabase;
+use crate::database::{hash_secret, random_token, Db};
+use crate::models::{AuthConfig, DeviceInfo, LoginResponse, MagicLinkError};
+use crate::schema::{magic_links, users};
+
+pub(crate) trait MagicLinkDatabase {
+    // None when there is no such user or a link was sent moments ago, callers must not reveal either
+    async fn create_magic_link(&mut self, config: &AuthConfig, login: &str) -> Result<Option<(uuid::Uuid, String)>, MagicLinkError>;
+    // the link stands in for the password, users with 2FA still have to pass `/auth/login/2fa`
+    async fn magic_link_login(&mut self, config: &AuthConfig, keys: &JwtKeys, token: &str, device: &DeviceInfo) -> Result<LoginResponse, MagicLinkError>;
+}
+
+impl MagicLinkDatabase for rocket_db_pools::Connection<Db> {
+    async fn create_magic_link(&mut self, config: &AuthConfig, login: &str) -> Result<Option<(uuid::Uuid, String)>, MagicLinkError> {
+        let user_id = users::table
+            .select(users::id)
+            .filter(users::name.eq(login))
+            .filter(users::bot.eq(false))
+            .first::<uuid::Uuid>(self)
+            .await
+            .optional()
+            .map_err(|_| MagicLinkError::InternalServerError)?;
+        let Some(user_id) = user_id else {
+            return Ok(None);
+        };
+
+        // anyone can ask for a link to any account, this keeps them from flooding its inbox
+        // and from replacing the link the user is about to open
+        let now = chrono::Utc::now();
+        let cooldown = chrono::Duration::try_seconds(config.magic_link_cooldown.into()).ok_or(MagicLinkError::InternalServerError)?;
+        let recently_sent = diesel::select(diesel::dsl::exists(
+            magic_links::table
+                .filter(magic_links::user_id.eq(user_id))
+                .filter(magic_links::created_at.gt(now - cooldown))
+        ))
+            .get_result::<bool>(self)
+            .await
+            .map_err(|_| MagicLinkError::InternalServerError)?;
+        if recently_sent {
+            return Ok(None);
+        }
+
+        let token = random_token().map_err(|_| MagicLinkError::InternalServerError)?;
+
+        let expires_at = now
+            + chrono::Duration::try_seconds(config.magic_link_ttl.into()).ok_or(MagicLinkError::InternalServerError)?;
+
+        // only the latest requested link stays valid
+        diesel::delete(magic_links::table)
+            .filter(magic_links::user_id.eq(user_id))
+            .execute(self)
+            .await
+            .map_err(|_| MagicLinkError::InternalServerError)?;
+
+        diesel::insert_into(magic_links::table)
+            .values((
+                magic_links::user_id.eq(user_id),
+                magic_links::token_hash.eq(hash_secret(&token).map_err(|_| MagicLinkError::InternalServerError)?),
+                magic_links::created_at.eq(now),
+                magic_links::expires_at.eq(expires_at),
+            ))
+            .execute(self)
+            .await
+            .map_err(|_| MagicLinkError::InternalServerError)?;
+
+        Ok(Some((user_id, token)))
+    }
+
+    async fn magic_link_login(&mut self, config: &AuthConfig, keys: &JwtKeys, token: &str, device: &DeviceInfo) -> Result<LoginResponse, MagicLinkError> {
+        let token_hash = hash_secret(token).map_err(|_| MagicLinkError::InternalServerError)?;
+
+        // marking the link used is the check itself, so it can't be redeemed twice concurrently
+        let user_id = diesel::update(magic_links::table)
+            .filter(magic_links::token_hash.eq(token_hash))
+            .filter(magic_links::used_at.is_null())
+            .filter(magic_links::expires_at.gt(chrono::Utc::now()))
+            .set(magic_links::used_at.eq(chrono::Utc::now()))
+            .returning(magic_links::user_id)
+            .get_result::<uuid::Uuid>(self)
+            .await
+            .optional()
+            .map_err(|_| MagicLinkError::InternalServerError)?
+            .ok_or(MagicLinkError::InvalidToken)?;
+
+        let login = users::table
+            .select(users::name)
+            .filter(users::id.eq(user_id))
+            .first::<String>(self)
+            .await
+            .map_err(|_| MagicLinkError::InternalServerError)?;
+
+        if self.is_totp_enabled(user_id).await.map_err(|_| MagicLinkError::InternalServerError)? {
+            let mfa_token = generate_mfa_token(keys, user_id, config.mfa_token_ttl)
+                .map_err(|_| MagicLinkError::InternalServerError)?;
+            return Ok(LoginResponse::MfaRequired { mfa_token, expires_in: config.mfa_token_ttl });
+        }
+
+        login_succeeded(self, user_id, &login, device, "magic_link").await;
+        issue_login_token(self, config, keys, user_id, &login, device, "magic_link")
+            .await
+            .map(LoginResponse::Token)
+            .map_err(|_| MagicLinkError::InternalServerError)
+    }
+}
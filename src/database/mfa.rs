@@ -0,0 +1,262 @@
+use diesel::result::Error;
+use rocket_db_pools::diesel::prelude::*;
+use totp_rs::TOTP;
+
+use crate::database::Db;
+use crate::models::{AuthConfig, MfaError, RecoveryCodes, TotpEnrollment};
+use crate::schema::{recovery_codes, totp_secrets, users};
+
+const TOTP_DIGITS: usize = 6;
+const TOTP_STEP: u64 = 30;
+const RECOVERY_CODE_COUNT: usize = 10;
+
+pub(crate) trait MfaDatabase {
+    async fn enroll_totp(&mut self, config: &AuthConfig, user_id: uuid::Uuid) -> Result<TotpEnrollment, MfaError>;
+    async fn confirm_totp(&mut self, user_id: uuid::Uuid, code: &str) -> Result<RecoveryCodes, MfaError>;
+    async fn is_totp_enabled(&mut self, user_id: uuid::Uuid) -> Result<bool, MfaError>;
+    // Accepts a TOTP code or an unused recovery code, either works only once
+    async fn verify_second_factor(&mut self, user_id: uuid::Uuid, code: &str) -> Result<(), MfaError>;
+    async fn disable_totp(&mut self, user_id: uuid::Uuid) -> Result<(), MfaError>;
+}
+
+impl MfaDatabase for rocket_db_pools::Connection<Db> {
+    async fn enroll_totp(&mut self, config: &AuthConfig, user_id: uuid::Uuid) -> Result<TotpEnrollment, MfaError> {
+        if self.is_totp_enabled(user_id).await? {
+            return Err(MfaError::AlreadyEnabled);
+        }
+
+        let name = users::table
+            .select(users::name)
+            .filter(users::id.eq(user_id))
+            .first::<String>(self)
+            .await
+            .map_err(|_| MfaError::InternalServerError)?;
+
+        let mut secret = vec![0; 20];
+        openssl::rand::rand_bytes(&mut secret).map_err(|_| MfaError::InternalServerError)?;
+
+        // enrolling again before confirming simply replaces the pending secret
+        diesel::insert_into(totp_secrets::table)
+            .values((
+                totp_secrets::user_id.eq(user_id),
+                totp_secrets::secret.eq(&secret),
+            ))
+            .on_conflict(totp_secrets::user_id)
+            .do_update()
+            .set((
+                totp_secrets::secret.eq(&secret),
+                totp_secrets::last_used_step.eq(None::<i64>),
+            ))
+            .execute(self)
+            .await
+            .map_err(|_| MfaError::InternalServerError)?;
+
+        let totp = totp(secret, Some(config.totp_issuer.clone()), name);
+        Ok(TotpEnrollment {
+            secret: totp.get_secret_base32(),
+            otpauth_url: totp.get_url(),
+        })
+    }
+
+    async fn confirm_totp(&mut self, user_id: uuid::Uuid, code: &str) -> Result<RecoveryCodes, MfaError> {
+        let (secret, enabled) = totp_secrets::table
+            .select((totp_secrets::secret, totp_secrets::enabled))
+            .filter(totp_secrets::user_id.eq(user_id))
+            .first::<(Vec<u8>, bool)>(self)
+            .await
+            .map_err(|e| match e {
+                Error::NotFound => MfaError::NotEnrolled,
+                _ => MfaError::InternalServerError,
+            })?;
+        if enabled {
+            return Err(MfaError::AlreadyEnabled);
+        }
+
+        let step = matching_step(&totp(secret, None, String::new()), code).ok_or(MfaError::InvalidCode)?;
+
+        diesel::update(totp_secrets::table)
+            .filter(totp_secrets::user_id.eq(user_id))
+            .set((
+                totp_secrets::enabled.eq(true),
+                totp_secrets::last_used_step.eq(step as i64),
+            ))
+            .execute(self)
+            .await
+            .map_err(|_| MfaError::InternalServerError)?;
+
+        let mut codes = Vec::with_capacity(RECOVERY_CODE_COUNT);
+        let mut hashes = Vec::with_capacity(RECOVERY_CODE_COUNT);
+        for _ in 0..RECOVERY_CODE_COUNT {
+            let code = generate_recovery_code().map_err(|_| MfaError::InternalServerError)?;
+            hashes.push((
+                recovery_codes::user_id.eq(user_id),
+                recovery_codes::code_hash.eq(hash_recovery_code(&code).map_err(|_| MfaError::InternalServerError)?),
+            ));
+            codes.push(code);
+        }
+
+        diesel::delete(recovery_codes::table)
+            .filter(recovery_codes::user_id.eq(user_id))
+            .execute(self)
+            .await
+            .map_err(|_| MfaError::InternalServerError)?;
+        diesel::insert_into(recovery_codes::table)
+            .values(hashes)
+            .execute(self)
+            .await
+            .map_err(|_| MfaError::InternalServerError)?;
+
+        Ok(RecoveryCodes { recovery_codes: codes })
+    }
+
+    async fn is_totp_enabled(&mut self, user_id: uuid::Uuid) -> Result<bool, MfaError> {
+        diesel::select(diesel::dsl::exists(
+            totp_secrets::table
+                .filter(totp_secrets::user_id.eq(user_id))
+                .filter(totp_secrets::enabled.eq(true))
+        ))
+            .get_result::<bool>(self)
+            .await
+            .map_err(|_| MfaError::InternalServerError)
+    }
+
+    async fn verify_second_factor(&mut self, user_id: uuid::Uuid, code: &str) -> Result<(), MfaError> {
+        let secret = totp_secrets::table
+            .select(totp_secrets::secret)
+            .filter(totp_secrets::user_id.eq(user_id))
+            .filter(totp_secrets::enabled.eq(true))
+            .first::<Vec<u8>>(self)
+            .await
+            .map_err(|e| match e {
+                Error::NotFound => MfaError::NotEnrolled,
+                _ => MfaError::InternalServerError,
+            })?;
+
+        let code = code.trim();
+        if code.len() == TOTP_DIGITS && code.bytes().all(|c| c.is_ascii_digit()) {
+            let step = matching_step(&totp(secret, None, String::new()), code).ok_or(MfaError::InvalidCode)?;
+
+            // only moves forward, so the same code (or an older one) is rejected the second time
+            let updated = diesel::update(totp_secrets::table)
+                .filter(totp_secrets::user_id.eq(user_id))
+                .filter(totp_secrets::last_used_step.is_null().or(totp_secrets::last_used_step.lt(step as i64)))
+                .set(totp_secrets::last_used_step.eq(step as i64))
+                .execute(self)
+                .await
+                .map_err(|_| MfaError::InternalServerError)?;
+            return if updated == 1 { Ok(()) } else { Err(MfaError::InvalidCode) };
+        }
+
+        let updated = diesel::update(recovery_codes::table)
+            .filter(recovery_codes::user_id.eq(user_id))
+            .filter(recovery_codes::code_hash.eq(hash_recovery_code(code).map_err(|_| MfaError::InternalServerError)?))
+            .filter(recovery_codes::used_at.is_null())
+            .set(recovery_codes::used_at.eq(chrono::Utc::now()))
+            .execute(self)
+            .await
+            .map_err(|_| MfaError::InternalServerError)?;
+        if updated == 1 { Ok(()) } else { Err(MfaError::InvalidCode) }
+    }
+
+    async fn disable_totp(&mut self, user_id: uuid::Uuid) -> Result<(), MfaError> {
+        let removed = diesel::delete(totp_secrets::table)
+            .filter(totp_secrets::user_id.eq(user_id))
+            .execute(self)
+            .await
+            .map_err(|_| MfaError::InternalServerError)?;
+        if removed == 0 {
+            return Err(MfaError::NotEnrolled);
+        }
+
+        diesel::delete(recovery_codes::table)
+            .filter(recovery_codes::user_id.eq(user_id))
+            .execute(self)
+            .await
+            .map_err(|_| MfaError::InternalServerError)?;
+        Ok(())
+    }
+}
+
+// RFC 6238 defaults, the only parameters authenticator apps reliably support
+fn totp(secret: Vec<u8>, issuer: Option<String>, account_name: String) -> TOTP {
+    TOTP::new_unchecked(totp_rs::Algorithm::SHA1, TOTP_DIGITS, 1, TOTP_STEP, secret, issuer, account_name)
+}
+
+// Time step the code belongs to, one step of clock drift is tolerated in both directions
+fn matching_step(totp: &TOTP, code: &str) -> Option<u64> {
+    let now = chrono::Utc::now().timestamp() as u64;
+    let current = now / TOTP_STEP;
+
+    (current.saturating_sub(1)..=current + 1).find(|step| {
+        let expected = totp.generate(step * TOTP_STEP);
+        expected.len() == code.len() && openssl::memcmp::eq(expected.as_bytes(), code.as_bytes())
+    })
+}
+
+// 80 random bits as four dash-separated groups of base32
+fn generate_recovery_code() -> Result<String, ()> {
+    let mut bytes = vec![0; 10];
+    openssl::rand::rand_bytes(&mut bytes).map_err(|_| ())?;
+    let encoded = totp_rs::Secret::Raw(bytes).to_encoded().to_string();
+
+    Ok(encoded.as_bytes()
+        .chunks(4)
+        .map(|chunk| String::from_utf8_lossy(chunk).into_owned())
+        .collect::<Vec<_>>()
+        .join("-"))
+}
+
+// Case and dashes don't matter when typing a recovery code back in
+fn hash_recovery_code(code: &str) -> Result<Vec<u8>, ()> {
+    let normalized: String = code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+
+    openssl::hash::hash(openssl::hash::MessageDigest::sha256(), normalized.as_bytes())
+        .map(|digest| digest.to_vec())
+        .map_err(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_totp() -> TOTP {
+        totp(b"12345678901234567890".to_vec(), None, "alice".to_string())
+    }
+
+    #[test]
+    fn codes_match_their_step_with_one_step_of_drift() {
+        let totp = test_totp();
+        let current = chrono::Utc::now().timestamp() as u64 / TOTP_STEP;
+
+        // a step ending mid-test only moves the codes one step into the past, which is still within the drift
+        let now = totp.generate(current * TOTP_STEP);
+        assert_eq!(matching_step(&totp, &now), Some(current));
+        let next = totp.generate((current + 1) * TOTP_STEP);
+        assert_eq!(matching_step(&totp, &next), Some(current + 1));
+
+        let stale = totp.generate((current - 2) * TOTP_STEP);
+        assert_eq!(matching_step(&totp, &stale), None);
+        assert_eq!(matching_step(&totp, &now[..TOTP_DIGITS - 1]), None);
+        assert_eq!(matching_step(&totp, ""), None);
+    }
+
+    #[test]
+    fn recovery_codes_ignore_case_and_separators() {
+        let hash = hash_recovery_code("ABCD-EFGH-IJKL-MNOP").unwrap();
+        assert_eq!(hash_recovery_code("abcd-efgh-ijkl-mnop").unwrap(), hash);
+        assert_eq!(hash_recovery_code(" abcd efgh ijkl mnop ").unwrap(), hash);
+        assert_eq!(hash_recovery_code("ABCDEFGHIJKLMNOP").unwrap(), hash);
+        assert_ne!(hash_recovery_code("ABCD-EFGH-IJKL-MNOQ").unwrap(), hash);
+    }
+
+    #[test]
+    fn recovery_codes_are_four_groups_of_base32() {
+        let code = generate_recovery_code().unwrap();
+        let groups = code.split('-').collect::<Vec<_>>();
+        assert_eq!(groups.len(), 4);
+        assert!(groups.iter().all(|group| group.len() == 4 && group.chars().all(|c| c.is_ascii_uppercase() || ('2'..='7').contains(&c))));
+    }
+}
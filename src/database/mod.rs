@@ -1,7 +1,31 @@
+pub(crate) mod access_tokens;
+pub(crate) mod account;
+pub(crate) mod audit;
 pub(crate) mod auth;
+pub(crate) mod bots;
+pub(crate) mod channels;
+pub(crate) mod email;
+pub(crate) mod invites;
+pub(crate) mod lockout;
+pub(crate) mod magic_links;
+pub(crate) mod mfa;
+pub(crate) mod oauth;
+pub(crate) mod oidc;
+pub(crate) mod passkeys;
+pub(crate) mod password;
+pub(crate) mod permissions;
+pub(crate) mod sessions;
+pub(crate) mod token;
 
+use std::time::Duration;
+
+use rocket::fairing::AdHoc;
 use rocket_db_pools::{Database, diesel};
 
+use crate::policy::username_skeleton;
+
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
 #[derive(Database)]
 #[database("chat_app")]
 pub struct Db(diesel::PgPool);
@@ -13,8 +37,125 @@ pub trait PostgreSQLDatabase {
 impl PostgreSQLDatabase for rocket::Rocket<rocket::Build> {
     fn attach_database(self) -> Self {
         self.attach(Db::init())
+            .attach(AdHoc::on_liftoff("Username skeletons", |rocket| Box::pin(async move {
+                if let Some(db) = Db::fetch(rocket) {
+                    backfill_name_skeletons(db).await;
+                }
+            })))
+            .attach(AdHoc::on_liftoff("Expired tokens", |rocket| Box::pin(async move {
+                if let Some(db) = Db::fetch(rocket) {
+                    rocket::tokio::spawn(prune_periodically(db.0.clone(), rocket.shutdown()));
+                }
+            })))
         // TODO: migrations
     }
 }
 
+// 256 random bits as base64url, for tokens, codes and states that end up in URLs and headers
+pub(crate) fn random_token() -> Result<String, ()> {
+    use base64::Engine;
+
+    let mut secret = [0; 32];
+    openssl::rand::rand_bytes(&mut secret).map_err(|_| ())?;
+    Ok(base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(secret))
+}
+
+// Secrets like `random_token`s can't be guessed, a plain digest is enough to keep them out of the database.
+// Passwords can, they go through `auth::hash_password` instead.
+pub(crate) fn hash_secret(secret: &str) -> Result<Vec<u8>, ()> {
+    openssl::hash::hash(openssl::hash::MessageDigest::sha256(), secret.as_bytes())
+        .map(|digest| digest.to_vec())
+        .map_err(|_| ())
+}
+
+// Accounts created before names had skeletons get theirs on the next start, until then their look-alikes can be registered
+async fn backfill_name_skeletons(db: &Db) {
+    use rocket_db_pools::diesel::prelude::*;
+    use crate::schema::users;
 
+    let mut connection = match db.get().await {
+        Ok(connection) => connection,
+        Err(err) => {
+            error!("Could not connect to fill in username skeletons: {}", err);
+            return;
+        }
+    };
+    let users = users::table
+        .select((users::id, users::name))
+        .filter(users::name_skeleton.is_null())
+        .load::<(uuid::Uuid, String)>(&mut connection)
+        .await;
+    let users = match users {
+        Ok(users) => users,
+        Err(err) => {
+            error!("Could not read users without a username skeleton: {}", err);
+            return;
+        }
+    };
+
+    for (user_id, name) in users {
+        let updated = diesel::update(users::table)
+            .filter(users::id.eq(user_id))
+            .set(users::name_skeleton.eq(username_skeleton(&name)))
+            .execute(&mut connection)
+            .await;
+        match updated {
+            Ok(_) => {}
+            // accounts that already looked alike keep working, whichever is filled in first holds the skeleton
+            Err(diesel::result::Error::DatabaseError(diesel::result::DatabaseErrorKind::UniqueViolation, _)) => {
+                warn!("User {} looks like another account, its name has no skeleton", user_id);
+            }
+            Err(err) => {
+                error!("Could not store the username skeleton of user {}: {}", user_id, err);
+                return;
+            }
+        }
+    }
+}
+
+
+
+// Rows that can't authenticate anything anymore, removed every `PRUNE_INTERVAL` until the server shuts down
+async fn prune_periodically(pool: diesel::PgPool, mut shutdown: rocket::Shutdown) {
+    let mut interval = rocket::tokio::time::interval(PRUNE_INTERVAL);
+    loop {
+        rocket::tokio::select! {
+            _ = interval.tick() => {}
+            _ = &mut shutdown => return,
+        }
+        let mut connection = match pool.get().await {
+            Ok(connection) => connection,
+            Err(err) => {
+                error!("Could not connect to prune expired tokens: {}", err);
+                continue;
+            }
+        };
+        if let Err(err) = prune_expired(&mut connection).await {
+            error!("Could not prune expired tokens: {}", err);
+        }
+    }
+}
+
+async fn prune_expired(db: &mut diesel::AsyncPgConnection) -> Result<(), rocket_db_pools::diesel::result::Error> {
+    use rocket_db_pools::diesel::prelude::*;
+    use crate::schema::{login_sessions, refresh_tokens, revoked_tokens};
+
+    let now = chrono::Utc::now();
+    // used tokens are kept until they expire, reuse detection needs them until then
+    let refresh_tokens = diesel::delete(refresh_tokens::table)
+        .filter(refresh_tokens::expires_at.lt(now))
+        .execute(db)
+        .await?;
+    // a token past its expiry is rejected without looking at the list
+    let revoked_tokens = diesel::delete(revoked_tokens::table)
+        .filter(revoked_tokens::expires_at.lt(now))
+        .execute(db)
+        .await?;
+    // access tokens of a session that's gone are rejected just like those of a revoked one
+    let sessions = diesel::delete(login_sessions::table)
+        .filter(login_sessions::expires_at.lt(now).or(login_sessions::revoked_at.is_not_null()))
+        .execute(db)
+        .await?;
+    info!("Pruned {} refresh tokens, {} revoked tokens and {} sessions", refresh_tokens, revoked_tokens, sessions);
+    Ok(())
+}
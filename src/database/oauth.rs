@@ -0,0 +1,451 @@
+use rocket_db_pools::diesel::prelude::*;
+use rocket_db_pools::diesel::scoped_futures::ScopedFutureExt;
+
+use crate::database::audit::record_auth_event;
+use crate::database::auth::{issue_token, verify_login_token, AuthDatabase, ClientGrant, JwtKeys};
+use crate::database::permissions::user_permissions;
+use crate::database::sessions::end_session;
+use crate::database::{hash_secret, random_token, Db};
+use crate::models::{AuthConfig, AuthEventKind, AuthorizationRequest, DeviceInfo, NewOAuthClient, OAuthClient, OAuthClientRequest, OAuthConsent, OAuthError, OAuthRevocationRequest, OAuthToken, OAuthTokenError, OAuthTokenRequest, Permission, Permissions};
+use crate::schema::{login_sessions, oauth_clients, oauth_codes, refresh_tokens, users};
+
+const MAX_REDIRECT_URIS: usize = 10;
+
+pub(crate) trait OAuthDatabase {
+    async fn register_client(&mut self, owner_id: uuid::Uuid, request: &OAuthClientRequest<'_>) -> Result<NewOAuthClient, OAuthError>;
+    async fn get_clients(&mut self, owner_id: uuid::Uuid) -> Result<Vec<OAuthClient>, OAuthError>;
+    async fn remove_client(&mut self, owner_id: uuid::Uuid, client_id: uuid::Uuid) -> Result<(), OAuthError>;
+    // validates the request, nothing is stored until the user decides
+    async fn authorization_consent(&mut self, user_id: uuid::Uuid, request: &AuthorizationRequest<'_>) -> Result<OAuthConsent, OAuthError>;
+    // returns the authorization code
+    async fn authorize(&mut self, config: &AuthConfig, user_id: uuid::Uuid, request: &AuthorizationRequest<'_>) -> Result<String, OAuthError>;
+    // the session is started on the device of the client, usually its server
+    async fn exchange_code(&mut self, config: &AuthConfig, keys: &JwtKeys, request: &OAuthTokenRequest<'_>, device: &DeviceInfo) -> Result<OAuthToken, OAuthTokenError>;
+    async fn refresh_client_token(&mut self, config: &AuthConfig, keys: &JwtKeys, request: &OAuthTokenRequest<'_>, device: &DeviceInfo) -> Result<OAuthToken, OAuthTokenError>;
+    async fn revoke_client_token(&mut self, keys: &JwtKeys, request: &OAuthRevocationRequest<'_>) -> Result<(), OAuthTokenError>;
+    // ends every session of the client, its access tokens stop working as well
+    async fn revoke_authorization(&mut self, user_id: uuid::Uuid, client_id: uuid::Uuid) -> Result<(), OAuthError>;
+}
+
+impl OAuthDatabase for rocket_db_pools::Connection<Db> {
+    async fn register_client(&mut self, owner_id: uuid::Uuid, request: &OAuthClientRequest<'_>) -> Result<NewOAuthClient, OAuthError> {
+        if request.name.is_empty() || request.name.chars().count() > 64 {
+            return Err(OAuthError::InvalidName);
+        }
+        if request.redirect_uris.is_empty()
+            || request.redirect_uris.len() > MAX_REDIRECT_URIS
+            || !request.redirect_uris.iter().all(|uri| is_valid_redirect_uri(uri)) {
+            return Err(OAuthError::InvalidRedirectUri);
+        }
+
+        let client_secret = if request.confidential {
+            Some(random_token().map_err(|_| OAuthError::InternalServerError)?)
+        } else {
+            None
+        };
+        let secret_hash = match &client_secret {
+            Some(secret) => Some(hash_secret(secret).map_err(|_| OAuthError::InternalServerError)?),
+            None => None,
+        };
+
+        let (id, created_at) = diesel::insert_into(oauth_clients::table)
+            .values((
+                oauth_clients::owner_id.eq(owner_id),
+                oauth_clients::name.eq(request.name),
+                oauth_clients::redirect_uris.eq(&request.redirect_uris),
+                oauth_clients::secret_hash.eq(secret_hash),
+            ))
+            .returning((oauth_clients::id, oauth_clients::created_at))
+            .get_result::<(uuid::Uuid, chrono::DateTime<chrono::Utc>)>(self)
+            .await
+            .map_err(|_| OAuthError::InternalServerError)?;
+
+        Ok(NewOAuthClient {
+            client_secret,
+            client: OAuthClient {
+                id,
+                name: request.name.to_string(),
+                redirect_uris: request.redirect_uris.clone(),
+                confidential: request.confidential,
+                created_at,
+            },
+        })
+    }
+
+    async fn get_clients(&mut self, owner_id: uuid::Uuid) -> Result<Vec<OAuthClient>, OAuthError> {
+        let clients = oauth_clients::table
+            .select((
+                oauth_clients::id,
+                oauth_clients::name,
+                oauth_clients::redirect_uris,
+                oauth_clients::secret_hash.is_not_null(),
+                oauth_clients::created_at,
+            ))
+            .filter(oauth_clients::owner_id.eq(owner_id))
+            .order(oauth_clients::created_at.asc())
+            .load::<(uuid::Uuid, String, Vec<String>, bool, chrono::DateTime<chrono::Utc>)>(self)
+            .await
+            .map_err(|_| OAuthError::InternalServerError)?;
+
+        Ok(clients.into_iter()
+            .map(|(id, name, redirect_uris, confidential, created_at)| OAuthClient {
+                id,
+                name,
+                redirect_uris,
+                confidential,
+                created_at,
+            })
+            .collect())
+    }
+
+    async fn remove_client(&mut self, owner_id: uuid::Uuid, client_id: uuid::Uuid) -> Result<(), OAuthError> {
+        // codes and refresh tokens of the client go with it
+        let removed = diesel::delete(oauth_clients::table)
+            .filter(oauth_clients::id.eq(client_id))
+            .filter(oauth_clients::owner_id.eq(owner_id))
+            .execute(self)
+            .await
+            .map_err(|_| OAuthError::InternalServerError)?;
+        if removed == 0 {
+            return Err(OAuthError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn authorization_consent(&mut self, user_id: uuid::Uuid, request: &AuthorizationRequest<'_>) -> Result<OAuthConsent, OAuthError> {
+        let (client_id, client_name) = validate_authorization_request(self, request).await?;
+        let scope = granted_scope(self, user_id, request.scope).await?;
+
+        Ok(OAuthConsent {
+            client_id,
+            client_name,
+            redirect_uri: request.redirect_uri.to_string(),
+            scope: scope.granted(),
+        })
+    }
+
+    async fn authorize(&mut self, config: &AuthConfig, user_id: uuid::Uuid, request: &AuthorizationRequest<'_>) -> Result<String, OAuthError> {
+        let (client_id, _) = validate_authorization_request(self, request).await?;
+        let scope = granted_scope(self, user_id, request.scope).await?;
+
+        let code = random_token().map_err(|_| OAuthError::InternalServerError)?;
+
+        let now = chrono::Utc::now();
+        let expires_at = now + chrono::Duration::try_seconds(config.authorization_code_ttl.into()).ok_or(OAuthError::InternalServerError)?;
+
+        // used codes are kept until they expire, exchanging one twice has to be recognized
+        diesel::delete(oauth_codes::table)
+            .filter(oauth_codes::expires_at.lt(now))
+            .execute(self)
+            .await
+            .map_err(|_| OAuthError::InternalServerError)?;
+
+        diesel::insert_into(oauth_codes::table)
+            .values((
+                oauth_codes::client_id.eq(client_id),
+                oauth_codes::user_id.eq(user_id),
+                oauth_codes::code_hash.eq(hash_secret(&code).map_err(|_| OAuthError::InternalServerError)?),
+                oauth_codes::redirect_uri.eq(request.redirect_uri),
+                oauth_codes::permissions.eq(scope.bits()),
+                oauth_codes::code_challenge.eq(request.code_challenge),
+                oauth_codes::expires_at.eq(expires_at),
+            ))
+            .execute(self)
+            .await
+            .map_err(|_| OAuthError::InternalServerError)?;
+        Ok(code)
+    }
+
+    async fn exchange_code(&mut self, config: &AuthConfig, keys: &JwtKeys, request: &OAuthTokenRequest<'_>, device: &DeviceInfo) -> Result<OAuthToken, OAuthTokenError> {
+        let (Some(code), Some(redirect_uri), Some(code_verifier)) = (request.code, request.redirect_uri, request.code_verifier) else {
+            return Err(OAuthTokenError::InvalidRequest);
+        };
+        let client_id = authenticate_client(self, request.client_id, request.client_secret).await?;
+        let code_hash = hash_secret(code).map_err(|_| OAuthTokenError::InternalServerError)?;
+        let now = chrono::Utc::now();
+
+        // the code is only marked as used once the request is known to come from its client, otherwise anyone who saw it
+        // could burn it with a wrong verifier and have the real client's exchange treated as a replay
+        let hash = &code_hash;
+        let redeemed = self.transaction::<_, OAuthTokenError, _>(|db| async move {
+            let code = oauth_codes::table
+                .select((
+                    oauth_codes::id,
+                    oauth_codes::client_id,
+                    oauth_codes::user_id,
+                    oauth_codes::redirect_uri,
+                    oauth_codes::permissions,
+                    oauth_codes::code_challenge,
+                ))
+                .filter(oauth_codes::code_hash.eq(hash))
+                .filter(oauth_codes::used_at.is_null())
+                .filter(oauth_codes::expires_at.gt(now))
+                // same as refresh tokens, a code can only be redeemed once
+                .for_update()
+                .first::<(uuid::Uuid, uuid::Uuid, uuid::Uuid, String, i32, String)>(db)
+                .await
+                .optional()?;
+            let Some((code_id, code_client_id, user_id, code_redirect_uri, scope, code_challenge)) = code else {
+                return Ok(None);
+            };
+            if code_client_id != client_id || code_redirect_uri != redirect_uri || !verify_code_challenge(&code_challenge, code_verifier) {
+                return Err(OAuthTokenError::InvalidGrant);
+            }
+
+            diesel::update(oauth_codes::table)
+                .filter(oauth_codes::id.eq(code_id))
+                .set(oauth_codes::used_at.eq(now))
+                .execute(db)
+                .await?;
+            Ok(Some((code_id, user_id, scope)))
+        }.scope_boxed()).await?;
+
+        let Some((code_id, user_id, scope)) = redeemed else {
+            // a replayed code may have been intercepted, the tokens issued for it are revoked (RFC 6749 section 4.1.2)
+            let replayed = oauth_codes::table
+                .select(oauth_codes::id)
+                .filter(oauth_codes::code_hash.eq(&code_hash))
+                .filter(oauth_codes::used_at.is_not_null())
+                .first::<uuid::Uuid>(self)
+                .await
+                .optional()
+                .map_err(|_| OAuthTokenError::InternalServerError)?;
+            if let Some(code_id) = replayed {
+                warn!("Authorization code reuse detected, revoking session {}", code_id);
+                end_session(self, code_id).await.map_err(|_| OAuthTokenError::InternalServerError)?;
+            }
+            return Err(OAuthTokenError::InvalidGrant);
+        };
+
+        // the code id is the session id, so a replayed code can find the tokens it produced
+        let scope = Permissions::from_bits(scope);
+        let token = issue_token(self, config, keys, user_id, code_id, Some(device), Some(ClientGrant { client_id, scope }))
+            .await
+            .map_err(|_| OAuthTokenError::InternalServerError)?;
+        record_auth_event(self, AuthEventKind::TokenIssued, Some(user_id), None, Some(device), Some("authorization_code")).await;
+        Ok(OAuthToken { token, scope: Some(scope_string(scope)) })
+    }
+
+    async fn refresh_client_token(&mut self, config: &AuthConfig, keys: &JwtKeys, request: &OAuthTokenRequest<'_>, device: &DeviceInfo) -> Result<OAuthToken, OAuthTokenError> {
+        use crate::models::RefreshError;
+
+        let refresh_token = request.refresh_token.ok_or(OAuthTokenError::InvalidRequest)?;
+        let client_id = authenticate_client(self, request.client_id, request.client_secret).await?;
+        let token = self.refresh(config, keys, refresh_token, Some(client_id), device)
+            .await
+            .map_err(|err| match err {
+                RefreshError::InternalServerError => OAuthTokenError::InternalServerError,
+                RefreshError::Unauthorized => OAuthTokenError::InvalidGrant,
+            })?;
+        // the scope is unchanged since the code exchange
+        Ok(OAuthToken { token, scope: None })
+    }
+
+    async fn revoke_client_token(&mut self, keys: &JwtKeys, request: &OAuthRevocationRequest<'_>) -> Result<(), OAuthTokenError> {
+        let token = request.token;
+        let client_id = authenticate_client(self, request.client_id, request.client_secret).await?;
+        let token_hash = hash_secret(token).map_err(|_| OAuthTokenError::InternalServerError)?;
+
+        let family_id = refresh_tokens::table
+            .select(refresh_tokens::family_id)
+            .filter(refresh_tokens::token_hash.eq(token_hash))
+            .filter(refresh_tokens::client_id.eq(client_id))
+            .first::<uuid::Uuid>(self)
+            .await
+            .optional()
+            .map_err(|_| OAuthTokenError::InternalServerError)?;
+        if let Some(family_id) = family_id {
+            end_session(self, family_id).await.map_err(|_| OAuthTokenError::InternalServerError)?;
+            return Ok(());
+        }
+
+        // unknown tokens and tokens of other clients are ignored, RFC 7009 answers them like a success
+        if let Ok(claims) = verify_login_token(keys, token) {
+            if claims.cid == Some(client_id) {
+                self.revoke_token(&claims).await.map_err(|_| OAuthTokenError::InternalServerError)?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn revoke_authorization(&mut self, user_id: uuid::Uuid, client_id: uuid::Uuid) -> Result<(), OAuthError> {
+        let revoked = diesel::update(refresh_tokens::table)
+            .filter(refresh_tokens::user_id.eq(user_id))
+            .filter(refresh_tokens::client_id.eq(client_id))
+            .filter(refresh_tokens::revoked.eq(false))
+            .set(refresh_tokens::revoked.eq(true))
+            .execute(self)
+            .await
+            .map_err(|_| OAuthError::InternalServerError)?;
+        if revoked == 0 {
+            return Err(OAuthError::NotFound);
+        }
+
+        diesel::update(login_sessions::table)
+            .filter(login_sessions::user_id.eq(user_id))
+            .filter(login_sessions::client_id.eq(client_id))
+            .filter(login_sessions::revoked_at.is_null())
+            .set(login_sessions::revoked_at.eq(chrono::Utc::now()))
+            .execute(self)
+            .await
+            .map_err(|_| OAuthError::InternalServerError)?;
+        Ok(())
+    }
+}
+
+// Returns the id and name of the client, the redirect URI is checked before anything else
+// so an error never sends the user to an address the client didn't register
+async fn validate_authorization_request(db: &mut rocket_db_pools::Connection<Db>, request: &AuthorizationRequest<'_>) -> Result<(uuid::Uuid, String), OAuthError> {
+    use std::str::FromStr;
+
+    let client_id = uuid::Uuid::from_str(request.client_id).map_err(|_| OAuthError::NotFound)?;
+    let (client_name, redirect_uris) = oauth_clients::table
+        .select((oauth_clients::name, oauth_clients::redirect_uris))
+        .filter(oauth_clients::id.eq(client_id))
+        .first::<(String, Vec<String>)>(db)
+        .await
+        .optional()
+        .map_err(|_| OAuthError::InternalServerError)?
+        .ok_or(OAuthError::NotFound)?;
+
+    if !redirect_uris.iter().any(|uri| uri == request.redirect_uri) {
+        return Err(OAuthError::InvalidRedirectUri);
+    }
+    if request.response_type != "code" {
+        return Err(OAuthError::UnsupportedResponseType);
+    }
+    // every client has to use PKCE, confidential ones included
+    if request.code_challenge_method != "S256" || !is_valid_pkce_value(request.code_challenge) {
+        return Err(OAuthError::InvalidCodeChallenge);
+    }
+    Ok((client_id, client_name))
+}
+
+// The requested scope limited to what the user holds, developer access is never delegated
+async fn granted_scope(db: &mut rocket_db_pools::Connection<Db>, user_id: uuid::Uuid, scope: &str) -> Result<Permissions, OAuthError> {
+    let requested = requested_scope(scope)?;
+    let (bits, developer) = users::table
+        .select((users::permissions, users::developer))
+        .filter(users::id.eq(user_id))
+        .first::<(i32, bool)>(db)
+        .await
+        .map_err(|_| OAuthError::InternalServerError)?;
+    Ok(requested.intersection(user_permissions(bits, developer)))
+}
+
+fn requested_scope(scope: &str) -> Result<Permissions, OAuthError> {
+    use rocket::request::FromParam;
+
+    let requested = scope.split(' ')
+        .filter(|name| !name.is_empty())
+        .map(Permission::from_param)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| OAuthError::InvalidScope)?;
+    if requested.is_empty() || requested.contains(&Permission::Developer) {
+        return Err(OAuthError::InvalidScope);
+    }
+    Ok(requested.into_iter().collect())
+}
+
+// Public clients send no secret, their codes are bound by PKCE instead
+async fn authenticate_client(db: &mut rocket_db_pools::Connection<Db>, client_id: Option<&str>, client_secret: Option<&str>) -> Result<uuid::Uuid, OAuthTokenError> {
+    use std::str::FromStr;
+
+    let client_id = client_id.ok_or(OAuthTokenError::InvalidRequest)?;
+    let client_id = uuid::Uuid::from_str(client_id).map_err(|_| OAuthTokenError::InvalidClient)?;
+    let secret_hash = oauth_clients::table
+        .select(oauth_clients::secret_hash)
+        .filter(oauth_clients::id.eq(client_id))
+        .first::<Option<Vec<u8>>>(db)
+        .await
+        .optional()
+        .map_err(|_| OAuthTokenError::InternalServerError)?
+        .ok_or(OAuthTokenError::InvalidClient)?;
+
+    if let Some(secret_hash) = secret_hash {
+        let client_secret = client_secret.ok_or(OAuthTokenError::InvalidClient)?;
+        let given_hash = hash_secret(client_secret).map_err(|_| OAuthTokenError::InternalServerError)?;
+        if !openssl::memcmp::eq(&secret_hash, &given_hash) {
+            return Err(OAuthTokenError::InvalidClient);
+        }
+    }
+    Ok(client_id)
+}
+
+fn is_valid_redirect_uri(uri: &str) -> bool {
+    // custom schemes are fine, native apps register those
+    !uri.contains('#') && rocket::http::uri::Absolute::parse(uri).is_ok()
+}
+
+// Code verifiers and S256 challenges share the RFC 7636 charset and length
+fn is_valid_pkce_value(value: &str) -> bool {
+    (43..=128).contains(&value.len())
+        && value.bytes().all(|byte| byte.is_ascii_alphanumeric() || b"-._~".contains(&byte))
+}
+
+// S256, the only method spiritbox accepts and uses towards other providers
+pub(crate) fn code_challenge(code_verifier: &str) -> Result<String, ()> {
+    use base64::Engine;
+
+    let digest = openssl::hash::hash(openssl::hash::MessageDigest::sha256(), code_verifier.as_bytes()).map_err(|_| ())?;
+    Ok(base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(digest))
+}
+
+pub(crate) fn verify_code_challenge(code_challenge: &str, code_verifier: &str) -> bool {
+    if !is_valid_pkce_value(code_verifier) {
+        return false;
+    }
+    let Ok(expected) = self::code_challenge(code_verifier) else {
+        return false;
+    };
+    expected.len() == code_challenge.len() && openssl::memcmp::eq(expected.as_bytes(), code_challenge.as_bytes())
+}
+
+fn scope_string(scope: Permissions) -> String {
+    scope.granted()
+        .iter()
+        .map(|permission| permission.name())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 7636 appendix B
+    const VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+    const CHALLENGE: &str = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM";
+
+    #[test]
+    fn s256_challenges_match_their_verifier() {
+        assert_eq!(code_challenge(VERIFIER).unwrap(), CHALLENGE);
+        assert!(verify_code_challenge(CHALLENGE, VERIFIER));
+        assert!(!verify_code_challenge(CHALLENGE, "eBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"));
+        // `plain` isn't accepted, the challenge itself is no verifier
+        assert!(!verify_code_challenge(CHALLENGE, CHALLENGE));
+    }
+
+    #[test]
+    fn verifiers_outside_the_rfc_charset_and_length_are_rejected() {
+        let short = &VERIFIER[..42];
+        assert!(!verify_code_challenge(&code_challenge(short).unwrap(), short));
+        let long = "a".repeat(129);
+        assert!(!verify_code_challenge(&code_challenge(&long).unwrap(), &long));
+        let spaced = format!("{} ", &VERIFIER[..42]);
+        assert!(!verify_code_challenge(&code_challenge(&spaced).unwrap(), &spaced));
+    }
+
+    #[test]
+    fn scopes_are_capped_by_the_user_permissions() {
+        let Ok(requested) = requested_scope("get_channels  see_messages") else {
+            panic!("valid scope rejected");
+        };
+        let user = Permissions::from_bits(0).with(Permission::GetChannels).with(Permission::SeeOtherUsers);
+        assert_eq!(requested.intersection(user).granted(), vec![Permission::GetChannels]);
+
+        assert!(matches!(requested_scope(""), Err(OAuthError::InvalidScope)));
+        assert!(matches!(requested_scope("developer get_channels"), Err(OAuthError::InvalidScope)));
+        assert!(matches!(requested_scope("get_channels everything"), Err(OAuthError::InvalidScope)));
+    }
+}
@@ -0,0 +1,183 @@
+use rocket_db_pools::diesel::prelude::*;
+
+use crate::database::audit::record_auth_event;
+use crate::database::auth::{issue_token, JwtKeys, NAME_SKELETON_CONSTRAINT};
+use crate::database::{hash_secret, random_token, Db};
+use crate::models::{AuthConfig, AuthEventKind, DeviceInfo, OidcError, PolicyViolation, Token};
+use crate::oidc::OidcIdentity;
+use crate::policy::{username_skeleton, RegistrationPolicy};
+use crate::schema::{oidc_identities, oidc_logins, users};
+
+// attempts at finding a free name for a new account before giving up
+const MAX_USERNAME_ATTEMPTS: usize = 5;
+
+// Secrets of a login that has been sent to the identity provider
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct OidcLogin {
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+}
+
+pub(crate) trait OidcDatabase {
+    async fn create_oidc_login(&mut self, config: &AuthConfig, provider: &str) -> Result<OidcLogin, OidcError>;
+    // a state can be used once, for the provider it was created for
+    async fn take_oidc_login(&mut self, provider: &str, state: &str) -> Result<OidcLogin, OidcError>;
+    // creates the account on the first login of the identity
+    async fn oidc_login(&mut self, config: &AuthConfig, policy: &RegistrationPolicy, keys: &JwtKeys, provider: &str, identity: &OidcIdentity, device: &DeviceInfo) -> Result<Token, OidcError>;
+}
+
+impl OidcDatabase for rocket_db_pools::Connection<Db> {
+    async fn create_oidc_login(&mut self, config: &AuthConfig, provider: &str) -> Result<OidcLogin, OidcError> {
+        let login = OidcLogin {
+            state: random_token().map_err(|_| OidcError::InternalServerError)?,
+            nonce: random_token().map_err(|_| OidcError::InternalServerError)?,
+            code_verifier: random_token().map_err(|_| OidcError::InternalServerError)?,
+        };
+
+        let now = chrono::Utc::now();
+        let expires_at = now + chrono::Duration::try_seconds(config.oidc_login_ttl.into()).ok_or(OidcError::InternalServerError)?;
+
+        // logins the user never came back from
+        diesel::delete(oidc_logins::table)
+            .filter(oidc_logins::expires_at.lt(now))
+            .execute(self)
+            .await
+            .map_err(|_| OidcError::InternalServerError)?;
+
+        diesel::insert_into(oidc_logins::table)
+            .values((
+                oidc_logins::state_hash.eq(hash_secret(&login.state).map_err(|_| OidcError::InternalServerError)?),
+                oidc_logins::provider.eq(provider),
+                oidc_logins::nonce.eq(&login.nonce),
+                oidc_logins::code_verifier.eq(&login.code_verifier),
+                oidc_logins::expires_at.eq(expires_at),
+            ))
+            .execute(self)
+            .await
+            .map_err(|_| OidcError::InternalServerError)?;
+        Ok(login)
+    }
+
+    async fn take_oidc_login(&mut self, provider: &str, state: &str) -> Result<OidcLogin, OidcError> {
+        let (nonce, code_verifier) = diesel::delete(oidc_logins::table)
+            .filter(oidc_logins::state_hash.eq(hash_secret(state).map_err(|_| OidcError::InternalServerError)?))
+            .filter(oidc_logins::provider.eq(provider))
+            .filter(oidc_logins::expires_at.gt(chrono::Utc::now()))
+            .returning((oidc_logins::nonce, oidc_logins::code_verifier))
+            .get_result::<(String, String)>(self)
+            .await
+            .optional()
+            .map_err(|_| OidcError::InternalServerError)?
+            .ok_or(OidcError::InvalidState)?;
+
+        Ok(OidcLogin { state: state.to_string(), nonce, code_verifier })
+    }
+
+    // The provider already checked the credentials and its own second factor, spiritbox's TOTP is not asked for
+    async fn oidc_login(&mut self, config: &AuthConfig, policy: &RegistrationPolicy, keys: &JwtKeys, provider: &str, identity: &OidcIdentity, device: &DeviceInfo) -> Result<Token, OidcError> {
+        // the provider is the method in the audit log
+        let method = format!("oidc:{}", provider);
+        let user_id = match find_identity(self, provider, &identity.subject).await? {
+            Some(user_id) => user_id,
+            None if config.registration.invite_only => return Err(OidcError::RegistrationClosed),
+            None => {
+                let user_id = provision_user(self, policy, provider, identity).await?;
+                record_auth_event(self, AuthEventKind::Registered, Some(user_id), None, Some(device), Some(method.as_str())).await;
+                user_id
+            }
+        };
+
+        record_auth_event(self, AuthEventKind::LoginSucceeded, Some(user_id), None, Some(device), Some(method.as_str())).await;
+        let token = issue_token(self, config, keys, user_id, uuid::Uuid::new_v4(), Some(device), None)
+            .await
+            .map_err(|_| OidcError::InternalServerError)?;
+        record_auth_event(self, AuthEventKind::TokenIssued, Some(user_id), None, Some(device), Some(method.as_str())).await;
+        Ok(token)
+    }
+}
+
+async fn find_identity(db: &mut rocket_db_pools::Connection<Db>, provider: &str, subject: &str) -> Result<Option<uuid::Uuid>, OidcError> {
+    oidc_identities::table
+        .select(oidc_identities::user_id)
+        .filter(oidc_identities::provider.eq(provider))
+        .filter(oidc_identities::subject.eq(subject))
+        .first::<uuid::Uuid>(db)
+        .await
+        .optional()
+        .map_err(|_| OidcError::InternalServerError)
+}
+
+// The account has no password, it can only be reached through the provider.
+// Existing accounts are never linked by name, whoever registers a name at the provider would take them over.
+// A name the username policy rejects is replaced by the provider's, names with a random suffix are tried when it's taken.
+async fn provision_user(db: &mut rocket_db_pools::Connection<Db>, policy: &RegistrationPolicy, provider: &str, identity: &OidcIdentity) -> Result<uuid::Uuid, OidcError> {
+    let base_name = identity.username.as_deref()
+        .and_then(|name| policy.check_username(name.trim()).ok())
+        .unwrap_or_else(|| provider.to_string());
+    let max_length = policy.max_username_length();
+
+    let mut user_id = None;
+    let mut confusable = false;
+    for attempt in 0..MAX_USERNAME_ATTEMPTS {
+        let name = if attempt == 0 {
+            base_name.chars().take(max_length).collect::<String>()
+        } else {
+            let mut suffix = [0; 3];
+            openssl::rand::rand_bytes(&mut suffix).map_err(|_| OidcError::InternalServerError)?;
+            let suffix = suffix.iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+            format!("{}-{}", base_name.chars().take(max_length.saturating_sub(1 + suffix.len())).collect::<String>(), suffix)
+        };
+        // e.g. a provider name that is reserved or too short, the suffixed names may still pass
+        let Ok(name) = policy.check_username(&name) else {
+            continue;
+        };
+
+        let inserted = diesel::insert_into(users::table)
+            .values((users::name.eq(&name), users::name_skeleton.eq(username_skeleton(&name))))
+            .returning(users::id)
+            .get_result::<uuid::Uuid>(db)
+            .await;
+        match inserted {
+            Ok(id) => {
+                user_id = Some(id);
+                break;
+            }
+            // a name that only looks like a taken one is tried with a suffix as well
+            Err(diesel::result::Error::DatabaseError(diesel::result::DatabaseErrorKind::UniqueViolation, info)) => {
+                confusable = info.constraint_name() == Some(NAME_SKELETON_CONSTRAINT);
+            }
+            Err(_) => return Err(OidcError::InternalServerError),
+        }
+    }
+    let Some(user_id) = user_id else {
+        warn!("Found no name the username policy accepts for subject {} of identity provider {}", identity.subject, provider);
+        if confusable {
+            return Err(OidcError::Rejected(vec![PolicyViolation::UsernameConfusable]));
+        }
+        return Err(OidcError::InternalServerError);
+    };
+
+    let linked = diesel::insert_into(oidc_identities::table)
+        .values((
+            oidc_identities::provider.eq(provider),
+            oidc_identities::subject.eq(&identity.subject),
+            oidc_identities::user_id.eq(user_id),
+        ))
+        .on_conflict_do_nothing()
+        .execute(db)
+        .await
+        .map_err(|_| OidcError::InternalServerError)?;
+    if linked == 1 {
+        info!("Provisioned user {} for subject {} of identity provider {}", user_id, identity.subject, provider);
+        return Ok(user_id);
+    }
+
+    // a concurrent first login of the same identity won, its account is the one to use
+    diesel::delete(users::table)
+        .filter(users::id.eq(user_id))
+        .execute(db)
+        .await
+        .map_err(|_| OidcError::InternalServerError)?;
+    find_identity(db, provider, &identity.subject).await?.ok_or(OidcError::InternalServerError)
+}
@@ -0,0 +1,280 @@
+use rocket_db_pools::diesel::prelude::*;
+
+use crate::database::audit::record_auth_event;
+use crate::database::auth::{issue_login_token, JwtKeys};
+use crate::database::Db;
+use crate::models::{AuthConfig, AuthEventKind, DeviceInfo, Passkey, PasskeyError, PasskeyLoginOptions, PasskeyLoginRequest, PasskeyRegistrationOptions, PasskeyRegistrationRequest, Token, WebAuthnConfig};
+use crate::schema::{passkey_challenges, passkeys, users};
+use crate::webauthn::{self, CeremonyError, SUPPORTED_ALGORITHMS};
+
+pub(crate) trait PasskeyDatabase {
+    async fn start_passkey_registration(&mut self, config: &AuthConfig, user_id: uuid::Uuid) -> Result<PasskeyRegistrationOptions, PasskeyError>;
+    async fn finish_passkey_registration(&mut self, config: &AuthConfig, user_id: uuid::Uuid, request: &PasskeyRegistrationRequest<'_>) -> Result<Passkey, PasskeyError>;
+    async fn get_passkeys(&mut self, user_id: uuid::Uuid) -> Result<Vec<Passkey>, PasskeyError>;
+    async fn remove_passkey(&mut self, user_id: uuid::Uuid, id: uuid::Uuid) -> Result<(), PasskeyError>;
+    async fn start_passkey_login(&mut self, config: &AuthConfig, login: Option<&str>) -> Result<PasskeyLoginOptions, PasskeyError>;
+    // Skips 2FA like key login, with user verification the passkey is two factors on its own
+    async fn passkey_login(&mut self, config: &AuthConfig, keys: &JwtKeys, request: &PasskeyLoginRequest<'_>, device: &DeviceInfo) -> Result<Token, PasskeyError>;
+}
+
+impl PasskeyDatabase for rocket_db_pools::Connection<Db> {
+    async fn start_passkey_registration(&mut self, config: &AuthConfig, user_id: uuid::Uuid) -> Result<PasskeyRegistrationOptions, PasskeyError> {
+        let user_name = users::table
+            .select(users::name)
+            .filter(users::id.eq(user_id))
+            .first::<String>(self)
+            .await
+            .map_err(|_| PasskeyError::InternalServerError)?;
+        let exclude_credentials = credential_ids(self, user_id).await?;
+
+        let (challenge_id, challenge) = create_challenge(self, &config.webauthn, Some(user_id), true).await?;
+        Ok(PasskeyRegistrationOptions {
+            challenge_id,
+            challenge: encode(&challenge),
+            rp_id: config.webauthn.rp_id.clone(),
+            rp_name: config.webauthn.rp_name.clone(),
+            user_id: encode(user_id.as_bytes()),
+            user_name,
+            algorithms: SUPPORTED_ALGORITHMS.to_vec(),
+            exclude_credentials,
+            user_verification: user_verification(&config.webauthn),
+            expires_in: config.webauthn.challenge_ttl,
+        })
+    }
+
+    async fn finish_passkey_registration(&mut self, config: &AuthConfig, user_id: uuid::Uuid, request: &PasskeyRegistrationRequest<'_>) -> Result<Passkey, PasskeyError> {
+        if request.name.is_empty() || request.name.chars().count() > 64 {
+            return Err(PasskeyError::InvalidName);
+        }
+        let (challenge, challenge_user) = take_challenge(self, request.challenge_id, true).await?;
+        if challenge_user != Some(user_id) {
+            return Err(PasskeyError::InvalidChallenge);
+        }
+
+        let credential = webauthn::verify_registration(
+            &config.webauthn,
+            &challenge,
+            &decode(request.credential_id).ok_or(PasskeyError::InvalidCredential)?,
+            &decode(request.client_data_json).ok_or(PasskeyError::InvalidCredential)?,
+            &decode(request.attestation_object).ok_or(PasskeyError::InvalidCredential)?,
+        ).map_err(|err| match err {
+            CeremonyError::Invalid => PasskeyError::InvalidCredential,
+            CeremonyError::UnsupportedAlgorithm => PasskeyError::UnsupportedAlgorithm,
+        })?;
+
+        let (id, created_at) = diesel::insert_into(passkeys::table)
+            .values((
+                passkeys::user_id.eq(user_id),
+                passkeys::credential_id.eq(&credential.credential_id),
+                passkeys::public_key.eq(&credential.public_key),
+                passkeys::algorithm.eq(credential.algorithm),
+                passkeys::sign_count.eq(i64::from(credential.sign_count)),
+                passkeys::name.eq(request.name),
+            ))
+            .returning((passkeys::id, passkeys::created_at))
+            .get_result::<(uuid::Uuid, chrono::DateTime<chrono::Utc>)>(self)
+            .await
+            .map_err(|err| match err {
+                diesel::result::Error::DatabaseError(diesel::result::DatabaseErrorKind::UniqueViolation, _) => PasskeyError::Conflict,
+                _ => PasskeyError::InternalServerError,
+            })?;
+
+        Ok(Passkey {
+            id,
+            name: request.name.to_string(),
+            created_at,
+            last_used_at: None,
+        })
+    }
+
+    async fn get_passkeys(&mut self, user_id: uuid::Uuid) -> Result<Vec<Passkey>, PasskeyError> {
+        let passkeys = passkeys::table
+            .select((passkeys::id, passkeys::name, passkeys::created_at, passkeys::last_used_at))
+            .filter(passkeys::user_id.eq(user_id))
+            .order(passkeys::created_at.asc())
+            .load::<(uuid::Uuid, String, chrono::DateTime<chrono::Utc>, Option<chrono::DateTime<chrono::Utc>>)>(self)
+            .await
+            .map_err(|_| PasskeyError::InternalServerError)?;
+
+        Ok(passkeys.into_iter()
+            .map(|(id, name, created_at, last_used_at)| Passkey { id, name, created_at, last_used_at })
+            .collect())
+    }
+
+    async fn remove_passkey(&mut self, user_id: uuid::Uuid, id: uuid::Uuid) -> Result<(), PasskeyError> {
+        let removed = diesel::delete(passkeys::table)
+            .filter(passkeys::id.eq(id))
+            .filter(passkeys::user_id.eq(user_id))
+            .execute(self)
+            .await
+            .map_err(|_| PasskeyError::InternalServerError)?;
+        if removed == 0 {
+            return Err(PasskeyError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn start_passkey_login(&mut self, config: &AuthConfig, login: Option<&str>) -> Result<PasskeyLoginOptions, PasskeyError> {
+        let user_id = match login {
+            Some(login) => users::table
+                .select(users::id)
+                .filter(users::name.eq(login))
+                .first::<uuid::Uuid>(self)
+                .await
+                .optional()
+                .map_err(|_| PasskeyError::InternalServerError)?,
+            None => None,
+        };
+        // an unknown name gets an unbound challenge and no credentials, the same as a user without passkeys
+        let allow_credentials = match user_id {
+            Some(user_id) => credential_ids(self, user_id).await?,
+            None => Vec::new(),
+        };
+
+        let (challenge_id, challenge) = create_challenge(self, &config.webauthn, user_id, false).await?;
+        Ok(PasskeyLoginOptions {
+            challenge_id,
+            challenge: encode(&challenge),
+            rp_id: config.webauthn.rp_id.clone(),
+            allow_credentials,
+            user_verification: user_verification(&config.webauthn),
+            expires_in: config.webauthn.challenge_ttl,
+        })
+    }
+
+    async fn passkey_login(&mut self, config: &AuthConfig, keys: &JwtKeys, request: &PasskeyLoginRequest<'_>, device: &DeviceInfo) -> Result<Token, PasskeyError> {
+        let (challenge, challenge_user) = take_challenge(self, request.challenge_id, false).await?;
+        let credential_id = decode(request.credential_id).ok_or(PasskeyError::Unauthorized)?;
+
+        let passkey = passkeys::table
+            .inner_join(users::table)
+            .select((passkeys::id, passkeys::user_id, users::name, passkeys::public_key, passkeys::algorithm, passkeys::sign_count))
+            .filter(passkeys::credential_id.eq(&credential_id))
+            .filter(users::bot.eq(false))
+            .first::<(uuid::Uuid, uuid::Uuid, String, Vec<u8>, i32, i64)>(self)
+            .await
+            .optional()
+            .map_err(|_| PasskeyError::InternalServerError)?;
+        let Some((id, user_id, login, public_key, algorithm, sign_count)) = passkey else {
+            record_auth_event(self, AuthEventKind::LoginFailed, None, None, Some(device), Some("passkey")).await;
+            return Err(PasskeyError::Unauthorized);
+        };
+
+        let handle_matches = match request.user_handle {
+            Some(user_handle) => decode(user_handle).is_some_and(|user_handle| user_handle == user_id.as_bytes()),
+            None => true,
+        };
+        // a challenge asked for a named user only signs that user in
+        let new_count = if challenge_user.unwrap_or(user_id) == user_id && handle_matches {
+            webauthn::verify_assertion(
+                &config.webauthn,
+                &challenge,
+                &public_key,
+                algorithm,
+                &decode(request.client_data_json).unwrap_or_default(),
+                &decode(request.authenticator_data).unwrap_or_default(),
+                &decode(request.signature).unwrap_or_default(),
+            ).ok()
+        } else {
+            None
+        };
+        let Some(new_count) = new_count else {
+            record_auth_event(self, AuthEventKind::LoginFailed, Some(user_id), Some(&login), Some(device), Some("passkey")).await;
+            return Err(PasskeyError::Unauthorized);
+        };
+
+        // the update only goes through against the count that was checked
+        let counter_moved = webauthn::sign_count_advanced(sign_count, new_count);
+        let new_count = i64::from(new_count);
+        let updated = if counter_moved {
+            diesel::update(passkeys::table)
+                .filter(passkeys::id.eq(id))
+                .filter(passkeys::sign_count.eq(sign_count))
+                .set((
+                    passkeys::sign_count.eq(new_count),
+                    passkeys::last_used_at.eq(chrono::Utc::now()),
+                ))
+                .execute(self)
+                .await
+                .map_err(|_| PasskeyError::InternalServerError)?
+        } else {
+            0
+        };
+        if updated == 0 {
+            warn!("Signature counter of passkey {} went from {} to {}, it may have been cloned", id, sign_count, new_count);
+            record_auth_event(self, AuthEventKind::LoginFailed, Some(user_id), Some(&login), Some(device), Some("passkey_counter")).await;
+            return Err(PasskeyError::Unauthorized);
+        }
+
+        record_auth_event(self, AuthEventKind::LoginSucceeded, Some(user_id), Some(&login), Some(device), Some("passkey")).await;
+        issue_login_token(self, config, keys, user_id, &login, device, "passkey")
+            .await
+            .map_err(|_| PasskeyError::InternalServerError)
+    }
+}
+
+async fn credential_ids(db: &mut rocket_db_pools::Connection<Db>, user_id: uuid::Uuid) -> Result<Vec<String>, PasskeyError> {
+    let credential_ids = passkeys::table
+        .select(passkeys::credential_id)
+        .filter(passkeys::user_id.eq(user_id))
+        .load::<Vec<u8>>(db)
+        .await
+        .map_err(|_| PasskeyError::InternalServerError)?;
+    Ok(credential_ids.iter().map(|credential_id| encode(credential_id)).collect())
+}
+
+async fn create_challenge(db: &mut rocket_db_pools::Connection<Db>, config: &WebAuthnConfig, user_id: Option<uuid::Uuid>, registration: bool) -> Result<(uuid::Uuid, Vec<u8>), PasskeyError> {
+    // abandoned ceremonies are cleaned up whenever a new one starts
+    diesel::delete(passkey_challenges::table)
+        .filter(passkey_challenges::expires_at.lt(chrono::Utc::now()))
+        .execute(db)
+        .await
+        .map_err(|_| PasskeyError::InternalServerError)?;
+
+    let mut challenge = vec![0; 32];
+    openssl::rand::rand_bytes(&mut challenge).map_err(|_| PasskeyError::InternalServerError)?;
+    let expires_at = chrono::Utc::now() + chrono::Duration::try_seconds(config.challenge_ttl.into()).ok_or(PasskeyError::InternalServerError)?;
+
+    let id = diesel::insert_into(passkey_challenges::table)
+        .values((
+            passkey_challenges::user_id.eq(user_id),
+            passkey_challenges::challenge.eq(&challenge),
+            passkey_challenges::registration.eq(registration),
+            passkey_challenges::expires_at.eq(expires_at),
+        ))
+        .returning(passkey_challenges::id)
+        .get_result::<uuid::Uuid>(db)
+        .await
+        .map_err(|_| PasskeyError::InternalServerError)?;
+    Ok((id, challenge))
+}
+
+// A challenge answers exactly one ceremony, it is gone whether the response checks out or not
+async fn take_challenge(db: &mut rocket_db_pools::Connection<Db>, id: uuid::Uuid, registration: bool) -> Result<(Vec<u8>, Option<uuid::Uuid>), PasskeyError> {
+    diesel::delete(passkey_challenges::table)
+        .filter(passkey_challenges::id.eq(id))
+        .filter(passkey_challenges::registration.eq(registration))
+        .filter(passkey_challenges::expires_at.gt(chrono::Utc::now()))
+        .returning((passkey_challenges::challenge, passkey_challenges::user_id))
+        .get_result::<(Vec<u8>, Option<uuid::Uuid>)>(db)
+        .await
+        .map_err(|err| match err {
+            diesel::result::Error::NotFound => PasskeyError::InvalidChallenge,
+            _ => PasskeyError::InternalServerError,
+        })
+}
+
+fn user_verification(config: &WebAuthnConfig) -> &'static str {
+    if config.user_verification { "required" } else { "preferred" }
+}
+
+fn encode(bytes: &[u8]) -> String {
+    use base64::Engine;
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+// browsers hand out base64url, padded or not
+fn decode(value: &str) -> Option<Vec<u8>> {
+    use base64::Engine;
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(value.trim_end_matches('=')).ok()
+}
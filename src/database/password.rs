@@ -0,0 +1,151 @@
+use diesel::result::Error;
+use rocket_db_pools::diesel::prelude::*;
+use rocket_db_pools::diesel::scoped_futures::ScopedFutureExt;
+use rocket_db_pools::diesel::AsyncPgConnection;
+
+use crate::database::auth::{check_reauthentication, hash_password, reauthentication_failed, revoke_all_tokens, verify_password};
+use crate::database::{hash_secret, random_token, Db};
+use crate::models::{AuthConfig, DeviceInfo, PasswordAlgorithm, PasswordError};
+use crate::schema::{password_resets, secrets, users};
+
+pub(crate) trait PasswordDatabase {
+    // wrong old passwords count towards the username lockout
+    async fn change_password(&mut self, config: &AuthConfig, user_id: uuid::Uuid, old_password: &str, new_password: &str, device: &DeviceInfo) -> Result<(), PasswordError>;
+    // None when there is no such user or one was sent within the cooldown, callers must not reveal that
+    async fn create_password_reset(&mut self, config: &AuthConfig, login: &str) -> Result<Option<(uuid::Uuid, String)>, PasswordError>;
+    async fn reset_password(&mut self, config: &AuthConfig, reset_token: &str, new_password: &str) -> Result<(), PasswordError>;
+}
+
+impl PasswordDatabase for rocket_db_pools::Connection<Db> {
+    async fn change_password(&mut self, config: &AuthConfig, user_id: uuid::Uuid, old_password: &str, new_password: &str, device: &DeviceInfo) -> Result<(), PasswordError> {
+        let login = check_reauthentication(self, user_id, device).await?;
+
+        // accounts made through an identity provider, a passkey or a login link get their first password from a reset
+        let (salted_hash, algorithm) = secrets::table
+            .select((secrets::salted_hash, secrets::algorithm))
+            .filter(secrets::user_id.eq(user_id))
+            .first::<(Vec<u8>, PasswordAlgorithm)>(self)
+            .await
+            .optional()
+            .map_err(|_| PasswordError::InternalServerError)?
+            .ok_or(PasswordError::NoPassword)?;
+
+        if !verify_password(algorithm, &salted_hash, old_password.as_bytes()).await.map_err(|_| PasswordError::InternalServerError)? {
+            reauthentication_failed(self, config, user_id, &login, device, "password_change").await;
+            return Err(PasswordError::Unauthorized);
+        }
+
+        let salted_hash = hash_password(&config.argon2, new_password.as_bytes()).await.map_err(|_| PasswordError::InternalServerError)?;
+        self.transaction::<_, PasswordError, _>(|db| async move {
+            store_password(db, user_id, salted_hash).await?;
+            Ok(())
+        }.scope_boxed()).await
+    }
+
+    async fn create_password_reset(&mut self, config: &AuthConfig, login: &str) -> Result<Option<(uuid::Uuid, String)>, PasswordError> {
+        let user_id = users::table
+            .select(users::id)
+            .filter(users::name.eq(login))
+            .filter(users::bot.eq(false))
+            .first::<uuid::Uuid>(self)
+            .await
+            .optional()
+            .map_err(|_| PasswordError::InternalServerError)?;
+        let Some(user_id) = user_id else {
+            return Ok(None);
+        };
+
+        // anyone can ask for a reset of any account, this keeps them from flooding its inbox
+        // and from replacing the token the user is about to use
+        let now = chrono::Utc::now();
+        let cooldown = chrono::Duration::try_seconds(config.password_reset_cooldown.into()).ok_or(PasswordError::InternalServerError)?;
+        let recently_sent = diesel::select(diesel::dsl::exists(
+            password_resets::table
+                .filter(password_resets::user_id.eq(user_id))
+                .filter(password_resets::created_at.gt(now - cooldown))
+        ))
+            .get_result::<bool>(self)
+            .await
+            .map_err(|_| PasswordError::InternalServerError)?;
+        if recently_sent {
+            return Ok(None);
+        }
+
+        let reset_token = random_token().map_err(|_| PasswordError::InternalServerError)?;
+
+        let expires_at = now
+            + chrono::Duration::try_seconds(config.password_reset_ttl.into()).ok_or(PasswordError::InternalServerError)?;
+
+        // only the latest requested token stays valid
+        diesel::delete(password_resets::table)
+            .filter(password_resets::user_id.eq(user_id))
+            .execute(self)
+            .await
+            .map_err(|_| PasswordError::InternalServerError)?;
+
+        diesel::insert_into(password_resets::table)
+            .values((
+                password_resets::user_id.eq(user_id),
+                password_resets::token_hash.eq(hash_secret(&reset_token).map_err(|_| PasswordError::InternalServerError)?),
+                password_resets::created_at.eq(now),
+                password_resets::expires_at.eq(expires_at),
+            ))
+            .execute(self)
+            .await
+            .map_err(|_| PasswordError::InternalServerError)?;
+
+        Ok(Some((user_id, reset_token)))
+    }
+
+    async fn reset_password(&mut self, config: &AuthConfig, reset_token: &str, new_password: &str) -> Result<(), PasswordError> {
+        let token_hash = hash_secret(reset_token).map_err(|_| PasswordError::InternalServerError)?;
+        // hashed up front, the transaction below shouldn't stay open for it
+        let salted_hash = hash_password(&config.argon2, new_password.as_bytes()).await.map_err(|_| PasswordError::InternalServerError)?;
+
+        // the token stays unused unless the new password is stored and the old tokens are revoked with it
+        self.transaction::<_, PasswordError, _>(|db| async move {
+            // marking the token used is the check itself, so it can't be redeemed twice concurrently
+            let user_id = diesel::update(password_resets::table)
+                .filter(password_resets::token_hash.eq(token_hash))
+                .filter(password_resets::used_at.is_null())
+                .filter(password_resets::expires_at.gt(chrono::Utc::now()))
+                .set(password_resets::used_at.eq(chrono::Utc::now()))
+                .returning(password_resets::user_id)
+                .get_result::<uuid::Uuid>(db)
+                .await
+                .optional()?
+                .ok_or(PasswordError::InvalidToken)?;
+
+            store_password(db, user_id, salted_hash).await?;
+            Ok(())
+        }.scope_boxed()).await
+    }
+}
+
+// Stores the new hash, or the first one for accounts without a password, and logs the user out everywhere;
+// pending reset tokens are dropped as well. Meant to run inside a transaction with whatever allowed the change
+async fn store_password(db: &mut AsyncPgConnection, user_id: uuid::Uuid, salted_hash: Vec<u8>) -> Result<(), Error> {
+    diesel::insert_into(secrets::table)
+        .values((
+            secrets::user_id.eq(user_id),
+            secrets::salted_hash.eq(&salted_hash),
+            secrets::algorithm.eq(PasswordAlgorithm::Argon2id),
+        ))
+        .on_conflict(secrets::user_id)
+        .do_update()
+        .set((
+            secrets::salted_hash.eq(&salted_hash),
+            secrets::algorithm.eq(PasswordAlgorithm::Argon2id),
+        ))
+        .execute(db)
+        .await?;
+
+    revoke_all_tokens(db, user_id).await?;
+
+    diesel::delete(password_resets::table)
+        .filter(password_resets::user_id.eq(user_id))
+        .filter(password_resets::used_at.is_null())
+        .execute(db)
+        .await?;
+    Ok(())
+}
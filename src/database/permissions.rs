@@ -0,0 +1,89 @@
+use diesel::sql_types::Integer;
+use diesel::IntoSql;
+use rocket_db_pools::diesel::prelude::*;
+
+use crate::database::Db;
+use crate::models::{Permission, PermissionError, Permissions};
+use crate::schema::users;
+
+diesel::infix_operator!(BitOr, " | ", Integer, backend: diesel::pg::Pg);
+diesel::infix_operator!(BitAnd, " & ", Integer, backend: diesel::pg::Pg);
+
+pub(crate) trait PermissionDatabase {
+    async fn get_permissions(&mut self, user_id: uuid::Uuid) -> Result<Permissions, PermissionError>;
+    async fn grant_permission(&mut self, user_id: uuid::Uuid, permission: Permission) -> Result<(), PermissionError>;
+    // access tokens carrying the permission stop working, refreshing gets one without it
+    async fn revoke_permission(&mut self, user_id: uuid::Uuid, permission: Permission) -> Result<(), PermissionError>;
+}
+
+impl PermissionDatabase for rocket_db_pools::Connection<Db> {
+    async fn get_permissions(&mut self, user_id: uuid::Uuid) -> Result<Permissions, PermissionError> {
+        users::table
+            .select((users::permissions, users::developer))
+            .filter(users::id.eq(user_id))
+            .first::<(i32, bool)>(self)
+            .await
+            .map(|(bits, developer)| user_permissions(bits, developer))
+            .map_err(|err| match err {
+                diesel::result::Error::NotFound => PermissionError::NotFound,
+                _ => PermissionError::InternalServerError,
+            })
+    }
+
+    async fn grant_permission(&mut self, user_id: uuid::Uuid, permission: Permission) -> Result<(), PermissionError> {
+        let query = diesel::update(users::table).filter(users::id.eq(user_id));
+        let updated = if permission == Permission::Developer {
+            query.set(users::developer.eq(true)).execute(self).await
+        } else {
+            let bit = Permissions::from_bits(0).with(permission).bits();
+            query.set(users::permissions.eq(BitOr::new(users::permissions, bit.into_sql::<Integer>())))
+                .execute(self)
+                .await
+        }.map_err(|_| PermissionError::InternalServerError)?;
+
+        if updated == 0 {
+            return Err(PermissionError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn revoke_permission(&mut self, user_id: uuid::Uuid, permission: Permission) -> Result<(), PermissionError> {
+        let query = diesel::update(users::table).filter(users::id.eq(user_id));
+        let generation = users::token_generation.eq(users::token_generation + 1);
+        let updated = if permission == Permission::Developer {
+            query.set((users::developer.eq(false), generation)).execute(self).await
+        } else {
+            let mask = Permissions::from_bits(!0).without(permission).bits();
+            query.set((users::permissions.eq(BitAnd::new(users::permissions, mask.into_sql::<Integer>())), generation))
+                .execute(self)
+                .await
+        }.map_err(|_| PermissionError::InternalServerError)?;
+
+        if updated == 0 {
+            return Err(PermissionError::NotFound);
+        }
+        Ok(())
+    }
+}
+
+// The stored developer bit is ignored, users.developer is the only source of truth for it
+pub(crate) fn user_permissions(bits: i32, developer: bool) -> Permissions {
+    let permissions = Permissions::from_bits(bits).without(Permission::Developer);
+    if developer {
+        permissions.with(Permission::Developer)
+    } else {
+        permissions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn developer_bit_only_comes_from_the_developer_column() {
+        let stored = Permissions::from_bits(0).with(Permission::Developer).with(Permission::GetChannels);
+        assert_eq!(user_permissions(stored.bits(), false).granted(), vec![Permission::GetChannels]);
+        assert_eq!(user_permissions(0, true).granted(), vec![Permission::Developer]);
+    }
+}
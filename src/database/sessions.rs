@@ -0,0 +1,165 @@
+use rocket_db_pools::diesel::prelude::*;
+
+use crate::database::Db;
+use crate::models::{DeviceInfo, Session, SessionError};
+use crate::schema::{login_sessions, refresh_tokens};
+
+// same trade-off as the last use of personal access tokens, a write per request is too much
+const LAST_SEEN_RESOLUTION_SECONDS: i64 = 60;
+
+pub(crate) trait SessionDatabase {
+    // sessions that are neither revoked nor expired, `current` is marked in the result
+    async fn get_sessions(&mut self, user_id: uuid::Uuid, current: Option<uuid::Uuid>) -> Result<Vec<Session>, SessionError>;
+    async fn revoke_session(&mut self, user_id: uuid::Uuid, session_id: uuid::Uuid) -> Result<(), SessionError>;
+    // false once the session is revoked or gone, keeps last_seen_at current otherwise
+    async fn touch_session(&mut self, session_id: uuid::Uuid) -> Result<bool, ()>;
+}
+
+impl SessionDatabase for rocket_db_pools::Connection<Db> {
+    async fn get_sessions(&mut self, user_id: uuid::Uuid, current: Option<uuid::Uuid>) -> Result<Vec<Session>, SessionError> {
+        let sessions = login_sessions::table
+            .select((
+                login_sessions::id,
+                login_sessions::device_name,
+                login_sessions::user_agent,
+                login_sessions::ip,
+                login_sessions::client_id,
+                login_sessions::created_at,
+                login_sessions::last_seen_at,
+                login_sessions::expires_at,
+            ))
+            .filter(login_sessions::user_id.eq(user_id))
+            .filter(login_sessions::revoked_at.is_null())
+            .filter(login_sessions::expires_at.gt(chrono::Utc::now()))
+            .order(login_sessions::last_seen_at.desc())
+            .load::<(uuid::Uuid, Option<String>, Option<String>, Option<String>, Option<uuid::Uuid>, chrono::DateTime<chrono::Utc>, chrono::DateTime<chrono::Utc>, chrono::DateTime<chrono::Utc>)>(self)
+            .await
+            .map_err(|_| SessionError::InternalServerError)?;
+
+        Ok(sessions.into_iter()
+            .map(|(id, device_name, user_agent, ip, client_id, created_at, last_seen_at, expires_at)| Session {
+                id,
+                device_name,
+                user_agent,
+                ip,
+                client_id,
+                created_at,
+                last_seen_at,
+                expires_at,
+                current: current == Some(id),
+            })
+            .collect())
+    }
+
+    async fn revoke_session(&mut self, user_id: uuid::Uuid, session_id: uuid::Uuid) -> Result<(), SessionError> {
+        let revoked = diesel::update(login_sessions::table)
+            .filter(login_sessions::id.eq(session_id))
+            .filter(login_sessions::user_id.eq(user_id))
+            .filter(login_sessions::revoked_at.is_null())
+            .set(login_sessions::revoked_at.eq(chrono::Utc::now()))
+            .execute(self)
+            .await
+            .map_err(|_| SessionError::InternalServerError)?;
+        if revoked == 0 {
+            return Err(SessionError::NotFound);
+        }
+
+        diesel::update(refresh_tokens::table)
+            .filter(refresh_tokens::family_id.eq(session_id))
+            .set(refresh_tokens::revoked.eq(true))
+            .execute(self)
+            .await
+            .map_err(|_| SessionError::InternalServerError)?;
+        Ok(())
+    }
+
+    async fn touch_session(&mut self, session_id: uuid::Uuid) -> Result<bool, ()> {
+        let now = chrono::Utc::now();
+
+        let session = login_sessions::table
+            .select((login_sessions::last_seen_at, login_sessions::revoked_at))
+            .filter(login_sessions::id.eq(session_id))
+            .first::<(chrono::DateTime<chrono::Utc>, Option<chrono::DateTime<chrono::Utc>>)>(self)
+            .await
+            .optional()
+            .map_err(|_| ())?;
+        let Some((last_seen_at, None)) = session else {
+            return Ok(false);
+        };
+
+        if last_seen_at < now - chrono::Duration::try_seconds(LAST_SEEN_RESOLUTION_SECONDS).ok_or(())? {
+            diesel::update(login_sessions::table)
+                .filter(login_sessions::id.eq(session_id))
+                .set(login_sessions::last_seen_at.eq(now))
+                .execute(self)
+                .await
+                .map_err(|_| ())?;
+        }
+        Ok(true)
+    }
+}
+
+// Creates the session on its first token, later tokens of the family only move it along.
+// Sessions are created on the fly for families that were started before sessions were recorded.
+pub(crate) async fn record_session(
+    db: &mut rocket_db_pools::Connection<Db>,
+    session_id: uuid::Uuid,
+    user_id: uuid::Uuid,
+    client_id: Option<uuid::Uuid>,
+    device: Option<&DeviceInfo>,
+    expires_at: chrono::DateTime<chrono::Utc>,
+) -> Result<(), ()> {
+    let now = chrono::Utc::now();
+
+    diesel::insert_into(login_sessions::table)
+        .values((
+            login_sessions::id.eq(session_id),
+            login_sessions::user_id.eq(user_id),
+            login_sessions::client_id.eq(client_id),
+            login_sessions::device_name.eq(device.and_then(|device| device.name.as_deref())),
+            login_sessions::user_agent.eq(device.and_then(|device| device.user_agent.as_deref())),
+            login_sessions::ip.eq(device.and_then(|device| device.ip).map(|ip| ip.to_string())),
+            login_sessions::expires_at.eq(expires_at),
+        ))
+        .on_conflict(login_sessions::id)
+        .do_update()
+        .set((
+            login_sessions::last_seen_at.eq(now),
+            login_sessions::expires_at.eq(expires_at),
+        ))
+        .execute(db)
+        .await
+        .map_err(|_| ())?;
+    Ok(())
+}
+
+// Families without a session row predate sessions and count as active
+pub(crate) async fn is_session_revoked(db: &mut rocket_db_pools::Connection<Db>, session_id: uuid::Uuid) -> Result<bool, ()> {
+    diesel::select(diesel::dsl::exists(
+        login_sessions::table
+            .filter(login_sessions::id.eq(session_id))
+            .filter(login_sessions::revoked_at.is_not_null())
+    ))
+        .get_result::<bool>(db)
+        .await
+        .map_err(|_| ())
+}
+
+// Revokes the session and its refresh token family, for when it's the tokens that turned up and not the user
+pub(crate) async fn end_session(db: &mut rocket_db_pools::Connection<Db>, session_id: uuid::Uuid) -> Result<(), ()> {
+    diesel::update(login_sessions::table)
+        .filter(login_sessions::id.eq(session_id))
+        .filter(login_sessions::revoked_at.is_null())
+        .set(login_sessions::revoked_at.eq(chrono::Utc::now()))
+        .execute(db)
+        .await
+        .map_err(|_| ())?;
+
+    diesel::update(refresh_tokens::table)
+        .filter(refresh_tokens::family_id.eq(session_id))
+        .set(refresh_tokens::revoked.eq(true))
+        .execute(db)
+        .await
+        .map_err(|_| ())?;
+    Ok(())
+}
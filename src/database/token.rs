@@ -1,4 +1,7 @@
-use diesel::result::Error;
+use diesel::result::{DatabaseErrorKind, Error};
+
+// how long a nonce handed out by a challenge can be signed
+pub(crate) const NONCE_TTL_SECONDS: i64 = 5 * 60;
 
 pub(crate) enum DataRetrievalError {
     NotFound,
@@ -21,10 +24,10 @@ pub(crate) enum DataRemovalError {
 }
 
 
-pub trait Database {
+pub(crate) trait Database {
     type Id<'a>;
 
-    async fn get_public_key(&mut self, user: Self::Id<'_>) -> Result<[u8; 32], DataRetrievalError>;
+    async fn get_public_keys(&mut self, user: Self::Id<'_>) -> Result<Vec<[u8; 32]>, DataRetrievalError>;
 
     async fn get_session_nonce(&mut self, user: Self::Id<'_>, key: [u8; 32]) -> Result<[u8; 32], DataRetrievalError>;
 
@@ -35,6 +38,14 @@ pub trait Database {
         nonce: [u8; 32],
     ) -> Result<(), DataSetError>;
 
+    // Replaces `nonce` with an unguessable one, fails if someone else consumed it first
+    async fn consume_session_nonce(
+        &mut self,
+        user: Self::Id<'_>,
+        key: [u8; 32],
+        nonce: [u8; 32],
+    ) -> Result<(), DataSetError>;
+
     async fn insert_session(
         &mut self,
         user: Self::Id<'_>,
@@ -45,27 +56,26 @@ pub trait Database {
     async fn remove_session(&mut self, user: Self::Id<'_>, key: [u8; 32]) -> Result<(), DataRemovalError>;
 }
 
+fn to_fixed_array(vec: Vec<u8>) -> Result<[u8; 32], DataRetrievalError> {
+    <[u8; 32]>::try_from(vec).map_err(|_| DataRetrievalError::InternalError)
+}
+
 impl Database for rocket_db_pools::Connection<crate::database::Db> {
     type Id<'a> = uuid::Uuid;
 
-    async fn get_public_key(&mut self, user: Self::Id<'_>) -> Result<[u8; 32], DataRetrievalError> {
+    async fn get_public_keys(&mut self, user: Self::Id<'_>) -> Result<Vec<[u8; 32]>, DataRetrievalError> {
         use rocket_db_pools::diesel::prelude::*;
         use crate::schema::sessions;
 
         sessions::table
             .select(sessions::key)
             .filter(sessions::user_id.eq(user))
-            .first::<Vec<u8>>(self)
+            .load::<Vec<u8>>(self)
             .await
-            .map_err(|e| match e {
-                Error::NotFound => DataRetrievalError::NotFound,
-                _ => DataRetrievalError::InternalError,
-            })
-            .and_then(|vec| {
-                let mut fixed_array = [0u8; 32];
-                fixed_array.copy_from_slice(&vec[..]);
-                Ok(fixed_array)
-            })
+            .map_err(|_| DataRetrievalError::InternalError)?
+            .into_iter()
+            .map(to_fixed_array)
+            .collect()
     }
 
     async fn get_session_nonce(&mut self, user: Self::Id<'_>, key: [u8; 32]) -> Result<[u8; 32], DataRetrievalError> {
@@ -76,40 +86,61 @@ impl Database for rocket_db_pools::Connection<crate::database::Db> {
             .select(sessions::nonce)
             .filter(sessions::user_id.eq(user))
             .filter(sessions::key.eq(key.as_slice()))
+            .filter(sessions::nonce_expires_at.gt(chrono::Utc::now()))
             .first::<Vec<u8>>(self)
             .await
             .map_err(|e| match e {
                 Error::NotFound => DataRetrievalError::NotFound,
                 _ => DataRetrievalError::InternalError,
             })
-            .and_then(|vec| {
-                let mut fixed_array = [0u8; 32];
-                fixed_array.copy_from_slice(&vec[..]);
-                Ok(fixed_array)
-            })
+            .and_then(to_fixed_array)
     }
 
     async fn set_session_nonce(&mut self, user: Self::Id<'_>, key: [u8; 32], nonce: [u8; 32]) -> Result<(), DataSetError> {
         use rocket_db_pools::diesel::prelude::*;
         use crate::schema::sessions;
 
+        let expires_at = chrono::Utc::now() + chrono::Duration::try_seconds(NONCE_TTL_SECONDS).ok_or(DataSetError::InternalError)?;
+
+        diesel::update(sessions::table)
+            .filter(sessions::user_id.eq(user))
+            .filter(sessions::key.eq(key.as_slice()))
+            .set((
+                sessions::nonce.eq(nonce.as_slice()),
+                sessions::nonce_expires_at.eq(expires_at),
+            ))
+            .execute(self)
+            .await
+            .map_err(|_| DataSetError::InternalError)
+            .and_then(|updated| if updated == 0 { Err(DataSetError::InvalidSession) } else { Ok(()) })
+    }
+
+    async fn consume_session_nonce(&mut self, user: Self::Id<'_>, key: [u8; 32], nonce: [u8; 32]) -> Result<(), DataSetError> {
+        use rocket_db_pools::diesel::prelude::*;
+        use crate::schema::sessions;
+
+        let mut replacement = [0; 32];
+        openssl::rand::rand_bytes(&mut replacement).map_err(|_| DataSetError::InternalError)?;
+
         diesel::update(sessions::table)
             .filter(sessions::user_id.eq(user))
             .filter(sessions::key.eq(key.as_slice()))
-            .set(sessions::nonce.eq(nonce.as_slice()))
+            .filter(sessions::nonce.eq(nonce.as_slice()))
+            .set((
+                sessions::nonce.eq(replacement.as_slice()),
+                sessions::nonce_expires_at.eq(chrono::Utc::now()),
+            ))
             .execute(self)
             .await
-            .map_err(|e| match e {
-                Error::NotFound => DataSetError::InvalidSession,
-                _ => DataSetError::InternalError,
-            })
-            .map(|_| ())
+            .map_err(|_| DataSetError::InternalError)
+            .and_then(|updated| if updated == 0 { Err(DataSetError::InvalidSession) } else { Ok(()) })
     }
 
     async fn insert_session(&mut self, user: Self::Id<'_>, key: [u8; 32], nonce: [u8; 32]) -> Result<(), DataInsertionError> {
         use rocket_db_pools::diesel::prelude::*;
         use crate::schema::sessions;
 
+        // the nonce starts out expired, a challenge has to be requested before the first login
         diesel::insert_into(sessions::table)
             .values((
                 sessions::key.eq(key.as_slice()),
@@ -119,7 +150,7 @@ impl Database for rocket_db_pools::Connection<crate::database::Db> {
             .execute(self)
             .await
             .map_err(|e| match e {
-                Error::DatabaseError(_, _) => DataInsertionError::AlreadyExists,
+                Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => DataInsertionError::AlreadyExists,
                 _ => DataInsertionError::InternalError,
             })
             .map(|_| ())
@@ -134,10 +165,7 @@ impl Database for rocket_db_pools::Connection<crate::database::Db> {
             .filter(sessions::key.eq(key.as_slice()))
             .execute(self)
             .await
-            .map_err(|e| match e {
-                Error::NotFound => DataRemovalError::InvalidSession,
-                _ => DataRemovalError::InternalError,
-            })
-            .map(|_| ())
+            .map_err(|_| DataRemovalError::InternalError)
+            .and_then(|removed| if removed == 0 { Err(DataRemovalError::InvalidSession) } else { Ok(()) })
     }
-}
\ No newline at end of file
+}
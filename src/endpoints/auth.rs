@@ -1,12 +1,38 @@
 use std::fmt::Display;
+use std::net::IpAddr;
+use std::sync::Arc;
+use rocket::fairing::AdHoc;
+use rocket::form::Form;
+use rocket::http::{CookieJar, Status};
 use rocket::http::uri::Origin;
-use rocket::response::Redirect;
-use rocket_contrib::databases::diesel::sql_types::Json;
+use rocket::serde::json::Json;
+use rocket::{Route, State};
+use jsonwebtoken::jwk::JwkSet;
 use rocket_db_pools::Connection;
 use crate::database::Db;
 use crate::models;
-use crate::database::auth::AuthDatabase;
-use crate::models::{AuthClaims, LoginError, RegisterError, Token};
+use crate::database::access_tokens::AccessTokenDatabase;
+use crate::database::account::AccountDatabase;
+use crate::database::audit::{record_auth_event, AuditDatabase};
+use crate::database::auth::{check_reauthentication, reauthentication_failed, AuthDatabase, JwtKeys};
+use crate::database::bots::BotDatabase;
+use crate::database::email::{verified_email, EmailDatabase};
+use crate::database::invites::InviteDatabase;
+use crate::database::lockout::LockoutDatabase;
+use crate::database::magic_links::MagicLinkDatabase;
+use crate::database::mfa::MfaDatabase;
+use crate::database::oauth::OAuthDatabase;
+use crate::database::oidc::OidcDatabase;
+use crate::database::passkeys::PasskeyDatabase;
+use crate::database::password::PasswordDatabase;
+use crate::database::permissions::PermissionDatabase;
+use crate::database::sessions::SessionDatabase;
+use crate::database::token::{self, Database as _};
+use crate::notifier::{self, Notification, Notifier, Recipient};
+use crate::oidc::OidcClient;
+use crate::policy::RegistrationPolicy;
+use crate::models::required::Developer;
+use crate::models::{AccessTokenError, AccountError, AccountExport, AuditError, AuthClaims, AuthEvent, AuthEventKind, Bot, BotError, AuthConfig, Challenge, CookieSession, CsrfVerified, DeviceInfo, EmailError, Invite, InviteError, InviteUse, Lockout, LockoutError, LockoutKind, LoginError, LoginResponse, MagicLinkError, MfaError, NewBot, NewInvite, NewOAuthClient, NewPersonalAccessToken, NotifierConfig, OAuthClient, OAuthConsent, OAuthError, OAuthRedirect, OAuthToken, OAuthTokenError, OidcError, Passkey, PasskeyError, PasskeyLoginOptions, PasskeyRegistrationOptions, PasswordError, Permission, PermissionError, PersonalAccessToken, PublicKeyError, RecoveryCodes, RefreshError, RegisterError, Require, RevocationError, Session, SessionError, Token, TotpEnrollment, UserEmail};
 
 pub(crate) trait Auth {
     fn mount_auth<'a, B>(self, base: B) -> Self where B: TryInto<Origin<'a>> + Clone + Display, B::Error: Display;
@@ -14,27 +40,804 @@ pub(crate) trait Auth {
 
 impl Auth for rocket::Rocket<rocket::Build> {
     fn mount_auth<'a, B>(self, base: B) -> Self where B: TryInto<Origin<'a>> + Clone + Display, B::Error: Display {
-        self.mount(base, routes![login, register, ping])
+        self.attach(AdHoc::try_on_ignite("Auth config", |rocket| async {
+            let config = match rocket.figment().focus("auth").extract::<AuthConfig>() {
+                Ok(config) => config,
+                Err(err) => {
+                    error!("Invalid auth configuration: {}", err);
+                    return Err(rocket);
+                }
+            };
+            let notifier = match &config.notifier {
+                Some(notifier) => notifier::from_config(notifier),
+                // the log would leak reset tokens and login links, so only debug builds fall back to it
+                None if cfg!(debug_assertions) => {
+                    warn!("No notifier configured, notifications are written to the log");
+                    notifier::from_config(&NotifierConfig::Log)
+                }
+                None => {
+                    error!("No notifier configured, set `auth.notifier`");
+                    return Err(rocket);
+                }
+            };
+            let oidc = match OidcClient::new() {
+                Ok(oidc) => oidc,
+                Err(err) => {
+                    error!("{}", err);
+                    return Err(rocket);
+                }
+            };
+            let policy = match RegistrationPolicy::from_config(&config.registration) {
+                Ok(policy) => policy,
+                Err(err) => {
+                    error!("Invalid registration policy: {}", err);
+                    return Err(rocket);
+                }
+            };
+            match JwtKeys::from_config(&config.jwt) {
+                Ok(keys) => Ok(rocket.manage(config).manage(keys).manage(notifier).manage(oidc).manage(policy)),
+                Err(err) => {
+                    error!("Invalid JWT key configuration: {}", err);
+                    Err(rocket)
+                }
+            }
+        }))
+            .register(base.clone(), catchers![models::forbidden])
+            .mount(base, routes![login, mfa_login, register, refresh, refresh_cookie, logout, logout_all, revoke_user_tokens, jwks, get_public_keys, add_public_key, remove_public_key, key_challenge, key_login, get_passkeys, passkey_registration_options, register_passkey, remove_passkey, passkey_login_options, passkey_login, enroll_totp, confirm_totp, disable_totp, reset_user_totp, change_password, request_password_reset, reset_password, request_magic_link, magic_link_login, get_email, set_email, verify_email, remove_email, get_user_email, get_lockouts, remove_user_lockout, remove_ip_lockout, get_user_permissions, grant_user_permission, revoke_user_permission, get_access_tokens, create_access_token, revoke_access_token, get_bots, create_bot, set_bot_permissions, regenerate_bot_token, get_oauth_clients, register_oauth_client, remove_oauth_client, oauth_consent, oauth_authorize, oauth_token, oauth_revoke, revoke_oauth_authorization, oidc_login, oidc_callback, get_sessions, revoke_session, export_account, delete_account, get_invites, create_invite, revoke_invite, get_invite_uses, get_auth_events, ping])
     }
 }
 
-#[post("/login", format = "json", data = "<login_request>")]
-pub async fn login(login_request: models::LoginRequest<'_>, mut db: Connection<Db>) -> Result<models::Token, LoginError> {
-    db.login(login_request.username, login_request.password).await
+// With `cookie=true` the tokens are set as HttpOnly cookies instead of being returned, for browser clients
+fn token_response(token: Token, cookie: bool, cookies: &CookieJar<'_>, config: &AuthConfig, route: &Route) -> Result<LoginResponse, ()> {
+    if !cookie {
+        return Ok(LoginResponse::Token(token));
+    }
+    CookieSession::start(cookies, config, route.uri.base(), token).map(LoginResponse::Cookie)
+}
+
+#[post("/login?<cookie>", format = "json", data = "<login_request>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn login(login_request: models::LoginRequest<'_>, cookie: bool, device: DeviceInfo, route: &Route, cookies: &CookieJar<'_>, config: &State<AuthConfig>, keys: &State<JwtKeys>, mut db: Connection<Db>) -> Result<LoginResponse, LoginError> {
+    match db.login(config, keys, login_request.username, login_request.password, device.ip, &device).await? {
+        LoginResponse::Token(token) => token_response(token, cookie, cookies, config, route).map_err(|_| LoginError::InternalServerError),
+        // the second factor is posted with `cookie=true` again
+        response => Ok(response),
+    }
 }
 
-#[post("/register", format = "json", data = "<register_request>")]
-pub async fn register(register_request: models::RegisterRequest<'_>, mut db: Connection<Db>) -> Result<Token, RegisterError> {
-    db.register(register_request.username, register_request.password).await?;
-    db.login(register_request.username, register_request.password).await.map_err(|e| match e {
-        LoginError::InternalServerError | LoginError::Unauthorized => RegisterError::InternalServerError
+#[post("/login/2fa?<cookie>", format = "json", data = "<mfa_login_request>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn mfa_login(mfa_login_request: models::MfaLoginRequest<'_>, cookie: bool, device: DeviceInfo, route: &Route, cookies: &CookieJar<'_>, config: &State<AuthConfig>, keys: &State<JwtKeys>, mut db: Connection<Db>) -> Result<LoginResponse, LoginError> {
+    let token = db.mfa_login(config, keys, mfa_login_request.mfa_token, mfa_login_request.code, device.ip, &device).await?;
+    token_response(token, cookie, cookies, config, route).map_err(|_| LoginError::InternalServerError)
+}
+
+#[post("/register?<cookie>", format = "json", data = "<register_request>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn register(register_request: models::RegisterRequest<'_>, cookie: bool, device: DeviceInfo, route: &Route, cookies: &CookieJar<'_>, config: &State<AuthConfig>, policy: &State<RegistrationPolicy>, keys: &State<JwtKeys>, mut db: Connection<Db>) -> Result<LoginResponse, RegisterError> {
+    let username = db.register(config, policy, register_request.username, register_request.password, register_request.invite_code, &device).await?;
+    // no client IP for the lockout, a freshly registered account must not be held back by failures of others behind the same address
+    let response = db.login(config, keys, &username, register_request.password, None, &device).await.map_err(|e| match e {
+        LoginError::InternalServerError | LoginError::Unauthorized | LoginError::TooManyAttempts { .. } => RegisterError::InternalServerError
         // LoginError::Unauthorized shouldn't happen, user has been registered one line before calling this
         // but there may be a place for the race condition, so it should return InternalServerError too
+    })?;
+    match response {
+        LoginResponse::Token(token) => token_response(token, cookie, cookies, config, route).map_err(|_| RegisterError::InternalServerError),
+        // a new account can't have 2FA enabled yet
+        LoginResponse::MfaRequired { .. } | LoginResponse::Cookie(_) => Err(RegisterError::InternalServerError),
+    }
+}
+
+#[post("/refresh", format = "json", data = "<refresh_request>")]
+pub async fn refresh(refresh_request: models::RefreshRequest<'_>, device: DeviceInfo, config: &State<AuthConfig>, keys: &State<JwtKeys>, mut db: Connection<Db>) -> Result<Token, RefreshError> {
+    db.refresh(config, keys, refresh_request.refresh_token, None, &device).await
+}
+
+// Cookie mode counterpart of `/refresh`, the refresh token comes from its cookie and all cookies are replaced
+#[post("/refresh/cookie")]
+pub async fn refresh_cookie(_csrf: CsrfVerified, device: DeviceInfo, route: &Route, cookies: &CookieJar<'_>, config: &State<AuthConfig>, keys: &State<JwtKeys>, mut db: Connection<Db>) -> Result<CookieSession, RefreshError> {
+    let refresh_token = cookies.get(models::REFRESH_COOKIE)
+        .map(|cookie| cookie.value().to_string())
+        .ok_or(RefreshError::Unauthorized)?;
+    let token = db.refresh(config, keys, &refresh_token, None, &device).await?;
+    CookieSession::start(cookies, config, route.uri.base(), token).map_err(|_| RefreshError::InternalServerError)
+}
+
+// The refresh token is optional, when it's sent its whole family is revoked as well.
+// Cookies of cookie mode are cleared either way.
+#[post("/logout", data = "<logout_request>")]
+pub async fn logout(claims: AuthClaims, logout_request: Option<models::RefreshRequest<'_>>, device: DeviceInfo, route: &Route, cookies: &CookieJar<'_>, mut db: Connection<Db>) -> Result<Status, RevocationError> {
+    CookieSession::end(cookies, route.uri.base());
+
+    // logging out with a personal access token deletes it
+    if let Some(access_token) = claims.access_token {
+        return match db.revoke_access_token(claims.sub, access_token).await {
+            Ok(()) => {
+                record_auth_event(&mut db, AuthEventKind::TokenRevoked, Some(claims.sub), None, Some(&device), Some("personal_access_token")).await;
+                Ok(Status::NoContent)
+            }
+            Err(AccessTokenError::NotFound) => Ok(Status::NoContent),
+            Err(_) => Err(RevocationError::InternalServerError),
+        };
+    }
+
+    db.revoke_token(&claims).await?;
+    if let Some(session_id) = claims.sid {
+        match db.revoke_session(claims.sub, session_id).await {
+            Ok(()) | Err(SessionError::NotFound) => {}
+            Err(_) => return Err(RevocationError::InternalServerError),
+        }
+    }
+    // only needed for refresh tokens issued before sessions were recorded, ending the session revokes the family
+    if let Some(logout_request) = logout_request {
+        db.revoke_refresh_token(claims.sub, logout_request.refresh_token).await?;
+    }
+    record_auth_event(&mut db, AuthEventKind::TokenRevoked, Some(claims.sub), None, Some(&device), Some("logout")).await;
+    Ok(Status::NoContent)
+}
+
+#[post("/logout/all")]
+pub async fn logout_all(claims: AuthClaims, device: DeviceInfo, route: &Route, cookies: &CookieJar<'_>, mut db: Connection<Db>) -> Result<Status, RevocationError> {
+    CookieSession::end(cookies, route.uri.base());
+    db.revoke_user_tokens(claims.sub).await?;
+    record_auth_event(&mut db, AuthEventKind::TokenRevoked, Some(claims.sub), None, Some(&device), Some("logout_all")).await;
+    Ok(Status::NoContent)
+}
+
+// The event is about the user whose tokens were revoked, the device is the developer's
+#[post("/users/<user_id>/revoke")]
+pub async fn revoke_user_tokens(user_id: models::UUIDWrapper, _claims: Require<Developer>, device: DeviceInfo, mut db: Connection<Db>) -> Result<Status, RevocationError> {
+    let user_id: uuid::Uuid = user_id.into();
+    db.revoke_user_tokens(user_id).await?;
+    record_auth_event(&mut db, AuthEventKind::TokenRevoked, Some(user_id), None, Some(&device), Some("developer")).await;
+    Ok(Status::NoContent)
+}
+
+#[get("/.well-known/jwks.json")]
+pub fn jwks(keys: &State<JwtKeys>) -> Json<JwkSet> {
+    Json(keys.jwks().clone())
+}
+
+fn decode_base64(value: &str) -> Option<Vec<u8>> {
+    use base64::Engine;
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(value).ok()
+}
+
+fn decode_public_key(value: &str) -> Option<[u8; 32]> {
+    decode_base64(value).and_then(|key| <[u8; 32]>::try_from(key).ok())
+}
+
+#[get("/keys")]
+pub async fn get_public_keys(claims: AuthClaims, mut db: Connection<Db>) -> Result<Json<Vec<String>>, PublicKeyError> {
+    use base64::Engine;
+
+    db.get_public_keys(claims.sub)
+        .await
+        .map_err(|_| PublicKeyError::InternalServerError)
+        .map(|keys| keys.iter().map(|key| base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(key)).collect())
+        .map(Json)
+}
+
+#[post("/keys", format = "json", data = "<key_request>")]
+pub async fn add_public_key(key_request: models::PublicKeyRequest<'_>, claims: AuthClaims, mut db: Connection<Db>) -> Result<Status, PublicKeyError> {
+    // a registered key logs in with every permission, more than a delegated token holds
+    if claims.is_delegated() {
+        return Err(PublicKeyError::Forbidden);
+    }
+    let public_key = decode_public_key(key_request.public_key).ok_or(PublicKeyError::InvalidKey)?;
+
+    let mut nonce = [0; 32];
+    openssl::rand::rand_bytes(&mut nonce).map_err(|_| PublicKeyError::InternalServerError)?;
+
+    db.insert_session(claims.sub, public_key, nonce)
+        .await
+        .map_err(|e| match e {
+            token::DataInsertionError::AlreadyExists => PublicKeyError::Conflict,
+            token::DataInsertionError::InternalError => PublicKeyError::InternalServerError,
+        })?;
+    Ok(Status::Created)
+}
+
+#[delete("/keys/<public_key>")]
+pub async fn remove_public_key(public_key: &str, claims: AuthClaims, mut db: Connection<Db>) -> Result<Status, PublicKeyError> {
+    if claims.is_delegated() {
+        return Err(PublicKeyError::Forbidden);
+    }
+    let public_key = decode_public_key(public_key).ok_or(PublicKeyError::InvalidKey)?;
+
+    db.remove_session(claims.sub, public_key)
+        .await
+        .map_err(|e| match e {
+            token::DataRemovalError::InvalidSession => PublicKeyError::NotFound,
+            token::DataRemovalError::InternalError => PublicKeyError::InternalServerError,
+        })?;
+    Ok(Status::NoContent)
+}
+
+#[post("/challenge", format = "json", data = "<challenge_request>")]
+pub async fn key_challenge(challenge_request: models::ChallengeRequest<'_>, mut db: Connection<Db>) -> Result<Challenge, LoginError> {
+    use base64::Engine;
+
+    let public_key = decode_public_key(challenge_request.public_key).ok_or(LoginError::Unauthorized)?;
+    let nonce = db.key_challenge(challenge_request.username, public_key).await?;
+
+    Ok(Challenge {
+        nonce: base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(nonce),
+        expires_in: token::NONCE_TTL_SECONDS as u32,
     })
 }
 
+// Skips 2FA, holding the registered private key is already a second factor
+#[post("/challenge/login", format = "json", data = "<key_login_request>")]
+pub async fn key_login(key_login_request: models::KeyLoginRequest<'_>, device: DeviceInfo, config: &State<AuthConfig>, keys: &State<JwtKeys>, mut db: Connection<Db>) -> Result<Token, LoginError> {
+    let public_key = decode_public_key(key_login_request.public_key).ok_or(LoginError::Unauthorized)?;
+    let signature = decode_base64(key_login_request.signature).ok_or(LoginError::Unauthorized)?;
+
+    db.key_login(config, keys, key_login_request.username, public_key, &signature, &device).await
+}
+
+#[get("/passkeys")]
+pub async fn get_passkeys(claims: AuthClaims, mut db: Connection<Db>) -> Result<Json<Vec<Passkey>>, PasskeyError> {
+    db.get_passkeys(claims.sub).await.map(Json)
+}
+
+// A passkey logs in with every permission, like a registered key
+#[post("/passkeys/options")]
+pub async fn passkey_registration_options(claims: AuthClaims, config: &State<AuthConfig>, mut db: Connection<Db>) -> Result<PasskeyRegistrationOptions, PasskeyError> {
+    if claims.is_delegated() {
+        return Err(PasskeyError::Forbidden);
+    }
+    db.start_passkey_registration(config, claims.sub).await
+}
+
+// Takes the authenticator's response to the options, binary fields base64url encoded
+#[post("/passkeys", format = "json", data = "<registration_request>")]
+pub async fn register_passkey(registration_request: models::PasskeyRegistrationRequest<'_>, claims: AuthClaims, config: &State<AuthConfig>, mut db: Connection<Db>) -> Result<Passkey, PasskeyError> {
+    if claims.is_delegated() {
+        return Err(PasskeyError::Forbidden);
+    }
+    db.finish_passkey_registration(config, claims.sub, &registration_request).await
+}
+
+#[delete("/passkeys/<id>")]
+pub async fn remove_passkey(id: models::UUIDWrapper, claims: AuthClaims, mut db: Connection<Db>) -> Result<Status, PasskeyError> {
+    if claims.is_delegated() {
+        return Err(PasskeyError::Forbidden);
+    }
+    db.remove_passkey(claims.sub, id.into()).await?;
+    Ok(Status::NoContent)
+}
+
+// Without a username any passkey of the relying party can answer, for discoverable credentials
+#[post("/passkeys/login/options", format = "json", data = "<options_request>")]
+pub async fn passkey_login_options(options_request: models::PasskeyLoginOptionsRequest<'_>, config: &State<AuthConfig>, mut db: Connection<Db>) -> Result<PasskeyLoginOptions, PasskeyError> {
+    db.start_passkey_login(config, options_request.username).await
+}
+
+#[post("/passkeys/login?<cookie>", format = "json", data = "<login_request>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn passkey_login(login_request: models::PasskeyLoginRequest<'_>, cookie: bool, device: DeviceInfo, route: &Route, cookies: &CookieJar<'_>, config: &State<AuthConfig>, keys: &State<JwtKeys>, mut db: Connection<Db>) -> Result<LoginResponse, PasskeyError> {
+    let token = db.passkey_login(config, keys, &login_request, &device).await?;
+    token_response(token, cookie, cookies, config, route).map_err(|_| PasskeyError::InternalServerError)
+}
+
+// Starts (or restarts) enrollment, 2FA stays off until the first code is confirmed
+#[post("/2fa/totp")]
+pub async fn enroll_totp(claims: AuthClaims, config: &State<AuthConfig>, mut db: Connection<Db>) -> Result<TotpEnrollment, MfaError> {
+    if claims.is_delegated() {
+        return Err(MfaError::Forbidden);
+    }
+    db.enroll_totp(config, claims.sub).await
+}
+
+// Recovery codes are only shown here, they are stored hashed
+#[post("/2fa/totp/confirm", format = "json", data = "<code_request>")]
+pub async fn confirm_totp(code_request: models::MfaCodeRequest<'_>, claims: AuthClaims, mut db: Connection<Db>) -> Result<RecoveryCodes, MfaError> {
+    if claims.is_delegated() {
+        return Err(MfaError::Forbidden);
+    }
+    db.confirm_totp(claims.sub, code_request.code).await
+}
+
+// A valid second factor is required, a leaked access token alone can't turn 2FA off
+#[post("/2fa/disable", format = "json", data = "<code_request>")]
+pub async fn disable_totp(code_request: models::MfaCodeRequest<'_>, claims: AuthClaims, device: DeviceInfo, config: &State<AuthConfig>, mut db: Connection<Db>) -> Result<Status, MfaError> {
+    if claims.is_delegated() {
+        return Err(MfaError::Forbidden);
+    }
+    let login = check_reauthentication(&mut db, claims.sub, &device).await?;
+    match db.verify_second_factor(claims.sub, code_request.code).await {
+        Err(MfaError::InvalidCode) => {
+            reauthentication_failed(&mut db, config, claims.sub, &login, &device, "2fa_disable").await;
+            return Err(MfaError::InvalidCode);
+        }
+        result => result?,
+    }
+    db.disable_totp(claims.sub).await?;
+    Ok(Status::NoContent)
+}
+
+// For users who lost both their authenticator and their recovery codes
+#[delete("/users/<user_id>/2fa")]
+pub async fn reset_user_totp(user_id: models::UUIDWrapper, _claims: Require<Developer>, mut db: Connection<Db>) -> Result<Status, MfaError> {
+    db.disable_totp(user_id.into()).await?;
+    Ok(Status::NoContent)
+}
+
+// Every token of the user is revoked afterwards, including the one used for this request
+#[put("/password", format = "json", data = "<password_request>")]
+pub async fn change_password(password_request: models::PasswordChangeRequest<'_>, claims: AuthClaims, device: DeviceInfo, config: &State<AuthConfig>, policy: &State<RegistrationPolicy>, mut db: Connection<Db>) -> Result<Status, PasswordError> {
+    if claims.is_delegated() {
+        return Err(PasswordError::Forbidden);
+    }
+    policy.check_password(password_request.new_password).map_err(PasswordError::Rejected)?;
+    db.change_password(config, claims.sub, password_request.old_password, password_request.new_password, &device).await?;
+    Ok(Status::NoContent)
+}
+
+// Always accepted, so it can't be used to find out which usernames exist
+#[post("/password/reset", format = "json", data = "<reset_request>")]
+pub async fn request_password_reset(reset_request: models::PasswordResetRequest<'_>, config: &State<AuthConfig>, notifier: &State<Arc<dyn Notifier>>, mut db: Connection<Db>) -> Result<Status, PasswordError> {
+    if let Some((user_id, token)) = db.create_password_reset(config, reset_request.username).await? {
+        let email = verified_email(&mut db, user_id).await;
+        let recipient = Recipient { user_id, username: reset_request.username.to_string(), email };
+        let notification = Notification::PasswordReset { token, expires_in: config.password_reset_ttl };
+        if notifier::send(notifier, recipient, notification).await.is_err() {
+            error!("Could not deliver password reset token of user {}", user_id);
+        }
+    }
+    Ok(Status::Accepted)
+}
+
+#[post("/password/reset/confirm", format = "json", data = "<confirm_request>")]
+pub async fn reset_password(confirm_request: models::PasswordResetConfirmRequest<'_>, config: &State<AuthConfig>, policy: &State<RegistrationPolicy>, mut db: Connection<Db>) -> Result<Status, PasswordError> {
+    policy.check_password(confirm_request.new_password).map_err(PasswordError::Rejected)?;
+    db.reset_password(config, confirm_request.token, confirm_request.new_password).await?;
+    Ok(Status::NoContent)
+}
+
+// Always accepted, whether the user exists or not and whether a link was sent moments ago
+#[post("/magic-link", format = "json", data = "<link_request>")]
+pub async fn request_magic_link(link_request: models::MagicLinkRequest<'_>, config: &State<AuthConfig>, notifier: &State<Arc<dyn Notifier>>, mut db: Connection<Db>) -> Result<Status, MagicLinkError> {
+    if let Some((user_id, token)) = db.create_magic_link(config, link_request.username).await? {
+        let mut link = reqwest::Url::parse(&config.magic_link_url).map_err(|_| MagicLinkError::InternalServerError)?;
+        link.query_pairs_mut().append_pair("token", &token);
+
+        let email = verified_email(&mut db, user_id).await;
+        let recipient = Recipient { user_id, username: link_request.username.to_string(), email };
+        let notification = Notification::MagicLink { link: String::from(link), expires_in: config.magic_link_ttl };
+        if notifier::send(notifier, recipient, notification).await.is_err() {
+            error!("Could not deliver login link of user {}", user_id);
+        }
+    }
+    Ok(Status::Accepted)
+}
+
+#[post("/magic-link/login?<cookie>", format = "json", data = "<login_request>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn magic_link_login(login_request: models::MagicLinkLoginRequest<'_>, cookie: bool, device: DeviceInfo, route: &Route, cookies: &CookieJar<'_>, config: &State<AuthConfig>, keys: &State<JwtKeys>, mut db: Connection<Db>) -> Result<LoginResponse, MagicLinkError> {
+    match db.magic_link_login(config, keys, login_request.token, &device).await? {
+        LoginResponse::Token(token) => token_response(token, cookie, cookies, config, route).map_err(|_| MagicLinkError::InternalServerError),
+        // the second factor is posted to `/login/2fa` with `cookie=true` again
+        response => Ok(response),
+    }
+}
+
+#[get("/email")]
+pub async fn get_email(claims: AuthClaims, mut db: Connection<Db>) -> Result<UserEmail, EmailError> {
+    db.get_email(claims.sub).await
+}
+
+// The address replaces the current one once the code sent to it is posted to `/email/verify`, putting it again sends a new code
+#[put("/email", format = "json", data = "<email_request>")]
+pub async fn set_email(email_request: models::EmailRequest<'_>, claims: AuthClaims, config: &State<AuthConfig>, notifier: &State<Arc<dyn Notifier>>, mut db: Connection<Db>) -> Result<Status, EmailError> {
+    if claims.is_delegated() {
+        return Err(EmailError::Forbidden);
+    }
+    let Some(pending) = db.set_email(config, claims.sub, email_request.email).await? else {
+        return Ok(Status::NoContent);
+    };
+
+    let recipient = Recipient { user_id: claims.sub, username: pending.username, email: Some(pending.email) };
+    let notification = Notification::EmailVerification { code: pending.code, expires_in: config.email_verification_ttl };
+    if notifier::send(notifier, recipient, notification).await.is_err() {
+        error!("Could not deliver email verification code of user {}", claims.sub);
+    }
+    Ok(Status::Accepted)
+}
+
+#[post("/email/verify", format = "json", data = "<verification_request>")]
+pub async fn verify_email(verification_request: models::EmailVerificationRequest<'_>, claims: AuthClaims, mut db: Connection<Db>) -> Result<Status, EmailError> {
+    if claims.is_delegated() {
+        return Err(EmailError::Forbidden);
+    }
+    db.verify_email(claims.sub, verification_request.code).await?;
+    Ok(Status::NoContent)
+}
+
+#[delete("/email")]
+pub async fn remove_email(claims: AuthClaims, mut db: Connection<Db>) -> Result<Status, EmailError> {
+    if claims.is_delegated() {
+        return Err(EmailError::Forbidden);
+    }
+    db.remove_email(claims.sub).await?;
+    Ok(Status::NoContent)
+}
+
+#[get("/users/<user_id>/email")]
+pub async fn get_user_email(user_id: models::UUIDWrapper, _claims: Require<Developer>, mut db: Connection<Db>) -> Result<UserEmail, EmailError> {
+    db.get_email(user_id.into()).await
+}
+
+#[get("/lockouts")]
+pub async fn get_lockouts(_claims: Require<Developer>, mut db: Connection<Db>) -> Result<Json<Vec<Lockout>>, LockoutError> {
+    db.get_lockouts().await.map(Json)
+}
+
+#[delete("/lockouts/users/<username>")]
+pub async fn remove_user_lockout(username: &str, _claims: Require<Developer>, mut db: Connection<Db>) -> Result<Status, LockoutError> {
+    db.remove_lockout(LockoutKind::Username, username).await?;
+    Ok(Status::NoContent)
+}
+
+#[delete("/lockouts/ips/<client_ip>")]
+pub async fn remove_ip_lockout(client_ip: IpAddr, _claims: Require<Developer>, mut db: Connection<Db>) -> Result<Status, LockoutError> {
+    db.remove_lockout(LockoutKind::Ip, &client_ip.to_string()).await?;
+    Ok(Status::NoContent)
+}
+
+#[get("/users/<user_id>/permissions")]
+pub async fn get_user_permissions(user_id: models::UUIDWrapper, _claims: Require<Developer>, mut db: Connection<Db>) -> Result<Json<Vec<Permission>>, PermissionError> {
+    db.get_permissions(user_id.into()).await.map(|permissions| Json(permissions.granted()))
+}
+
+#[put("/users/<user_id>/permissions/<permission>")]
+pub async fn grant_user_permission(user_id: models::UUIDWrapper, permission: Permission, _claims: Require<Developer>, mut db: Connection<Db>) -> Result<Status, PermissionError> {
+    db.grant_permission(user_id.into(), permission).await?;
+    Ok(Status::NoContent)
+}
+
+#[delete("/users/<user_id>/permissions/<permission>")]
+pub async fn revoke_user_permission(user_id: models::UUIDWrapper, permission: Permission, _claims: Require<Developer>, mut db: Connection<Db>) -> Result<Status, PermissionError> {
+    db.revoke_permission(user_id.into(), permission).await?;
+    Ok(Status::NoContent)
+}
+
+// Managing personal access tokens needs a login, a leaked token must not be able to mint more
+#[get("/tokens")]
+pub async fn get_access_tokens(claims: AuthClaims, mut db: Connection<Db>) -> Result<Json<Vec<PersonalAccessToken>>, AccessTokenError> {
+    if claims.is_delegated() {
+        return Err(AccessTokenError::Forbidden);
+    }
+    db.get_access_tokens(claims.sub).await.map(Json)
+}
+
+#[post("/tokens", format = "json", data = "<token_request>")]
+pub async fn create_access_token(token_request: models::PersonalAccessTokenRequest<'_>, claims: AuthClaims, device: DeviceInfo, mut db: Connection<Db>) -> Result<NewPersonalAccessToken, AccessTokenError> {
+    if claims.is_delegated() {
+        return Err(AccessTokenError::Forbidden);
+    }
+    let token = db.create_access_token(claims.sub, &token_request).await?;
+    record_auth_event(&mut db, AuthEventKind::TokenIssued, Some(claims.sub), None, Some(&device), Some("personal_access_token")).await;
+    Ok(token)
+}
+
+#[delete("/tokens/<id>")]
+pub async fn revoke_access_token(id: models::UUIDWrapper, claims: AuthClaims, device: DeviceInfo, mut db: Connection<Db>) -> Result<Status, AccessTokenError> {
+    if claims.is_delegated() {
+        return Err(AccessTokenError::Forbidden);
+    }
+    db.revoke_access_token(claims.sub, id.into()).await?;
+    record_auth_event(&mut db, AuthEventKind::TokenRevoked, Some(claims.sub), None, Some(&device), Some("personal_access_token")).await;
+    Ok(Status::NoContent)
+}
+
+// Bots are owned by the human who created them, a bot token can't manage bots
+#[get("/bots")]
+pub async fn get_bots(claims: AuthClaims, mut db: Connection<Db>) -> Result<Json<Vec<Bot>>, BotError> {
+    if claims.is_delegated() {
+        return Err(BotError::Forbidden);
+    }
+    db.get_bots(claims.sub).await.map(Json)
+}
+
+#[post("/bots", format = "json", data = "<bot_request>")]
+pub async fn create_bot(bot_request: models::BotRequest<'_>, claims: AuthClaims, device: DeviceInfo, policy: &State<RegistrationPolicy>, mut db: Connection<Db>) -> Result<NewBot, BotError> {
+    if claims.is_delegated() {
+        return Err(BotError::Forbidden);
+    }
+    let bot = db.create_bot(policy, claims.sub, &bot_request).await?;
+    record_auth_event(&mut db, AuthEventKind::Registered, Some(bot.bot.id), Some(bot.bot.name.as_str()), Some(&device), Some("bot")).await;
+    record_auth_event(&mut db, AuthEventKind::TokenIssued, Some(bot.bot.id), Some(bot.bot.name.as_str()), Some(&device), Some("bot_token")).await;
+    Ok(bot)
+}
+
+#[put("/bots/<bot_id>/permissions", format = "json", data = "<permissions>")]
+pub async fn set_bot_permissions(bot_id: models::UUIDWrapper, permissions: Json<Vec<Permission>>, claims: AuthClaims, mut db: Connection<Db>) -> Result<Bot, BotError> {
+    if claims.is_delegated() {
+        return Err(BotError::Forbidden);
+    }
+    db.set_bot_permissions(claims.sub, bot_id.into(), &permissions).await
+}
+
+#[post("/bots/<bot_id>/token")]
+pub async fn regenerate_bot_token(bot_id: models::UUIDWrapper, claims: AuthClaims, device: DeviceInfo, mut db: Connection<Db>) -> Result<NewBot, BotError> {
+    if claims.is_delegated() {
+        return Err(BotError::Forbidden);
+    }
+    let bot = db.regenerate_bot_token(claims.sub, bot_id.into()).await?;
+    record_auth_event(&mut db, AuthEventKind::TokenIssued, Some(bot.bot.id), Some(bot.bot.name.as_str()), Some(&device), Some("bot_token")).await;
+    Ok(bot)
+}
+
+// OAuth clients are registered by their developers, any user with a login can do that
+#[get("/oauth/clients")]
+pub async fn get_oauth_clients(claims: AuthClaims, mut db: Connection<Db>) -> Result<Json<Vec<OAuthClient>>, OAuthError> {
+    if claims.is_delegated() {
+        return Err(OAuthError::Forbidden);
+    }
+    db.get_clients(claims.sub).await.map(Json)
+}
+
+#[post("/oauth/clients", format = "json", data = "<client_request>")]
+pub async fn register_oauth_client(client_request: models::OAuthClientRequest<'_>, claims: AuthClaims, mut db: Connection<Db>) -> Result<NewOAuthClient, OAuthError> {
+    if claims.is_delegated() {
+        return Err(OAuthError::Forbidden);
+    }
+    db.register_client(claims.sub, &client_request).await
+}
+
+#[delete("/oauth/clients/<client_id>")]
+pub async fn remove_oauth_client(client_id: models::UUIDWrapper, claims: AuthClaims, mut db: Connection<Db>) -> Result<Status, OAuthError> {
+    if claims.is_delegated() {
+        return Err(OAuthError::Forbidden);
+    }
+    db.remove_client(claims.sub, client_id.into()).await?;
+    Ok(Status::NoContent)
+}
+
+// The consent page looks up what it should show here, then posts the decision with the same query
+#[get("/oauth/authorize?<request..>")]
+pub async fn oauth_consent(request: models::AuthorizationRequest<'_>, claims: AuthClaims, mut db: Connection<Db>) -> Result<OAuthConsent, OAuthError> {
+    if claims.is_delegated() {
+        return Err(OAuthError::Forbidden);
+    }
+    db.authorization_consent(claims.sub, &request).await
+}
+
+#[post("/oauth/authorize?<request..>", format = "json", data = "<decision>")]
+pub async fn oauth_authorize(request: models::AuthorizationRequest<'_>, decision: models::AuthorizationDecision, claims: AuthClaims, config: &State<AuthConfig>, mut db: Connection<Db>) -> Result<OAuthRedirect, OAuthError> {
+    if claims.is_delegated() {
+        return Err(OAuthError::Forbidden);
+    }
+    let params = if decision.approve {
+        let code = db.authorize(config, claims.sub, &request).await?;
+        vec![("code", code)]
+    } else {
+        // validated anyway, the user must only be sent to a registered redirect URI
+        db.authorization_consent(claims.sub, &request).await?;
+        vec![("error", "access_denied".to_string())]
+    };
+    Ok(OAuthRedirect { redirect_to: redirect_uri_with(request.redirect_uri, params, request.state) })
+}
+
+fn redirect_uri_with(redirect_uri: &str, mut params: Vec<(&str, String)>, state: Option<&str>) -> String {
+    use rocket::http::RawStr;
+
+    if let Some(state) = state {
+        params.push(("state", state.to_string()));
+    }
+    let query = params.iter()
+        .map(|(key, value)| format!("{}={}", key, RawStr::new(value).percent_encode()))
+        .collect::<Vec<_>>()
+        .join("&");
+    let separator = if redirect_uri.contains('?') { '&' } else { '?' };
+    format!("{}{}{}", redirect_uri, separator, query)
+}
+
+// Called by the client itself, not by the user's browser
+#[post("/oauth/token", format = "form", data = "<token_request>")]
+pub async fn oauth_token(token_request: Form<models::OAuthTokenRequest<'_>>, device: DeviceInfo, config: &State<AuthConfig>, keys: &State<JwtKeys>, mut db: Connection<Db>) -> Result<OAuthToken, OAuthTokenError> {
+    match token_request.grant_type {
+        "authorization_code" => db.exchange_code(config, keys, &token_request, &device).await,
+        "refresh_token" => db.refresh_client_token(config, keys, &token_request, &device).await,
+        _ => Err(OAuthTokenError::UnsupportedGrantType),
+    }
+}
+
+#[post("/oauth/revoke", format = "form", data = "<revocation_request>")]
+pub async fn oauth_revoke(revocation_request: Form<models::OAuthRevocationRequest<'_>>, keys: &State<JwtKeys>, mut db: Connection<Db>) -> Result<Status, OAuthTokenError> {
+    db.revoke_client_token(keys, &revocation_request).await?;
+    Ok(Status::Ok)
+}
+
+// Takes back what the user consented to, the client needs a new authorization afterwards
+#[delete("/oauth/authorizations/<client_id>")]
+pub async fn revoke_oauth_authorization(client_id: models::UUIDWrapper, claims: AuthClaims, device: DeviceInfo, mut db: Connection<Db>) -> Result<Status, OAuthError> {
+    if claims.is_delegated() {
+        return Err(OAuthError::Forbidden);
+    }
+    db.revoke_authorization(claims.sub, client_id.into()).await?;
+    record_auth_event(&mut db, AuthEventKind::TokenRevoked, Some(claims.sub), None, Some(&device), Some("oauth_authorization")).await;
+    Ok(Status::NoContent)
+}
+
+// The frontend sends the user to `redirect_to` and posts what the provider redirects back with to the callback
+#[get("/oidc/<provider>/login")]
+pub async fn oidc_login(provider: &str, config: &State<AuthConfig>, oidc: &State<OidcClient>, mut db: Connection<Db>) -> Result<OAuthRedirect, OidcError> {
+    let provider_config = config.oidc.get(provider).ok_or(OidcError::NotFound)?;
+    let login = db.create_oidc_login(config, provider).await?;
+    let redirect_to = oidc.authorization_url(provider, provider_config, &login.state, &login.nonce, &login.code_verifier).await?;
+    Ok(OAuthRedirect { redirect_to })
+}
+
+#[post("/oidc/<provider>/callback", format = "json", data = "<callback>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn oidc_callback(provider: &str, callback: models::OidcCallbackRequest<'_>, device: DeviceInfo, config: &State<AuthConfig>, policy: &State<RegistrationPolicy>, keys: &State<JwtKeys>, oidc: &State<OidcClient>, mut db: Connection<Db>) -> Result<Token, OidcError> {
+    let provider_config = config.oidc.get(provider).ok_or(OidcError::NotFound)?;
+    let login = db.take_oidc_login(provider, callback.state).await?;
+    let identity = oidc.exchange_code(provider, provider_config, callback.code, &login.code_verifier, &login.nonce).await?;
+    db.oidc_login(config, policy, keys, provider, &identity, &device).await
+}
+
+// Includes the sessions OAuth clients hold, they show up with their client id
+#[get("/sessions")]
+pub async fn get_sessions(claims: AuthClaims, mut db: Connection<Db>) -> Result<Json<Vec<Session>>, SessionError> {
+    if claims.is_delegated() {
+        return Err(SessionError::Forbidden);
+    }
+    db.get_sessions(claims.sub, claims.sid).await.map(Json)
+}
+
+// Signs the device out, its access token stops working with the next request
+#[delete("/sessions/<id>")]
+pub async fn revoke_session(id: models::UUIDWrapper, claims: AuthClaims, device: DeviceInfo, mut db: Connection<Db>) -> Result<Status, SessionError> {
+    if claims.is_delegated() {
+        return Err(SessionError::Forbidden);
+    }
+    db.revoke_session(claims.sub, id.into()).await?;
+    record_auth_event(&mut db, AuthEventKind::TokenRevoked, Some(claims.sub), None, Some(&device), Some("session")).await;
+    Ok(Status::NoContent)
+}
+
+// Everything held about the user, as a JSON file
+#[get("/account/export")]
+pub async fn export_account(claims: AuthClaims, mut db: Connection<Db>) -> Result<AccountExport, AccountError> {
+    if claims.is_delegated() {
+        return Err(AccountError::Forbidden);
+    }
+    db.export_account(claims.sub, claims.sid).await
+}
+
+// Every token of the account stops working right away, its messages stay without an author
+#[delete("/account", format = "json", data = "<deletion_request>")]
+pub async fn delete_account(deletion_request: models::AccountDeletionRequest<'_>, claims: AuthClaims, device: DeviceInfo, config: &State<AuthConfig>, mut db: Connection<Db>) -> Result<Status, AccountError> {
+    if claims.is_delegated() {
+        return Err(AccountError::Forbidden);
+    }
+    db.delete_account(config, claims.sub, claims.sid, deletion_request.password, &device).await?;
+    Ok(Status::NoContent)
+}
+
+#[get("/invites")]
+pub async fn get_invites(_claims: Require<Developer>, mut db: Connection<Db>) -> Result<Json<Vec<Invite>>, InviteError> {
+    db.get_invites().await.map(Json)
+}
+
+// The code is only returned here, it can't be looked up later
+#[post("/invites", format = "json", data = "<invite_request>")]
+pub async fn create_invite(invite_request: models::InviteRequest, claims: Require<Developer>, mut db: Connection<Db>) -> Result<NewInvite, InviteError> {
+    db.create_invite(claims.sub, &invite_request).await
+}
+
+#[delete("/invites/<id>")]
+pub async fn revoke_invite(id: models::UUIDWrapper, _claims: Require<Developer>, mut db: Connection<Db>) -> Result<Status, InviteError> {
+    db.revoke_invite(id.into()).await?;
+    Ok(Status::NoContent)
+}
+
+#[get("/invites/<id>/uses")]
+pub async fn get_invite_uses(id: models::UUIDWrapper, _claims: Require<Developer>, mut db: Connection<Db>) -> Result<Json<Vec<InviteUse>>, InviteError> {
+    db.get_invite_uses(id.into()).await.map(Json)
+}
+
+// Newest first, pass the id of the last event as `before` to get the next page
+#[get("/audit/events?<query..>")]
+pub async fn get_auth_events(query: models::AuthEventQuery<'_>, _claims: Require<Developer>, mut db: Connection<Db>) -> Result<Json<Vec<AuthEvent>>, AuditError> {
+    db.get_auth_events(&query).await.map(Json)
+}
+
 #[get("/ping")]
 fn ping(claims: models::AuthClaims) -> String {
     format!("{:?}", claims)
 }
 
+#[cfg(test)]
+mod tests {
+    use rocket::http::{ContentType, Header, Status};
+    use rocket::local::asynchronous::Client;
+    use rocket::serde::json::{json, Value};
+
+    use super::*;
+
+    const PASSWORD: &str = "correct horse battery staple";
+
+    // The whole app against the `chat_app` database from Rocket.toml or `ROCKET_DATABASES`, migrations applied
+    async fn app() -> Client {
+        Client::tracked(crate::rocket()).await.unwrap()
+    }
+
+    // Registers a user with a name of its own, returns the token response
+    async fn register(client: &Client) -> Value {
+        let username = format!("test{}", &uuid::Uuid::new_v4().simple().to_string()[..12]);
+        let response = client.post("/auth/register")
+            .header(ContentType::JSON)
+            .body(json!({ "username": username, "password": PASSWORD }).to_string())
+            .dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        response.into_json::<Value>().await.unwrap()
+    }
+
+    async fn refresh(client: &Client, refresh_token: &Value) -> (Status, Option<Value>) {
+        let response = client.post("/auth/refresh")
+            .header(ContentType::JSON)
+            .body(json!({ "refresh_token": refresh_token }).to_string())
+            .dispatch().await;
+        let status = response.status();
+        (status, response.into_json::<Value>().await.filter(|_| status == Status::Ok))
+    }
+
+    async fn ping(client: &Client, access_token: &Value) -> Status {
+        client.get("/auth/ping")
+            .header(Header::new("Authorization", format!("BEARER {}", access_token.as_str().unwrap())))
+            .dispatch().await
+            .status()
+    }
+
+    #[rocket::async_test]
+    #[ignore = "needs the chat_app database"]
+    async fn reused_refresh_tokens_end_their_session() {
+        let client = app().await;
+        let first = register(&client).await;
+        let (status, second) = refresh(&client, &first["refresh_token"]).await;
+        assert_eq!(status, Status::Ok);
+        let second = second.unwrap();
+        assert_ne!(second["refresh_token"], first["refresh_token"]);
+
+        // the rotated token coming back means one of the two copies was stolen
+        assert_eq!(refresh(&client, &first["refresh_token"]).await.0, Status::Unauthorized);
+        assert_eq!(refresh(&client, &second["refresh_token"]).await.0, Status::Unauthorized);
+        assert_eq!(ping(&client, &second["access_token"]).await, Status::Unauthorized);
+    }
+
+    #[rocket::async_test]
+    #[ignore = "needs the chat_app database"]
+    async fn logout_revokes_the_tokens_it_was_called_with() {
+        let client = app().await;
+        let token = register(&client).await;
+        let someone_else = register(&client).await;
+        assert_eq!(ping(&client, &token["access_token"]).await, Status::Ok);
+
+        let response = client.post("/auth/logout")
+            .header(Header::new("Authorization", format!("BEARER {}", token["access_token"].as_str().unwrap())))
+            .dispatch().await;
+        assert_eq!(response.status(), Status::NoContent);
+
+        // the access token stops working before it expires
+        assert_eq!(ping(&client, &token["access_token"]).await, Status::Unauthorized);
+        assert_eq!(refresh(&client, &token["refresh_token"]).await.0, Status::Unauthorized);
+        assert_eq!(ping(&client, &someone_else["access_token"]).await, Status::Ok);
+    }
+
+    #[test]
+    fn redirect_parameters_are_appended_and_encoded() {
+        let params = || vec![("code", "a b&c".to_string())];
+        assert_eq!(redirect_uri_with("https://app.example/cb", params(), None), "https://app.example/cb?code=a%20b%26c");
+        // registered URIs may carry a query of their own
+        assert_eq!(
+            redirect_uri_with("https://app.example/cb?app=1", params(), Some("x=y")),
+            "https://app.example/cb?app=1&code=a%20b%26c&state=x%3Dy"
+        );
+    }
+}
@@ -1,5 +1,3 @@
 mod auth;
 
-use std::fmt::Display;
-
 pub(crate) use auth::Auth;
\ No newline at end of file
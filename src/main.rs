@@ -3,7 +3,10 @@ extern crate core;
 extern crate rocket;
 
 use crate::database::PostgreSQLDatabase;
+use crate::chat::ChatService;
 use crate::endpoints::Auth;
+use crate::oidc::MockIdp;
+use crate::webauthn::SoftwareAuthenticator;
 
 
 pub mod schema;
@@ -11,13 +14,18 @@ mod models;
 mod chat;
 mod endpoints;
 mod database;
+mod notifier;
+mod oidc;
+mod policy;
+mod webauthn;
 
 #[launch]
 fn rocket() -> _ {
     rocket::build()
         .attach_database()
         .mount_auth("/auth")
-//        .mount_auth_service("/auth")
-//        .mount_chat_service("/chat")
+        .mount_mock_idp("/mock-idp")
+        .mount_software_authenticator("/mock-authenticator")
+        .mount_chat_service("/chat")
 }
 
@@ -0,0 +1,13 @@
+use rocket::serde::Deserialize;
+
+use crate::impl_from_data_json_for;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct AccountDeletionRequest<'a> {
+    // required when the account has one, accounts without one need a login from the last few minutes instead
+    #[serde(default)]
+    pub password: Option<&'a str>,
+}
+
+impl_from_data_json_for!(AccountDeletionRequest<'a>);
@@ -0,0 +1,70 @@
+use rocket::http::Header;
+use rocket::response::Responder;
+use rocket::serde::json::Json;
+use rocket::serde::Serialize;
+
+use crate::models::{AuthEvent, Bot, MemberRole, Message, OAuthClient, Passkey, Permission, PersonalAccessToken, Session};
+
+// Everything spiritbox holds about a user, secrets only show up as whether they are set
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct AccountExport {
+    pub exported_at: chrono::DateTime<chrono::Utc>,
+    pub user: ExportedUser,
+    pub memberships: Vec<ExportedMembership>,
+    // channels the user is banned from
+    pub bans: Vec<uuid::Uuid>,
+    pub messages: Vec<Message>,
+    pub sessions: Vec<Session>,
+    pub personal_access_tokens: Vec<PersonalAccessToken>,
+    // base64url, the keys of `/auth/challenge/login`
+    pub public_keys: Vec<String>,
+    pub passkeys: Vec<Passkey>,
+    pub bots: Vec<Bot>,
+    pub oauth_clients: Vec<OAuthClient>,
+    pub identities: Vec<ExportedIdentity>,
+    // logins, registrations and tokens of the account from the audit log
+    pub auth_events: Vec<AuthEvent>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct ExportedUser {
+    pub id: uuid::Uuid,
+    pub name: String,
+    pub permissions: Vec<Permission>,
+    pub has_password: bool,
+    pub totp_enabled: bool,
+    pub email: Option<String>,
+    // waiting for its verification code
+    pub pending_email: Option<String>,
+    // the invite the account was registered with
+    pub invite_id: Option<uuid::Uuid>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct ExportedMembership {
+    pub channel_id: uuid::Uuid,
+    pub channel_name: String,
+    pub role: MemberRole,
+}
+
+// An account at an identity provider the user logs in with
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct ExportedIdentity {
+    pub provider: String,
+    pub subject: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+// sent as a file download rather than a plain JSON response
+impl<'r> Responder<'r, 'static> for AccountExport {
+    fn respond_to(self, request: &'r rocket::Request<'_>) -> rocket::response::Result<'static> {
+        let filename = format!("spiritbox-export-{}.json", self.user.id);
+        let mut response = Json(self).respond_to(request)?;
+        response.set_header(Header::new("Content-Disposition", format!("attachment; filename=\"{}\"", filename)));
+        Ok(response)
+    }
+}
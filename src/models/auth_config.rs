@@ -0,0 +1,330 @@
+use std::collections::HashMap;
+
+use rocket::serde::Deserialize;
+
+// read from the `auth` table of Rocket.toml, every key is optional
+#[derive(Deserialize, Debug, Clone)]
+#[serde(crate = "rocket::serde", default)]
+pub struct AuthConfig {
+    pub argon2: Argon2Config,
+    pub jwt: JwtConfig,
+    pub lockout: LockoutConfig,
+    // seconds
+    pub access_token_ttl: u32,
+    pub refresh_token_ttl: u32,
+    pub mfa_token_ttl: u32,
+    pub password_reset_ttl: u32,
+    // least time between two reset tokens sent for the same user
+    pub password_reset_cooldown: u32,
+    pub magic_link_ttl: u32,
+    // how recent the login has to be for accounts without a password to be deleted
+    pub reauthentication_window: u32,
+    // least time between two login links sent for the same user
+    pub magic_link_cooldown: u32,
+    pub email_verification_ttl: u32,
+    // least time between two verification codes sent for the same user or to the same address
+    pub email_verification_cooldown: u32,
+    // frontend page login links point to, it posts the `token` query parameter to `/auth/magic-link/login`
+    pub magic_link_url: String,
+    // OAuth2 authorization codes, the client exchanges them right after the redirect
+    pub authorization_code_ttl: u32,
+    // shown by authenticator apps next to the account name
+    pub totp_issuer: String,
+    // where password reset tokens, login links and email verification codes are delivered, required in release builds
+    pub notifier: Option<NotifierConfig>,
+    // external identity providers, keyed by the name used in their URLs
+    pub oidc: HashMap<String, OidcProviderConfig>,
+    // seconds the user has to come back from the identity provider
+    pub oidc_login_ttl: u32,
+    // what `/auth/register` accepts as username and password
+    pub registration: RegistrationConfig,
+    // attributes of the cookies set for browser clients that log in with `?cookie=true`
+    pub cookie: CookieConfig,
+    // passkey logins, see `WebAuthnConfig`
+    pub webauthn: WebAuthnConfig,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            argon2: Argon2Config::default(),
+            jwt: JwtConfig::default(),
+            lockout: LockoutConfig::default(),
+            access_token_ttl: 60 * 60,
+            refresh_token_ttl: 30 * 24 * 60 * 60,
+            mfa_token_ttl: 5 * 60,
+            password_reset_ttl: 15 * 60,
+            password_reset_cooldown: 60,
+            magic_link_ttl: 10 * 60,
+            reauthentication_window: 5 * 60,
+            magic_link_cooldown: 60,
+            email_verification_ttl: 15 * 60,
+            email_verification_cooldown: 60,
+            magic_link_url: "http://localhost:8000/magic-link".to_string(),
+            authorization_code_ttl: 60,
+            totp_issuer: "spiritbox".to_string(),
+            notifier: None,
+            oidc: HashMap::new(),
+            oidc_login_ttl: 10 * 60,
+            registration: RegistrationConfig::default(),
+            cookie: CookieConfig::default(),
+            webauthn: WebAuthnConfig::default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(crate = "rocket::serde", default)]
+pub struct JwtConfig {
+    // kid new tokens are signed with, the first key when omitted
+    pub signing_kid: Option<String>,
+    // every listed key is accepted for verification, so a retired key stays here until its tokens expire
+    pub keys: Vec<JwtKeyConfig>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct JwtKeyConfig {
+    pub kid: String,
+    #[serde(default)]
+    pub algorithm: JwtAlgorithm,
+    // HS512 only
+    pub secret: Option<String>,
+    // PEM, EdDSA and RS256 only; a key with just a public key can verify but never sign
+    pub private_key: Option<String>,
+    pub public_key: Option<String>,
+}
+
+impl std::fmt::Debug for JwtKeyConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JwtKeyConfig")
+            .field("kid", &self.kid)
+            .field("algorithm", &self.algorithm)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(crate = "rocket::serde")]
+pub enum JwtAlgorithm {
+    // shared secret, fine as long as spiritbox is the only service verifying its tokens
+    #[default]
+    HS512,
+    EdDSA,
+    RS256,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(crate = "rocket::serde", default)]
+pub struct Argon2Config {
+    // KiB
+    pub memory_cost: u32,
+    pub time_cost: u32,
+    pub parallelism: u32,
+}
+
+impl Default for Argon2Config {
+    // OWASP recommended minimum for Argon2id
+    fn default() -> Self {
+        Self {
+            memory_cost: 19 * 1024,
+            time_cost: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(crate = "rocket::serde", default)]
+pub struct LockoutConfig {
+    // failures before the first lockout, per username and per client IP
+    pub max_attempts: u32,
+    // an IP is shared by everyone behind the same NAT, so it gets more room
+    pub ip_max_attempts: u32,
+    // seconds; the lockout doubles with every failure past the threshold, up to `max_lockout`
+    pub base_lockout: u32,
+    pub max_lockout: u32,
+    // seconds without failures after which the counter starts over
+    pub reset_after: u32,
+}
+
+impl Default for LockoutConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            ip_max_attempts: 50,
+            base_lockout: 30,
+            max_lockout: 60 * 60,
+            reset_after: 24 * 60 * 60,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(crate = "rocket::serde", tag = "kind", rename_all = "lowercase")]
+pub enum NotifierConfig {
+    // only for local testing, notifications end up in the server log
+    Log,
+    // appends one JSON object per line
+    File { path: std::path::PathBuf },
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct OidcProviderConfig {
+    // the discovery document is read from `{issuer}/.well-known/openid-configuration`
+    pub issuer: String,
+    pub client_id: String,
+    // sent with the code exchange when set
+    pub client_secret: Option<String>,
+    // page of the frontend the provider sends the user back to, it posts `code` and `state` to the callback
+    pub redirect_uri: String,
+    #[serde(default = "OidcProviderConfig::default_scopes")]
+    pub scopes: String,
+    // ID token claim new accounts are named after
+    #[serde(default = "OidcProviderConfig::default_username_claim")]
+    pub username_claim: String,
+}
+
+impl OidcProviderConfig {
+    fn default_scopes() -> String {
+        "openid profile".to_string()
+    }
+
+    fn default_username_claim() -> String {
+        "preferred_username".to_string()
+    }
+}
+
+impl std::fmt::Debug for OidcProviderConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OidcProviderConfig")
+            .field("issuer", &self.issuer)
+            .field("client_id", &self.client_id)
+            .field("redirect_uri", &self.redirect_uri)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(crate = "rocket::serde", default)]
+pub struct CookieConfig {
+    // only for local testing over plain HTTP, browsers drop secure cookies there
+    pub secure: bool,
+    pub same_site: CookieSameSite,
+}
+
+impl Default for CookieConfig {
+    fn default() -> Self {
+        Self {
+            secure: true,
+            same_site: CookieSameSite::default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum CookieSameSite {
+    // the session doesn't survive following a link from another site
+    #[default]
+    Strict,
+    // for frontends that are opened through links from elsewhere, the CSRF token still protects writes
+    Lax,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(crate = "rocket::serde", default)]
+pub struct WebAuthnConfig {
+    // the domain passkeys are bound to, the host of the frontend or a parent domain of it; never an IP address
+    pub rp_id: String,
+    // shown by authenticators when a passkey is created
+    pub rp_name: String,
+    // where the frontend is served from, ceremonies coming from any other origin are rejected
+    pub origins: Vec<String>,
+    // seconds the user has to answer the authenticator prompt
+    pub challenge_ttl: u32,
+    // PIN or biometrics on top of presence, a passkey login skips TOTP so this makes it two factors
+    pub user_verification: bool,
+}
+
+impl Default for WebAuthnConfig {
+    fn default() -> Self {
+        Self {
+            rp_id: "localhost".to_string(),
+            rp_name: "spiritbox".to_string(),
+            origins: vec!["http://localhost:8000".to_string()],
+            challenge_ttl: 5 * 60,
+            user_verification: true,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(crate = "rocket::serde", default)]
+pub struct RegistrationConfig {
+    // `/auth/register` only accepts invite codes minted by developers, identity providers can't create accounts either
+    pub invite_only: bool,
+    pub username: UsernamePolicyConfig,
+    // also applies to password changes and resets
+    pub password: PasswordPolicyConfig,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(crate = "rocket::serde", default)]
+pub struct UsernamePolicyConfig {
+    // characters after NFKC normalization, 32 at most
+    pub min_length: usize,
+    pub max_length: usize,
+    pub charset: UsernameCharset,
+    // matched case-insensitively and against look-alikes, so `Adm1n` is taken as well
+    pub reserved: Vec<String>,
+}
+
+impl Default for UsernamePolicyConfig {
+    fn default() -> Self {
+        Self {
+            min_length: 3,
+            max_length: 32,
+            charset: UsernameCharset::default(),
+            reserved: ["admin", "administrator", "root", "system", "spiritbox", "support", "moderator", "staff", "security", "deleted", "anonymous"]
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum UsernameCharset {
+    // ASCII letters, digits, `_`, `-` and `.`
+    Ascii,
+    // letters and digits of any script, as long as a single name doesn't mix scripts, plus `_`, `-` and `.`
+    #[default]
+    Unicode,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(crate = "rocket::serde", default)]
+pub struct PasswordPolicyConfig {
+    // characters
+    pub min_length: usize,
+    // hashing gets slow for huge inputs, no real password is this long
+    pub max_length: usize,
+    // estimated bits, repeated and sequential characters barely count
+    pub min_entropy: f64,
+    // one password per line, `#` starts a comment; matched case-insensitively
+    pub breached_passwords: Option<std::path::PathBuf>,
+}
+
+impl Default for PasswordPolicyConfig {
+    fn default() -> Self {
+        Self {
+            min_length: 8,
+            max_length: 256,
+            min_entropy: 45.0,
+            breached_passwords: None,
+        }
+    }
+}
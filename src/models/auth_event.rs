@@ -0,0 +1,20 @@
+use rocket::serde::Serialize;
+use rocket_db_pools::diesel::Queryable;
+
+use crate::models::AuthEventKind;
+
+// An entry of the authentication audit log
+#[derive(Serialize, Queryable, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct AuthEvent {
+    // increasing, pass the last one as `before` for the next page
+    pub id: i64,
+    pub kind: AuthEventKind,
+    pub user_id: Option<uuid::Uuid>,
+    pub username: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    // how it happened, e.g. `password`, `2fa` or `refresh`
+    pub detail: Option<String>,
+    pub occurred_at: chrono::DateTime<chrono::Utc>,
+}
@@ -0,0 +1,20 @@
+use diesel_derive_enum::DbEnum;
+use rocket::serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize, DbEnum, FromFormField)]
+#[ExistingTypePath = "crate::schema::sql_types::AuthEventKind"]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum AuthEventKind {
+    #[field(value = "login_succeeded")]
+    LoginSucceeded,
+    // wrong credentials, a wrong second factor and attempts during a lockout
+    #[field(value = "login_failed")]
+    LoginFailed,
+    #[field(value = "registered")]
+    Registered,
+    // access tokens of logins, refreshes and OAuth clients, and personal access tokens
+    #[field(value = "token_issued")]
+    TokenIssued,
+    #[field(value = "token_revoked")]
+    TokenRevoked,
+}
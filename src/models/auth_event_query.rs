@@ -0,0 +1,17 @@
+use crate::models::AuthEventKind;
+
+// Filters of the audit log, newest events first
+#[derive(FromForm, Debug, Clone, PartialEq)]
+pub struct AuthEventQuery<'r> {
+    pub kind: Option<AuthEventKind>,
+    pub user_id: Option<&'r str>,
+    pub username: Option<&'r str>,
+    pub ip: Option<&'r str>,
+    // RFC 3339 timestamps, both inclusive
+    pub since: Option<&'r str>,
+    pub until: Option<&'r str>,
+    // only events with a smaller id, the cursor of the next page
+    pub before: Option<i64>,
+    // 50 when missing, 500 at most
+    pub limit: Option<u32>,
+}
@@ -0,0 +1,15 @@
+// Query of both the consent lookup and the decision, the consent page passes it on unchanged
+#[derive(FromForm, Debug, Clone, PartialEq)]
+pub struct AuthorizationRequest<'r> {
+    // only `code` is supported
+    pub response_type: &'r str,
+    pub client_id: &'r str,
+    pub redirect_uri: &'r str,
+    // space separated permission names
+    pub scope: &'r str,
+    // echoed back in the redirect
+    pub state: Option<&'r str>,
+    // base64url SHA-256 of the code verifier, `plain` is not accepted
+    pub code_challenge: &'r str,
+    pub code_challenge_method: &'r str,
+}
@@ -0,0 +1,25 @@
+use rocket::serde::Serialize;
+
+use crate::impl_responder_json_for;
+use crate::models::Permission;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct Bot {
+    pub id: uuid::Uuid,
+    pub name: String,
+    // what the bot may do at most, its owner's permissions cap it further
+    pub permissions: Vec<Permission>,
+}
+
+// returned on creation and when the token is regenerated, only the hash of `token` is stored
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct NewBot {
+    pub token: String,
+    #[serde(flatten)]
+    pub bot: Bot,
+}
+
+impl_responder_json_for!(Bot);
+impl_responder_json_for!(NewBot);
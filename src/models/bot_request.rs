@@ -0,0 +1,14 @@
+use rocket::serde::Deserialize;
+
+use crate::impl_from_data_json_for;
+use crate::models::Permission;
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct BotRequest<'a> {
+    pub name: &'a str,
+    // every one of them has to be held by the owner
+    pub permissions: Vec<Permission>,
+}
+
+impl_from_data_json_for!(BotRequest<'a>);
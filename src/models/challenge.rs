@@ -0,0 +1,13 @@
+use rocket::serde::Serialize;
+
+use crate::impl_responder_json_for;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct Challenge {
+    // base64url, sign it with the registered key before `expires_in` seconds pass
+    pub nonce: String,
+    pub expires_in: u32,
+}
+
+impl_responder_json_for!(Challenge);
@@ -0,0 +1,12 @@
+use rocket::serde::Deserialize;
+
+use crate::impl_from_data_json_for;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct ChallengeRequest<'a> {
+    pub username: &'a str,
+    pub public_key: &'a str,
+}
+
+impl_from_data_json_for!(ChallengeRequest<'a>);
@@ -6,8 +6,8 @@ use crate::models::channel::Channel;
 
 #[derive(Debug, Clone, PartialEq, Serialize)]
 #[serde(crate = "rocket::serde")]
-pub struct Channels<'a>(Vec<Channel<'a>>);
+pub struct Channels(Vec<Channel>);
 
-impl_deserialize_for_vector_wrapper!(Channels<'a>, Channel);
-impl_responder_json_for!(Channels<'a>);
-impl_from_data_json_for!(Channels<'a>);
\ No newline at end of file
+impl_deserialize_for_vector_wrapper!(Channels, Channel);
+impl_responder_json_for!(Channels);
+impl_from_data_json_for!(Channels);
@@ -4,24 +4,25 @@ use rocket_db_pools::diesel::Queryable;
 use crate::{impl_from_data_json_for, impl_responder_json_for};
 use crate::models::Model;
 
+#[allow(dead_code)]
 pub(crate) mod channels;
 pub(crate) mod patch;
 pub(crate) mod insert;
 
-impl<'a> Model for Channel<'a> {
-    type Patch = patch::Patch<'a>;
-    type Insert = insert::Insert<'a>;
-    type Vector = channels::Channels<'a>;
+impl Model for Channel {
+    type Patch = patch::Patch;
+    type Insert = insert::Insert;
+    type Vector = channels::Channels;
 
     fn to_patch(&self) -> Self::Patch {
         Self::Patch {
-            name: Some(self.name)
+            name: Some(self.name.clone())
         }
     }
 
     fn to_insert(&self) -> Self::Insert {
         Self::Insert {
-            name: self.name
+            name: self.name.clone()
         }
     }
 }
@@ -30,10 +31,10 @@ impl<'a> Model for Channel<'a> {
 #[diesel(table_name = crate::schema::channels)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 #[serde(crate = "rocket::serde")]
-pub struct Channel<'a> {
+pub struct Channel {
     pub id: uuid::Uuid,
-    pub name: &'a str,
+    pub name: String,
 }
 
-impl_responder_json_for!(Channel<'a>);
-impl_from_data_json_for!(Channel<'a>);
\ No newline at end of file
+impl_responder_json_for!(Channel);
+impl_from_data_json_for!(Channel);
@@ -11,9 +11,7 @@ impl Model for ChannelBan {
     type Insert = Self;
     type Vector = channel_bans::ChannelBans;
 
-    fn to_patch(&self) -> Self::Patch {
-        ()
-    }
+    fn to_patch(&self) -> Self::Patch {}
 
     fn to_insert(&self) -> Self::Insert {
         self.clone()
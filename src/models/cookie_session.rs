@@ -0,0 +1,134 @@
+use rocket::http::{Cookie, CookieJar, Method, SameSite, Status};
+use rocket::Request;
+use rocket::request::{FromRequest, Outcome};
+use rocket::serde::Serialize;
+
+use crate::database::random_token;
+use crate::impl_responder_json_for;
+use crate::models::{AuthConfig, CookieSameSite, Token};
+
+// the access token, read by `AuthClaims` when there is no Authorization header
+pub const SESSION_COOKIE: &str = "spiritbox_session";
+// only sent to the auth routes, `/auth/refresh/cookie` reads it
+pub const REFRESH_COOKIE: &str = "spiritbox_refresh";
+// readable by the page on purpose, it has to be echoed in `CSRF_HEADER`
+pub const CSRF_COOKIE: &str = "spiritbox_csrf";
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+// Returned instead of the tokens when they went into cookies, the CSRF token is also in its cookie
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct CookieSession {
+    pub csrf_token: String,
+    // seconds until the session cookie has to be refreshed
+    pub expires_in: u32,
+}
+
+impl_responder_json_for!(CookieSession);
+
+impl CookieSession {
+    // `auth_path` is where the auth routes are mounted, the refresh token is never sent anywhere else
+    pub fn start(cookies: &CookieJar<'_>, config: &AuthConfig, auth_path: &str, token: Token) -> Result<Self, ()> {
+        let csrf_token = random_token()?;
+
+        let same_site = match config.cookie.same_site {
+            CookieSameSite::Strict => SameSite::Strict,
+            CookieSameSite::Lax => SameSite::Lax,
+        };
+        let cookie = |name: &'static str, value: String, path: String, ttl: u32, http_only: bool| Cookie::build((name, value))
+            .path(path)
+            .http_only(http_only)
+            .secure(config.cookie.secure)
+            .same_site(same_site)
+            .max_age(rocket::time::Duration::seconds(ttl.into()));
+
+        cookies.add(cookie(SESSION_COOKIE, token.access_token, "/".to_string(), config.access_token_ttl, true));
+        cookies.add(cookie(REFRESH_COOKIE, token.refresh_token, auth_path.to_string(), config.refresh_token_ttl, true));
+        cookies.add(cookie(CSRF_COOKIE, csrf_token.clone(), "/".to_string(), config.refresh_token_ttl, false));
+
+        Ok(Self { csrf_token, expires_in: token.expires_in })
+    }
+
+    pub fn end(cookies: &CookieJar<'_>, auth_path: &str) {
+        cookies.remove(Cookie::build(SESSION_COOKIE).path("/"));
+        cookies.remove(Cookie::build(REFRESH_COOKIE).path(auth_path.to_string()));
+        cookies.remove(Cookie::build(CSRF_COOKIE).path("/"));
+    }
+}
+
+// Double-submit check, another site can make the browser send the cookie but can't read it to set the header
+pub(crate) fn csrf_token_matches(request: &Request<'_>) -> bool {
+    if matches!(request.method(), Method::Get | Method::Head | Method::Options) {
+        return true;
+    }
+    let (Some(cookie), Some(header)) = (request.cookies().get(CSRF_COOKIE), request.headers().get_one(CSRF_HEADER)) else {
+        return false;
+    };
+    cookie.value().len() == header.len() && openssl::memcmp::eq(cookie.value().as_bytes(), header.as_bytes())
+}
+
+// For routes authenticated by a cookie without going through `AuthClaims`
+pub struct CsrfVerified;
+
+#[async_trait]
+impl<'r> FromRequest<'r> for CsrfVerified {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        if csrf_token_matches(request) {
+            Outcome::Success(CsrfVerified)
+        } else {
+            Outcome::Error((Status::Forbidden, ()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::{Cookie, Header, Status};
+    use rocket::local::asynchronous::{Client, LocalRequest};
+
+    use super::*;
+
+    #[get("/")]
+    fn read(_csrf: CsrfVerified) {}
+
+    #[post("/")]
+    fn write(_csrf: CsrfVerified) {}
+
+    async fn client() -> Client {
+        let figment = rocket::figment::Figment::from(rocket::Config::debug_default())
+            .merge(("log_level", "off"));
+        Client::tracked(rocket::custom(figment).mount("/", routes![read, write])).await.unwrap()
+    }
+
+    async fn status(request: LocalRequest<'_>, cookie: Option<&'static str>, header: Option<&'static str>) -> Status {
+        let request = match cookie {
+            Some(value) => request.cookie(Cookie::new(CSRF_COOKIE, value)),
+            None => request,
+        };
+        let request = match header {
+            Some(value) => request.header(Header::new(CSRF_HEADER, value)),
+            None => request,
+        };
+        request.dispatch().await.status()
+    }
+
+    #[rocket::async_test]
+    async fn writes_need_the_cookie_echoed_in_the_header() {
+        let client = client().await;
+        assert_eq!(status(client.post("/"), Some("token"), Some("token")).await, Status::Ok);
+        assert_eq!(status(client.post("/"), None, None).await, Status::Forbidden);
+        // what a cross-site form post looks like, the browser attaches the cookie on its own
+        assert_eq!(status(client.post("/"), Some("token"), None).await, Status::Forbidden);
+        assert_eq!(status(client.post("/"), None, Some("token")).await, Status::Forbidden);
+        assert_eq!(status(client.post("/"), Some("token"), Some("tokem")).await, Status::Forbidden);
+        assert_eq!(status(client.post("/"), Some("token"), Some("token2")).await, Status::Forbidden);
+    }
+
+    #[rocket::async_test]
+    async fn reads_need_no_csrf_token() {
+        let client = client().await;
+        assert_eq!(status(client.get("/"), None, None).await, Status::Ok);
+    }
+}
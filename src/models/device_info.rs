@@ -0,0 +1,79 @@
+use std::net::IpAddr;
+
+use rocket::Request;
+use rocket::request::{FromRequest, Outcome};
+
+// clients name their device with this header, e.g. "Work laptop"
+pub const DEVICE_NAME_HEADER: &str = "X-Device-Name";
+
+const MAX_DEVICE_NAME_LENGTH: usize = 64;
+const MAX_USER_AGENT_LENGTH: usize = 512;
+
+// Where a login comes from, stored with the session so users can tell their devices apart
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DeviceInfo {
+    pub name: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip: Option<IpAddr>,
+}
+
+#[async_trait]
+impl<'r> FromRequest<'r> for DeviceInfo {
+    type Error = ();
+
+    // never fails, everything in here is up to the client and only shown back to the user
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let header = |name, max_length| request.headers()
+            .get_one(name)
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(|value| value.chars().take(max_length).collect::<String>());
+
+        Outcome::Success(DeviceInfo {
+            name: header(DEVICE_NAME_HEADER, MAX_DEVICE_NAME_LENGTH),
+            user_agent: header("User-Agent", MAX_USER_AGENT_LENGTH),
+            // the peer address unless `ip_header` names a header set by a trusted proxy
+            ip: request.client_ip(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::Header;
+    use rocket::local::asynchronous::Client;
+
+    use super::*;
+
+    #[get("/")]
+    fn client_ip(device: DeviceInfo) -> String {
+        device.ip.map(|ip| ip.to_string()).unwrap_or_default()
+    }
+
+    // The address the lockout sees for a request claiming to come from 203.0.113.7
+    async fn ip_with_header(ip_header: rocket::figment::value::Value) -> String {
+        let figment = rocket::figment::Figment::from(rocket::Config::debug_default())
+            .merge(("log_level", "off"))
+            .merge(("ip_header", ip_header));
+        let client = Client::tracked(rocket::custom(figment).mount("/", routes![client_ip])).await.unwrap();
+        client.get("/")
+            .header(Header::new("X-Real-IP", "203.0.113.7"))
+            .remote("192.0.2.1:4000".parse().unwrap())
+            .dispatch().await
+            .into_string().await
+            .unwrap()
+    }
+
+    #[test]
+    fn client_ip_headers_are_not_trusted_by_default() {
+        let config = rocket::Config::from(rocket::Config::figment());
+        assert!(config.ip_header.is_none());
+    }
+
+    #[rocket::async_test]
+    async fn ip_header_is_only_honoured_when_configured() {
+        // anyone could send the header and spread their failed logins over made up addresses
+        assert_eq!(ip_with_header(false.into()).await, "192.0.2.1");
+        assert_eq!(ip_with_header("X-Real-IP".into()).await, "203.0.113.7");
+    }
+}
@@ -0,0 +1,11 @@
+use rocket::serde::Deserialize;
+
+use crate::impl_from_data_json_for;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct EmailRequest<'a> {
+    pub email: &'a str,
+}
+
+impl_from_data_json_for!(EmailRequest<'a>);
@@ -0,0 +1,12 @@
+use rocket::serde::Deserialize;
+
+use crate::impl_from_data_json_for;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct EmailVerificationRequest<'a> {
+    // as delivered to the new address
+    pub code: &'a str,
+}
+
+impl_from_data_json_for!(EmailVerificationRequest<'a>);
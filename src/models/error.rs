@@ -4,17 +4,29 @@ use rocket::serde::json::Json;
 use serde::ser::SerializeStruct;
 use serde::Serialize;
 
+use crate::models::{Permission, PolicyViolation};
+
 #[macro_export]
 macro_rules! impl_responder_for_error_type {
     ($struct_name:ident) => {
         #[async_trait]
         impl<'r> Responder<'r, 'static> for $struct_name {
             fn respond_to(self, request: &'r rocket::Request<'_>) -> rocket::response::Result<'static> {
-                (self.status(), Json(self.message())).respond_to(request)
+                let retry_after = self.retry_after();
+                let mut response = if self.violations().is_empty() {
+                    (self.status(), Json(self.message())).respond_to(request)?
+                } else {
+                    let body = rocket::serde::json::json!({ "message": self.message(), "errors": self.violations() });
+                    (self.status(), Json(body)).respond_to(request)?
+                };
+                if let Some(seconds) = retry_after {
+                    response.set_raw_header("Retry-After", seconds.to_string());
+                }
+                Ok(response)
             }
         }
 
-        impl<'r> Serialize for $struct_name {
+        impl Serialize for $struct_name {
             fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
             where
             S: serde::Serializer {
@@ -29,7 +41,17 @@ macro_rules! impl_responder_for_error_type {
         #[async_trait]
         impl<'r> Responder<'r, 'static> for $struct_name<'lr> {
             fn respond_to(self, request: &'r rocket::Request<'_>) -> rocket::response::Result<'static> {
-                (self.status(), Json(self.message())).respond_to(request)
+                let retry_after = self.retry_after();
+                let mut response = if self.violations().is_empty() {
+                    (self.status(), Json(self.message())).respond_to(request)?
+                } else {
+                    let body = rocket::serde::json::json!({ "message": self.message(), "errors": self.violations() });
+                    (self.status(), Json(body)).respond_to(request)?
+                };
+                if let Some(seconds) = retry_after {
+                    response.set_raw_header("Retry-After", seconds.to_string());
+                }
+                Ok(response)
             }
         }
 
@@ -48,17 +70,46 @@ macro_rules! impl_responder_for_error_type {
 pub trait Error<'a> {
     fn message(&'a self) -> &'a str;
     fn status(&self) -> Status;
+    // seconds, sent as the Retry-After header
+    fn retry_after(&self) -> Option<u64> {
+        None
+    }
+    // sent next to the message as `errors`, one entry per broken rule
+    fn violations(&self) -> &[PolicyViolation] {
+        &[]
+    }
 }
 
 
 impl_responder_for_error_type!(LoginError);
 impl_responder_for_error_type!(RegisterError);
+impl_responder_for_error_type!(RefreshError);
+impl_responder_for_error_type!(RevocationError);
+impl_responder_for_error_type!(PublicKeyError);
+impl_responder_for_error_type!(MfaError);
+impl_responder_for_error_type!(PasswordError);
+impl_responder_for_error_type!(LockoutError);
+impl_responder_for_error_type!(PermissionError);
+impl_responder_for_error_type!(PermissionDenied);
+impl_responder_for_error_type!(AccessTokenError);
+impl_responder_for_error_type!(BotError);
+impl_responder_for_error_type!(ChannelError);
+impl_responder_for_error_type!(OAuthError);
+impl_responder_for_error_type!(OidcError);
+impl_responder_for_error_type!(SessionError);
+impl_responder_for_error_type!(InviteError);
+impl_responder_for_error_type!(AccountError);
+impl_responder_for_error_type!(AuditError);
+impl_responder_for_error_type!(PasskeyError);
+impl_responder_for_error_type!(MagicLinkError);
+impl_responder_for_error_type!(EmailError);
 
 
 
 pub enum LoginError {
     InternalServerError,
     Unauthorized,
+    TooManyAttempts { retry_after: u64 },
 }
 
 impl Error<'_> for LoginError {
@@ -66,6 +117,7 @@ impl Error<'_> for LoginError {
         match self {
             LoginError::InternalServerError => "Internal Server Error",
             LoginError::Unauthorized => "Invalid credentials",
+            LoginError::TooManyAttempts { .. } => "Too many failed login attempts, try again later",
         }
     }
 
@@ -73,6 +125,14 @@ impl Error<'_> for LoginError {
         match self {
             LoginError::InternalServerError => Status::InternalServerError,
             LoginError::Unauthorized => Status::Unauthorized,
+            LoginError::TooManyAttempts { .. } => Status::TooManyRequests,
+        }
+    }
+
+    fn retry_after(&self) -> Option<u64> {
+        match self {
+            LoginError::TooManyAttempts { retry_after } => Some(*retry_after),
+            _ => None,
         }
     }
 }
@@ -80,6 +140,9 @@ impl Error<'_> for LoginError {
 pub enum RegisterError {
     InternalServerError,
     Conflict,
+    Rejected(Vec<PolicyViolation>),
+    InviteRequired,
+    InvalidInvite,
 }
 
 impl Error<'_> for crate::models::error::RegisterError {
@@ -87,6 +150,9 @@ impl Error<'_> for crate::models::error::RegisterError {
         match self {
             crate::models::error::RegisterError::InternalServerError => "Internal Server Error",
             crate::models::error::RegisterError::Conflict => "User already exists",
+            crate::models::error::RegisterError::Rejected(_) => "Username or password rejected by the registration policy",
+            crate::models::error::RegisterError::InviteRequired => "Registration requires an invite code",
+            crate::models::error::RegisterError::InvalidInvite => "Invalid, expired or used up invite code",
         }
     }
 
@@ -94,7 +160,752 @@ impl Error<'_> for crate::models::error::RegisterError {
         match self {
             crate::models::error::RegisterError::InternalServerError => Status::InternalServerError,
             crate::models::error::RegisterError::Conflict => Status::Conflict,
+            crate::models::error::RegisterError::Rejected(_) => Status::UnprocessableEntity,
+            crate::models::error::RegisterError::InviteRequired => Status::Forbidden,
+            crate::models::error::RegisterError::InvalidInvite => Status::Forbidden,
+        }
+    }
+
+    fn violations(&self) -> &[PolicyViolation] {
+        match self {
+            crate::models::error::RegisterError::Rejected(violations) => violations,
+            _ => &[],
+        }
+    }
+}
+
+pub enum RefreshError {
+    InternalServerError,
+    Unauthorized,
+}
+
+impl Error<'_> for RefreshError {
+    fn message(&'_ self) -> &'_ str {
+        match self {
+            RefreshError::InternalServerError => "Internal Server Error",
+            RefreshError::Unauthorized => "Invalid or expired refresh token",
+        }
+    }
+
+    fn status(&self) -> Status {
+        match self {
+            RefreshError::InternalServerError => Status::InternalServerError,
+            RefreshError::Unauthorized => Status::Unauthorized,
+        }
+    }
+}
+
+pub enum RevocationError {
+    InternalServerError,
+}
+
+impl Error<'_> for RevocationError {
+    fn message(&'_ self) -> &'_ str {
+        match self {
+            RevocationError::InternalServerError => "Internal Server Error",
+        }
+    }
+
+    fn status(&self) -> Status {
+        match self {
+            RevocationError::InternalServerError => Status::InternalServerError,
+        }
+    }
+}
+
+pub enum PublicKeyError {
+    InternalServerError,
+    Forbidden,
+    InvalidKey,
+    NotFound,
+    Conflict,
+}
+
+impl Error<'_> for PublicKeyError {
+    fn message(&'_ self) -> &'_ str {
+        match self {
+            PublicKeyError::InternalServerError => "Internal Server Error",
+            PublicKeyError::Forbidden => "Public keys can only be managed after a login",
+            PublicKeyError::InvalidKey => "Public key must be a base64url encoded Ed25519 key",
+            PublicKeyError::NotFound => "Public key is not registered",
+            PublicKeyError::Conflict => "Public key is already registered",
+        }
+    }
+
+    fn status(&self) -> Status {
+        match self {
+            PublicKeyError::InternalServerError => Status::InternalServerError,
+            PublicKeyError::Forbidden => Status::Forbidden,
+            PublicKeyError::InvalidKey => Status::BadRequest,
+            PublicKeyError::NotFound => Status::NotFound,
+            PublicKeyError::Conflict => Status::Conflict,
+        }
+    }
+}
+
+pub enum MfaError {
+    InternalServerError,
+    InvalidCode,
+    NotEnrolled,
+    AlreadyEnabled,
+    Forbidden,
+    TooManyAttempts { retry_after: u64 },
+}
+
+// for `check_reauthentication`, an unknown user means the token outlived its account
+impl From<LoginError> for MfaError {
+    fn from(err: LoginError) -> Self {
+        match err {
+            LoginError::TooManyAttempts { retry_after } => MfaError::TooManyAttempts { retry_after },
+            LoginError::Unauthorized => MfaError::InvalidCode,
+            LoginError::InternalServerError => MfaError::InternalServerError,
+        }
+    }
+}
+
+impl Error<'_> for MfaError {
+    fn message(&'_ self) -> &'_ str {
+        match self {
+            MfaError::InternalServerError => "Internal Server Error",
+            MfaError::InvalidCode => "Invalid code",
+            MfaError::NotEnrolled => "Two-factor authentication is not set up",
+            MfaError::AlreadyEnabled => "Two-factor authentication is already enabled",
+            MfaError::Forbidden => "Two-factor authentication can only be managed after a login",
+            MfaError::TooManyAttempts { .. } => "Too many failed attempts, try again later",
+        }
+    }
+
+    fn status(&self) -> Status {
+        match self {
+            MfaError::InternalServerError => Status::InternalServerError,
+            MfaError::InvalidCode => Status::Unauthorized,
+            MfaError::NotEnrolled => Status::NotFound,
+            MfaError::AlreadyEnabled => Status::Conflict,
+            MfaError::Forbidden => Status::Forbidden,
+            MfaError::TooManyAttempts { .. } => Status::TooManyRequests,
+        }
+    }
+
+    fn retry_after(&self) -> Option<u64> {
+        match self {
+            MfaError::TooManyAttempts { retry_after } => Some(*retry_after),
+            _ => None,
+        }
+    }
+}
+
+pub enum PasswordError {
+    InternalServerError,
+    Forbidden,
+    Unauthorized,
+    InvalidToken,
+    NoPassword,
+    TooManyAttempts { retry_after: u64 },
+    Rejected(Vec<PolicyViolation>),
+}
+
+// for `check_reauthentication`, an unknown user means the token outlived its account
+impl From<LoginError> for PasswordError {
+    fn from(err: LoginError) -> Self {
+        match err {
+            LoginError::TooManyAttempts { retry_after } => PasswordError::TooManyAttempts { retry_after },
+            LoginError::Unauthorized => PasswordError::Unauthorized,
+            LoginError::InternalServerError => PasswordError::InternalServerError,
+        }
+    }
+}
+
+// for queries inside a transaction, which needs its error type to take the database's
+impl From<diesel::result::Error> for PasswordError {
+    fn from(_: diesel::result::Error) -> Self {
+        PasswordError::InternalServerError
+    }
+}
+
+impl Error<'_> for PasswordError {
+    fn message(&'_ self) -> &'_ str {
+        match self {
+            PasswordError::InternalServerError => "Internal Server Error",
+            PasswordError::Forbidden => "The password can only be changed after a login",
+            PasswordError::Unauthorized => "Wrong password",
+            PasswordError::InvalidToken => "Invalid or expired reset token",
+            PasswordError::NoPassword => "The account has no password yet, request a password reset to set one",
+            PasswordError::TooManyAttempts { .. } => "Too many failed attempts, try again later",
+            PasswordError::Rejected(_) => "New password rejected by the password policy",
+        }
+    }
+
+    fn status(&self) -> Status {
+        match self {
+            PasswordError::InternalServerError => Status::InternalServerError,
+            PasswordError::Forbidden => Status::Forbidden,
+            PasswordError::Unauthorized => Status::Unauthorized,
+            PasswordError::InvalidToken => Status::Unauthorized,
+            PasswordError::NoPassword => Status::Conflict,
+            PasswordError::TooManyAttempts { .. } => Status::TooManyRequests,
+            PasswordError::Rejected(_) => Status::UnprocessableEntity,
+        }
+    }
+
+    fn retry_after(&self) -> Option<u64> {
+        match self {
+            PasswordError::TooManyAttempts { retry_after } => Some(*retry_after),
+            _ => None,
+        }
+    }
+
+    fn violations(&self) -> &[PolicyViolation] {
+        match self {
+            PasswordError::Rejected(violations) => violations,
+            _ => &[],
+        }
+    }
+}
+
+pub enum LockoutError {
+    InternalServerError,
+    NotFound,
+}
+
+impl Error<'_> for LockoutError {
+    fn message(&'_ self) -> &'_ str {
+        match self {
+            LockoutError::InternalServerError => "Internal Server Error",
+            LockoutError::NotFound => "No failed login attempts recorded",
+        }
+    }
+
+    fn status(&self) -> Status {
+        match self {
+            LockoutError::InternalServerError => Status::InternalServerError,
+            LockoutError::NotFound => Status::NotFound,
+        }
+    }
+}
+
+pub enum PermissionError {
+    InternalServerError,
+    NotFound,
+}
+
+impl Error<'_> for PermissionError {
+    fn message(&'_ self) -> &'_ str {
+        match self {
+            PermissionError::InternalServerError => "Internal Server Error",
+            PermissionError::NotFound => "User not found",
+        }
+    }
+
+    fn status(&self) -> Status {
+        match self {
+            PermissionError::InternalServerError => Status::InternalServerError,
+            PermissionError::NotFound => Status::NotFound,
+        }
+    }
+}
+
+// A valid token without the permission the route needs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PermissionDenied(pub Permission);
+
+impl Error<'_> for PermissionDenied {
+    fn message(&'_ self) -> &'_ str {
+        self.0.denied_message()
+    }
+
+    fn status(&self) -> Status {
+        Status::Forbidden
+    }
+}
+
+pub enum AccessTokenError {
+    InternalServerError,
+    Forbidden,
+    NotFound,
+    Conflict,
+    ExceedsPermissions,
+    InvalidName,
+}
+
+impl Error<'_> for AccessTokenError {
+    fn message(&'_ self) -> &'_ str {
+        match self {
+            AccessTokenError::InternalServerError => "Internal Server Error",
+            AccessTokenError::Forbidden => "Personal access tokens can only be managed after a login",
+            AccessTokenError::NotFound => "Personal access token not found",
+            AccessTokenError::Conflict => "A personal access token with this name already exists",
+            AccessTokenError::ExceedsPermissions => "A personal access token can't have permissions its owner lacks",
+            AccessTokenError::InvalidName => "Name must be between 1 and 64 characters",
+        }
+    }
+
+    fn status(&self) -> Status {
+        match self {
+            AccessTokenError::InternalServerError => Status::InternalServerError,
+            AccessTokenError::Forbidden => Status::Forbidden,
+            AccessTokenError::NotFound => Status::NotFound,
+            AccessTokenError::Conflict => Status::Conflict,
+            AccessTokenError::ExceedsPermissions => Status::Forbidden,
+            AccessTokenError::InvalidName => Status::BadRequest,
+        }
+    }
+}
+
+pub enum BotError {
+    InternalServerError,
+    Forbidden,
+    NotFound,
+    Conflict,
+    ExceedsPermissions,
+    Rejected(Vec<PolicyViolation>),
+}
+
+impl Error<'_> for BotError {
+    fn message(&'_ self) -> &'_ str {
+        match self {
+            BotError::InternalServerError => "Internal Server Error",
+            BotError::Forbidden => "Bots can only be managed by their owner after a login",
+            BotError::NotFound => "Bot not found",
+            BotError::Conflict => "User already exists",
+            BotError::ExceedsPermissions => "A bot can't have permissions its owner lacks",
+            BotError::Rejected(_) => "Bot name rejected by the username policy",
+        }
+    }
+
+    fn status(&self) -> Status {
+        match self {
+            BotError::InternalServerError => Status::InternalServerError,
+            BotError::Forbidden => Status::Forbidden,
+            BotError::NotFound => Status::NotFound,
+            BotError::Conflict => Status::Conflict,
+            BotError::ExceedsPermissions => Status::Forbidden,
+            BotError::Rejected(_) => Status::UnprocessableEntity,
+        }
+    }
+
+    fn violations(&self) -> &[PolicyViolation] {
+        match self {
+            BotError::Rejected(violations) => violations,
+            _ => &[],
+        }
+    }
+}
+
+pub enum ChannelError {
+    InternalServerError,
+    NotFound,
+    Forbidden,
+    Conflict,
+    MissingPermission(Permission),
+}
+
+impl From<PermissionDenied> for ChannelError {
+    fn from(denied: PermissionDenied) -> Self {
+        ChannelError::MissingPermission(denied.0)
+    }
+}
+
+impl Error<'_> for ChannelError {
+    fn message(&'_ self) -> &'_ str {
+        match self {
+            ChannelError::InternalServerError => "Internal Server Error",
+            ChannelError::NotFound => "Not found",
+            ChannelError::Forbidden => "Insufficient channel role",
+            ChannelError::Conflict => "Already exists",
+            ChannelError::MissingPermission(permission) => permission.denied_message(),
+        }
+    }
+
+    fn status(&self) -> Status {
+        match self {
+            ChannelError::InternalServerError => Status::InternalServerError,
+            ChannelError::NotFound => Status::NotFound,
+            ChannelError::Forbidden => Status::Forbidden,
+            ChannelError::Conflict => Status::Conflict,
+            ChannelError::MissingPermission(_) => Status::Forbidden,
+        }
+    }
+}
+
+pub enum OAuthError {
+    InternalServerError,
+    Forbidden,
+    NotFound,
+    InvalidName,
+    InvalidRedirectUri,
+    UnsupportedResponseType,
+    InvalidCodeChallenge,
+    InvalidScope,
+}
+
+impl Error<'_> for OAuthError {
+    fn message(&'_ self) -> &'_ str {
+        match self {
+            OAuthError::InternalServerError => "Internal Server Error",
+            OAuthError::Forbidden => "OAuth clients and grants can only be managed after a login",
+            OAuthError::NotFound => "OAuth client not found",
+            OAuthError::InvalidName => "Name must be between 1 and 64 characters",
+            OAuthError::InvalidRedirectUri => "Redirect URI is not registered for this client",
+            OAuthError::UnsupportedResponseType => "Only the `code` response type is supported",
+            OAuthError::InvalidCodeChallenge => "A S256 code challenge is required",
+            OAuthError::InvalidScope => "Scope must list permissions other than `developer`",
+        }
+    }
+
+    fn status(&self) -> Status {
+        match self {
+            OAuthError::InternalServerError => Status::InternalServerError,
+            OAuthError::Forbidden => Status::Forbidden,
+            OAuthError::NotFound => Status::NotFound,
+            OAuthError::InvalidName => Status::BadRequest,
+            OAuthError::InvalidRedirectUri => Status::BadRequest,
+            OAuthError::UnsupportedResponseType => Status::BadRequest,
+            OAuthError::InvalidCodeChallenge => Status::BadRequest,
+            OAuthError::InvalidScope => Status::BadRequest,
+        }
+    }
+}
+
+// Errors of the token and revocation endpoints, shaped as RFC 6749 section 5.2 asks
+pub enum OAuthTokenError {
+    InternalServerError,
+    InvalidRequest,
+    InvalidClient,
+    InvalidGrant,
+    UnsupportedGrantType,
+}
+
+impl OAuthTokenError {
+    // what clients match on, the message is for humans
+    pub fn code(&self) -> &'static str {
+        match self {
+            OAuthTokenError::InternalServerError => "server_error",
+            OAuthTokenError::InvalidRequest => "invalid_request",
+            OAuthTokenError::InvalidClient => "invalid_client",
+            OAuthTokenError::InvalidGrant => "invalid_grant",
+            OAuthTokenError::UnsupportedGrantType => "unsupported_grant_type",
+        }
+    }
+}
+
+// for queries inside a transaction, which needs its error type to take the database's
+impl From<diesel::result::Error> for OAuthTokenError {
+    fn from(_: diesel::result::Error) -> Self {
+        OAuthTokenError::InternalServerError
+    }
+}
+
+impl Error<'_> for OAuthTokenError {
+    fn message(&'_ self) -> &'_ str {
+        match self {
+            OAuthTokenError::InternalServerError => "Internal Server Error",
+            OAuthTokenError::InvalidRequest => "A parameter of this grant type is missing",
+            OAuthTokenError::InvalidClient => "Client authentication failed",
+            OAuthTokenError::InvalidGrant => "Invalid, expired or already used grant",
+            OAuthTokenError::UnsupportedGrantType => "Only `authorization_code` and `refresh_token` grants are supported",
+        }
+    }
+
+    fn status(&self) -> Status {
+        match self {
+            OAuthTokenError::InternalServerError => Status::InternalServerError,
+            OAuthTokenError::InvalidRequest => Status::BadRequest,
+            OAuthTokenError::InvalidClient => Status::Unauthorized,
+            OAuthTokenError::InvalidGrant => Status::BadRequest,
+            OAuthTokenError::UnsupportedGrantType => Status::BadRequest,
+        }
+    }
+}
+
+#[async_trait]
+impl<'r> Responder<'r, 'static> for OAuthTokenError {
+    fn respond_to(self, request: &'r rocket::Request<'_>) -> rocket::response::Result<'static> {
+        let body = rocket::serde::json::json!({
+            "error": self.code(),
+            "error_description": self.message(),
+        });
+        (self.status(), Json(body)).respond_to(request)
+    }
+}
+
+pub enum OidcError {
+    InternalServerError,
+    NotFound,
+    InvalidState,
+    ProviderUnavailable,
+    InvalidIdToken,
+    RegistrationClosed,
+    // no free name was found that the policy accepts
+    Rejected(Vec<PolicyViolation>),
+}
+
+impl Error<'_> for OidcError {
+    fn message(&'_ self) -> &'_ str {
+        match self {
+            OidcError::InternalServerError => "Internal Server Error",
+            OidcError::NotFound => "Identity provider not found",
+            OidcError::InvalidState => "Invalid or expired login state",
+            OidcError::ProviderUnavailable => "Identity provider could not be reached",
+            OidcError::InvalidIdToken => "Identity provider returned an invalid ID token",
+            OidcError::RegistrationClosed => "Registration requires an invite code, new accounts can't be created through an identity provider",
+            OidcError::Rejected(_) => "No username could be found for the account that the registration policy accepts",
+        }
+    }
+
+    fn status(&self) -> Status {
+        match self {
+            OidcError::InternalServerError => Status::InternalServerError,
+            OidcError::NotFound => Status::NotFound,
+            OidcError::InvalidState => Status::Unauthorized,
+            OidcError::ProviderUnavailable => Status::BadGateway,
+            OidcError::InvalidIdToken => Status::Unauthorized,
+            OidcError::RegistrationClosed => Status::Forbidden,
+            OidcError::Rejected(_) => Status::UnprocessableEntity,
+        }
+    }
+
+    fn violations(&self) -> &[PolicyViolation] {
+        match self {
+            OidcError::Rejected(violations) => violations,
+            _ => &[],
+        }
+    }
+}
+
+pub enum SessionError {
+    InternalServerError,
+    Forbidden,
+    NotFound,
+}
+
+impl Error<'_> for SessionError {
+    fn message(&'_ self) -> &'_ str {
+        match self {
+            SessionError::InternalServerError => "Internal Server Error",
+            SessionError::Forbidden => "Sessions can only be managed after a login",
+            SessionError::NotFound => "Session not found",
+        }
+    }
+
+    fn status(&self) -> Status {
+        match self {
+            SessionError::InternalServerError => Status::InternalServerError,
+            SessionError::Forbidden => Status::Forbidden,
+            SessionError::NotFound => Status::NotFound,
+        }
+    }
+}
+
+pub enum InviteError {
+    InternalServerError,
+    NotFound,
+    InvalidMaxUses,
+}
+
+impl Error<'_> for InviteError {
+    fn message(&'_ self) -> &'_ str {
+        match self {
+            InviteError::InternalServerError => "Internal Server Error",
+            InviteError::NotFound => "Invite not found",
+            InviteError::InvalidMaxUses => "An invite has to allow at least one use",
+        }
+    }
+
+    fn status(&self) -> Status {
+        match self {
+            InviteError::InternalServerError => Status::InternalServerError,
+            InviteError::NotFound => Status::NotFound,
+            InviteError::InvalidMaxUses => Status::UnprocessableEntity,
+        }
+    }
+}
+
+pub enum AccountError {
+    InternalServerError,
+    Forbidden,
+    Unauthorized,
+    // the account has no password and the session is too old to stand in for one
+    ReauthenticationRequired,
+    OwnsChannels,
+    TooManyAttempts { retry_after: u64 },
+}
+
+impl From<LoginError> for AccountError {
+    fn from(err: LoginError) -> Self {
+        match err {
+            LoginError::TooManyAttempts { retry_after } => AccountError::TooManyAttempts { retry_after },
+            LoginError::Unauthorized => AccountError::Unauthorized,
+            LoginError::InternalServerError => AccountError::InternalServerError,
+        }
+    }
+}
+
+// for queries inside a transaction, which needs its error type to take the database's
+impl From<diesel::result::Error> for AccountError {
+    fn from(_: diesel::result::Error) -> Self {
+        AccountError::InternalServerError
+    }
+}
+
+impl Error<'_> for AccountError {
+    fn message(&'_ self) -> &'_ str {
+        match self {
+            AccountError::InternalServerError => "Internal Server Error",
+            AccountError::Forbidden => "The account can only be exported or deleted after a login",
+            AccountError::Unauthorized => "Wrong password",
+            AccountError::ReauthenticationRequired => "Log in again before deleting the account",
+            AccountError::OwnsChannels => "Transfer or remove the channels you or your bots own before deleting the account",
+            AccountError::TooManyAttempts { .. } => "Too many failed attempts, try again later",
+        }
+    }
+
+    fn status(&self) -> Status {
+        match self {
+            AccountError::InternalServerError => Status::InternalServerError,
+            AccountError::Forbidden => Status::Forbidden,
+            AccountError::Unauthorized => Status::Unauthorized,
+            AccountError::ReauthenticationRequired => Status::Unauthorized,
+            AccountError::OwnsChannels => Status::Conflict,
+            AccountError::TooManyAttempts { .. } => Status::TooManyRequests,
+        }
+    }
+
+    fn retry_after(&self) -> Option<u64> {
+        match self {
+            AccountError::TooManyAttempts { retry_after } => Some(*retry_after),
+            _ => None,
+        }
+    }
+}
+
+pub enum AuditError {
+    InternalServerError,
+    InvalidQuery,
+}
+
+impl Error<'_> for AuditError {
+    fn message(&'_ self) -> &'_ str {
+        match self {
+            AuditError::InternalServerError => "Internal Server Error",
+            AuditError::InvalidQuery => "Invalid user id or timestamp",
+        }
+    }
+
+    fn status(&self) -> Status {
+        match self {
+            AuditError::InternalServerError => Status::InternalServerError,
+            AuditError::InvalidQuery => Status::BadRequest,
+        }
+    }
+}
+
+pub enum PasskeyError {
+    InternalServerError,
+    Forbidden,
+    NotFound,
+    Conflict,
+    InvalidName,
+    // unknown, expired or already answered
+    InvalidChallenge,
+    InvalidCredential,
+    UnsupportedAlgorithm,
+    Unauthorized,
+}
+
+impl Error<'_> for PasskeyError {
+    fn message(&'_ self) -> &'_ str {
+        match self {
+            PasskeyError::InternalServerError => "Internal Server Error",
+            PasskeyError::Forbidden => "Passkeys can only be managed after a login",
+            PasskeyError::NotFound => "Passkey not found",
+            PasskeyError::Conflict => "This passkey is already registered",
+            PasskeyError::InvalidName => "Passkey names must be 1 to 64 characters long",
+            PasskeyError::InvalidChallenge => "The challenge is unknown or has expired, start over",
+            PasskeyError::InvalidCredential => "The authenticator response could not be verified",
+            PasskeyError::UnsupportedAlgorithm => "The authenticator uses an unsupported key type",
+            PasskeyError::Unauthorized => "Invalid credentials",
+        }
+    }
+
+    fn status(&self) -> Status {
+        match self {
+            PasskeyError::InternalServerError => Status::InternalServerError,
+            PasskeyError::Forbidden => Status::Forbidden,
+            PasskeyError::NotFound => Status::NotFound,
+            PasskeyError::Conflict => Status::Conflict,
+            PasskeyError::InvalidName => Status::UnprocessableEntity,
+            PasskeyError::InvalidChallenge => Status::BadRequest,
+            PasskeyError::InvalidCredential => Status::BadRequest,
+            PasskeyError::UnsupportedAlgorithm => Status::UnprocessableEntity,
+            PasskeyError::Unauthorized => Status::Unauthorized,
+        }
+    }
+}
+
+pub enum MagicLinkError {
+    InternalServerError,
+    // unknown, expired or already used
+    InvalidToken,
+}
+
+impl Error<'_> for MagicLinkError {
+    fn message(&'_ self) -> &'_ str {
+        match self {
+            MagicLinkError::InternalServerError => "Internal Server Error",
+            MagicLinkError::InvalidToken => "Invalid or expired login link",
         }
     }
+
+    fn status(&self) -> Status {
+        match self {
+            MagicLinkError::InternalServerError => Status::InternalServerError,
+            MagicLinkError::InvalidToken => Status::Unauthorized,
+        }
+    }
+}
+
+pub enum EmailError {
+    InternalServerError,
+    Forbidden,
+    NotFound,
+    InvalidEmail,
+    // the address of another account
+    Conflict,
+    // wrong, expired or out of attempts
+    InvalidCode,
+    // a code was sent to the user or the address moments ago, or the current one is out of attempts
+    TooManyRequests { retry_after: u64 },
 }
 
+impl Error<'_> for EmailError {
+    fn message(&'_ self) -> &'_ str {
+        match self {
+            EmailError::InternalServerError => "Internal Server Error",
+            EmailError::Forbidden => "The email address can only be changed after a login",
+            EmailError::NotFound => "User not found",
+            EmailError::InvalidEmail => "Invalid email address",
+            EmailError::Conflict => "This email address belongs to another account",
+            EmailError::InvalidCode => "Invalid or expired verification code",
+            EmailError::TooManyRequests { .. } => "A verification code was sent recently, try again later",
+        }
+    }
+
+    fn status(&self) -> Status {
+        match self {
+            EmailError::InternalServerError => Status::InternalServerError,
+            EmailError::Forbidden => Status::Forbidden,
+            EmailError::NotFound => Status::NotFound,
+            EmailError::InvalidEmail => Status::UnprocessableEntity,
+            EmailError::Conflict => Status::Conflict,
+            EmailError::InvalidCode => Status::BadRequest,
+            EmailError::TooManyRequests { .. } => Status::TooManyRequests,
+        }
+    }
+
+    fn retry_after(&self) -> Option<u64> {
+        match self {
+            EmailError::TooManyRequests { retry_after } => Some(*retry_after),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use rocket::serde::Serialize;
+
+use crate::impl_responder_json_for;
+use crate::models::Permission;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct Invite {
+    pub id: uuid::Uuid,
+    // the developer who minted it, missing once their account is gone
+    pub created_by: Option<uuid::Uuid>,
+    // what accounts start with instead of the defaults, the defaults apply when missing
+    pub permissions: Option<Vec<Permission>>,
+    pub max_uses: u32,
+    pub uses: u32,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    // never expires when missing
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub revoked_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+// returned once on creation, only the hash of `code` is stored
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct NewInvite {
+    pub code: String,
+    #[serde(flatten)]
+    pub details: Invite,
+}
+
+// An account that registered with the invite
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct InviteUse {
+    pub user_id: uuid::Uuid,
+    pub username: String,
+    pub used_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl_responder_json_for!(NewInvite);
@@ -0,0 +1,23 @@
+use rocket::serde::Deserialize;
+
+use crate::impl_from_data_json_for;
+use crate::models::Permission;
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct InviteRequest {
+    #[serde(default = "InviteRequest::default_max_uses")]
+    pub max_uses: u32,
+    // seconds, the invite never expires when missing
+    pub expires_in: Option<u32>,
+    // pre-granted to every account registering with the invite, replacing the defaults
+    pub permissions: Option<Vec<Permission>>,
+}
+
+impl InviteRequest {
+    fn default_max_uses() -> u32 {
+        1
+    }
+}
+
+impl_from_data_json_for!(InviteRequest);
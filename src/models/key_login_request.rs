@@ -0,0 +1,14 @@
+use rocket::serde::Deserialize;
+
+use crate::impl_from_data_json_for;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct KeyLoginRequest<'a> {
+    pub username: &'a str,
+    pub public_key: &'a str,
+    // base64url Ed25519 signature over the raw nonce bytes
+    pub signature: &'a str,
+}
+
+impl_from_data_json_for!(KeyLoginRequest<'a>);
@@ -0,0 +1,15 @@
+use rocket::serde::Serialize;
+use rocket_db_pools::diesel::Queryable;
+
+use crate::models::LockoutKind;
+
+// failed login attempts recorded for a username or a client IP
+#[derive(Serialize, Queryable, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct Lockout {
+    pub kind: LockoutKind,
+    pub key: String,
+    pub failures: i32,
+    pub last_failure: chrono::DateTime<chrono::Utc>,
+    pub locked_until: Option<chrono::DateTime<chrono::Utc>>,
+}
@@ -0,0 +1,11 @@
+use diesel_derive_enum::DbEnum;
+use rocket::serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize, DbEnum)]
+#[ExistingTypePath = "crate::schema::sql_types::LockoutKind"]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum LockoutKind {
+    // counted whether or not the user exists, so lockouts don't reveal usernames
+    Username,
+    Ip,
+}
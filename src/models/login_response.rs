@@ -0,0 +1,34 @@
+use serde::ser::SerializeStruct;
+use serde::Serialize;
+
+use crate::impl_responder_json_for;
+use crate::models::{CookieSession, Token};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoginResponse {
+    Token(Token),
+    // the tokens went into cookies, see `CookieSession`
+    Cookie(CookieSession),
+    // password was right, the mfa_token has to be exchanged together with a second factor
+    MfaRequired { mfa_token: String, expires_in: u32 },
+}
+
+impl_responder_json_for!(LoginResponse);
+
+impl Serialize for LoginResponse {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: serde::Serializer {
+        match self {
+            LoginResponse::Token(token) => token.serialize(serializer),
+            LoginResponse::Cookie(session) => session.serialize(serializer),
+            LoginResponse::MfaRequired { mfa_token, expires_in } => {
+                let mut state = serializer.serialize_struct("MfaRequired", 3)?;
+                state.serialize_field("mfa_required", &true)?;
+                state.serialize_field("mfa_token", mfa_token)?;
+                state.serialize_field("expires_in", expires_in)?;
+                state.end()
+            }
+        }
+    }
+}
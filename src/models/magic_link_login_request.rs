@@ -0,0 +1,12 @@
+use rocket::serde::Deserialize;
+
+use crate::impl_from_data_json_for;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct MagicLinkLoginRequest<'a> {
+    // the `token` query parameter of the delivered link
+    pub token: &'a str,
+}
+
+impl_from_data_json_for!(MagicLinkLoginRequest<'a>);
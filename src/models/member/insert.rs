@@ -9,6 +9,7 @@ use crate::models::member::role::MemberRole;
 #[diesel(check_for_backend(diesel::pg::Pg))]
 #[serde(crate = "rocket::serde")]
 pub struct Insert {
+    pub user_id: uuid::Uuid,
     pub role: Option<MemberRole>,
 }
 
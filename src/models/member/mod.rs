@@ -5,6 +5,7 @@ use crate::{impl_from_data_json_for, impl_responder_json_for};
 use crate::models::member::role::MemberRole;
 use crate::models::Model;
 
+#[allow(dead_code)]
 pub(crate) mod members;
 pub(crate) mod patch;
 pub(crate) mod insert;
@@ -23,6 +24,7 @@ impl Model for Member {
 
     fn to_insert(&self) -> Self::Insert {
         Self::Insert {
+            user_id: self.user_id,
             role: Some(self.role)
         }
     }
@@ -7,4 +7,11 @@ pub enum MemberRole {
     Owner,
     Admin,
     Member,
-}
\ No newline at end of file
+}
+
+impl MemberRole {
+    // owners and admins manage the channel and its members
+    pub fn is_moderator(&self) -> bool {
+        matches!(self, MemberRole::Owner | MemberRole::Admin)
+    }
+}
@@ -3,14 +3,13 @@ use rocket::serde::{Deserialize, Serialize};
 
 use crate::impl_from_data_json_for;
 
+// author and channel are taken from the token and the path, not from the body
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Insertable)]
 #[diesel(table_name = crate::schema::messages)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 #[serde(crate = "rocket::serde")]
-pub struct Insert<'a> {
-    pub channel_id: uuid::Uuid,
-    pub content: &'a str,
-    pub user_id: uuid::Uuid,
+pub struct Insert {
+    pub content: String,
 }
 
-impl_from_data_json_for!(Insert<'a>);
\ No newline at end of file
+impl_from_data_json_for!(Insert);
@@ -6,8 +6,8 @@ use crate::models::message::Message;
 
 #[derive(Debug, Clone, PartialEq, Serialize)]
 #[serde(crate = "rocket::serde")]
-pub struct Messages<'a>(Vec<Message<'a>>);
+pub struct Messages(Vec<Message>);
 
-impl_deserialize_for_vector_wrapper!(Messages<'a>, Message);
-impl_responder_json_for!(Messages<'a>);
-impl_from_data_json_for!(Messages<'a>);
\ No newline at end of file
+impl_deserialize_for_vector_wrapper!(Messages, Message);
+impl_responder_json_for!(Messages);
+impl_from_data_json_for!(Messages);
@@ -4,26 +4,25 @@ use rocket_db_pools::diesel::Queryable;
 use crate::{impl_from_data_json_for, impl_responder_json_for};
 use crate::models::Model;
 
+#[allow(dead_code)]
 pub(crate) mod messages;
 pub(crate) mod patch;
 pub(crate) mod insert;
 
-impl<'a> Model for Message<'a> {
-    type Patch = patch::Patch<'a>;
-    type Insert = insert::Insert<'a>;
-    type Vector = messages::Messages<'a>;
+impl Model for Message {
+    type Patch = patch::Patch;
+    type Insert = insert::Insert;
+    type Vector = messages::Messages;
 
     fn to_patch(&self) -> Self::Patch {
         Self::Patch {
-            content: Some(self.content)
+            content: Some(self.content.clone())
         }
     }
 
     fn to_insert(&self) -> Self::Insert {
         Self::Insert {
-            user_id: self.user_id,
-            channel_id: self.channel_id,
-            content: self.content,
+            content: self.content.clone(),
         }
     }
 }
@@ -32,13 +31,14 @@ impl<'a> Model for Message<'a> {
 #[diesel(table_name = crate::schema::messages)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 #[serde(crate = "rocket::serde")]
-pub struct Message<'a> {
+pub struct Message {
     pub id: uuid::Uuid,
-    pub user_id: uuid::Uuid,
+    // None once the author deleted their account
+    pub user_id: Option<uuid::Uuid>,
     pub channel_id: uuid::Uuid,
-    pub content: &'a str,
-    pub created_at: chrono::NaiveDateTime,
+    pub content: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
 }
 
-impl_responder_json_for!(Message<'a>);
-impl_from_data_json_for!(Message<'a>);
\ No newline at end of file
+impl_responder_json_for!(Message);
+impl_from_data_json_for!(Message);
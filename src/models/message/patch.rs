@@ -7,8 +7,8 @@ use crate::impl_from_data_json_for;
 #[diesel(table_name = crate::schema::messages)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 #[serde(crate = "rocket::serde")]
-pub struct Patch<'a> {
-    pub content: Option<&'a str>,
+pub struct Patch {
+    pub content: Option<String>,
 }
 
-impl_from_data_json_for!(Patch<'a>);
\ No newline at end of file
+impl_from_data_json_for!(Patch);
@@ -0,0 +1,12 @@
+use rocket::serde::Deserialize;
+
+use crate::impl_from_data_json_for;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct MfaCodeRequest<'a> {
+    // TOTP code or recovery code
+    pub code: &'a str,
+}
+
+impl_from_data_json_for!(MfaCodeRequest<'a>);
@@ -0,0 +1,13 @@
+use rocket::serde::Deserialize;
+
+use crate::impl_from_data_json_for;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct MfaLoginRequest<'a> {
+    pub mfa_token: &'a str,
+    // TOTP code or recovery code
+    pub code: &'a str,
+}
+
+impl_from_data_json_for!(MfaLoginRequest<'a>);
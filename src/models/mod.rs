@@ -2,14 +2,69 @@ mod user;
 mod channel;
 mod message;
 mod member;
+// not every model has an endpoint yet
+#[allow(dead_code)]
 mod channel_ban;
 mod login_request;
 mod register_request;
+mod refresh_request;
+mod public_key_request;
+mod challenge_request;
+mod key_login_request;
+mod challenge;
+mod login_response;
+mod totp_enrollment;
+mod recovery_codes;
+mod mfa_code_request;
+mod mfa_login_request;
+mod password_change_request;
+mod password_reset_request;
+mod password_reset_confirm_request;
+mod magic_link_request;
+mod magic_link_login_request;
+mod user_email;
+mod email_request;
+mod email_verification_request;
+mod personal_access_token;
+mod personal_access_token_request;
+mod bot;
+mod bot_request;
+mod oauth_client;
+mod oauth_client_request;
+mod authorization_request;
+mod authorization_decision;
+mod oauth_consent;
+mod oauth_redirect;
+mod oauth_token_request;
+mod oauth_revocation_request;
+mod oauth_token;
+mod oidc_callback_request;
+mod session;
+mod invite;
+mod invite_request;
+mod account_deletion_request;
+mod account_export;
+mod passkey;
+mod passkey_options;
+mod passkey_registration_request;
+mod passkey_login_options_request;
+mod passkey_login_request;
+mod device_info;
+mod cookie_session;
 mod token;
 mod uuid;
 mod error;
 mod permissions;
+mod auth_config;
+mod password_algorithm;
+mod lockout;
+mod lockout_kind;
+mod policy_violation;
+mod auth_event;
+mod auth_event_kind;
+mod auth_event_query;
 
+#[allow(dead_code)]
 trait Model {
     type Patch;
     type Insert;
@@ -22,39 +77,132 @@ trait Model {
 // --- exports ---
 
 pub use user::User;
-pub use user::insert::Insert as UserInsert;
-pub use user::patch::Patch as UserPatch;
-pub use user::users::Users;
 
 pub use channel::Channel;
 pub use channel::insert::Insert as ChannelInsert;
 pub use channel::patch::Patch as ChannelPatch;
-pub use channel::channels::Channels;
 
 pub use message::Message;
 pub use message::insert::Insert as MessageInsert;
-pub use message::patch::Patch as MessagePatch;
-pub use message::messages::Messages;
 
 pub use member::Member;
 pub use member::insert::Insert as MemberInsert;
 pub use member::patch::Patch as MemberPatch;
-pub use member::members::Members;
-
-pub use channel_ban::ChannelBan;
-pub use channel_ban::channel_bans::ChannelBans;
+pub use member::role::MemberRole;
 
 pub use login_request::LoginRequest;
 pub use register_request::RegisterRequest;
+pub use refresh_request::RefreshRequest;
+pub use public_key_request::PublicKeyRequest;
+pub use challenge_request::ChallengeRequest;
+pub use key_login_request::KeyLoginRequest;
+pub use challenge::Challenge;
+pub use login_response::LoginResponse;
+pub use totp_enrollment::TotpEnrollment;
+pub use recovery_codes::RecoveryCodes;
+pub use mfa_code_request::MfaCodeRequest;
+pub use mfa_login_request::MfaLoginRequest;
+pub use password_change_request::PasswordChangeRequest;
+pub use password_reset_request::PasswordResetRequest;
+pub use password_reset_confirm_request::PasswordResetConfirmRequest;
+pub use magic_link_request::MagicLinkRequest;
+pub use magic_link_login_request::MagicLinkLoginRequest;
+pub use user_email::UserEmail;
+pub use email_request::EmailRequest;
+pub use email_verification_request::EmailVerificationRequest;
+pub use personal_access_token::PersonalAccessToken;
+pub use personal_access_token::NewPersonalAccessToken;
+pub use personal_access_token_request::PersonalAccessTokenRequest;
+pub use bot::Bot;
+pub use bot::NewBot;
+pub use bot_request::BotRequest;
+pub use oauth_client::OAuthClient;
+pub use oauth_client::NewOAuthClient;
+pub use oauth_client_request::OAuthClientRequest;
+pub use authorization_request::AuthorizationRequest;
+pub use authorization_decision::AuthorizationDecision;
+pub use oauth_consent::OAuthConsent;
+pub use oauth_redirect::OAuthRedirect;
+pub use oauth_token_request::OAuthTokenRequest;
+pub use oauth_revocation_request::OAuthRevocationRequest;
+pub use oauth_token::OAuthToken;
+pub use oidc_callback_request::OidcCallbackRequest;
+pub use session::Session;
+pub use invite::Invite;
+pub use invite::NewInvite;
+pub use invite::InviteUse;
+pub use invite_request::InviteRequest;
+pub use account_deletion_request::AccountDeletionRequest;
+pub use account_export::AccountExport;
+pub use account_export::ExportedUser;
+pub use account_export::ExportedMembership;
+pub use account_export::ExportedIdentity;
+pub use passkey::Passkey;
+pub use passkey_options::PasskeyRegistrationOptions;
+pub use passkey_options::PasskeyLoginOptions;
+pub use passkey_registration_request::PasskeyRegistrationRequest;
+pub use passkey_login_options_request::PasskeyLoginOptionsRequest;
+pub use passkey_login_request::PasskeyLoginRequest;
+pub use device_info::DeviceInfo;
+pub use cookie_session::CookieSession;
+pub use cookie_session::CsrfVerified;
+pub use cookie_session::SESSION_COOKIE;
+pub use cookie_session::REFRESH_COOKIE;
 pub use token::Token;
 pub use uuid::UUIDWrapper;
 
-pub use error::Error;
 pub use error::LoginError;
 pub use error::RegisterError;
+pub use error::RefreshError;
+pub use error::RevocationError;
+pub use error::PublicKeyError;
+pub use error::MfaError;
+pub use error::PasswordError;
+pub use error::LockoutError;
+pub use error::PermissionError;
+pub use error::PermissionDenied;
+pub use error::AccessTokenError;
+pub use error::BotError;
+pub use error::ChannelError;
+pub use error::OAuthError;
+pub use error::OAuthTokenError;
+pub use error::OidcError;
+pub use error::SessionError;
+pub use error::InviteError;
+pub use error::AccountError;
+pub use error::AuditError;
+pub use error::PasskeyError;
+pub use error::MagicLinkError;
+pub use error::EmailError;
 
 pub use permissions::AuthClaims;
 pub use permissions::Permissions;
+pub use permissions::Permission;
+pub use permissions::Require;
+pub use permissions::required;
+pub use permissions::forbidden;
+
+pub use auth_config::AuthConfig;
+pub use auth_config::Argon2Config;
+pub use auth_config::JwtConfig;
+pub use auth_config::JwtKeyConfig;
+pub use auth_config::JwtAlgorithm;
+pub use auth_config::NotifierConfig;
+pub use auth_config::LockoutConfig;
+pub use auth_config::OidcProviderConfig;
+pub use auth_config::RegistrationConfig;
+pub use auth_config::UsernamePolicyConfig;
+pub use auth_config::UsernameCharset;
+pub use auth_config::PasswordPolicyConfig;
+pub use auth_config::CookieSameSite;
+pub use auth_config::WebAuthnConfig;
+pub use password_algorithm::PasswordAlgorithm;
+pub use lockout::Lockout;
+pub use lockout_kind::LockoutKind;
+pub use policy_violation::PolicyViolation;
+pub use auth_event::AuthEvent;
+pub use auth_event_kind::AuthEventKind;
+pub use auth_event_query::AuthEventQuery;
 
 // --- Macros---
 #[macro_export]
@@ -0,0 +1,28 @@
+use rocket::serde::Serialize;
+
+use crate::impl_responder_json_for;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct OAuthClient {
+    // the `client_id` of the authorization and token requests
+    pub id: uuid::Uuid,
+    pub name: String,
+    // codes are only sent to one of these, compared exactly
+    pub redirect_uris: Vec<String>,
+    // confidential clients authenticate with their secret, public ones rely on PKCE alone
+    pub confidential: bool,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+// returned once on registration, only the hash of `client_secret` is stored
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct NewOAuthClient {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>,
+    #[serde(flatten)]
+    pub client: OAuthClient,
+}
+
+impl_responder_json_for!(NewOAuthClient);
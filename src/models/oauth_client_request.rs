@@ -0,0 +1,16 @@
+use rocket::serde::Deserialize;
+
+use crate::impl_from_data_json_for;
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct OAuthClientRequest<'a> {
+    pub name: &'a str,
+    // absolute URIs without a fragment
+    pub redirect_uris: Vec<String>,
+    // false for apps that can't keep a secret, e.g. single page and mobile apps
+    #[serde(default)]
+    pub confidential: bool,
+}
+
+impl_from_data_json_for!(OAuthClientRequest<'a>);
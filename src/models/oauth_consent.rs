@@ -0,0 +1,17 @@
+use rocket::serde::Serialize;
+
+use crate::impl_responder_json_for;
+use crate::models::Permission;
+
+// What the user is asked to approve
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct OAuthConsent {
+    pub client_id: uuid::Uuid,
+    pub client_name: String,
+    pub redirect_uri: String,
+    // requested permissions the user holds, the rest is dropped from the grant
+    pub scope: Vec<Permission>,
+}
+
+impl_responder_json_for!(OAuthConsent);
@@ -0,0 +1,12 @@
+use rocket::serde::Serialize;
+
+use crate::impl_responder_json_for;
+
+// Where the browser goes next, e.g. back to an OAuth client or on to an identity provider
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct OAuthRedirect {
+    pub redirect_to: String,
+}
+
+impl_responder_json_for!(OAuthRedirect);
@@ -0,0 +1,9 @@
+// RFC 7009, both access and refresh tokens are accepted
+#[derive(FromForm, Debug, Clone, PartialEq)]
+pub struct OAuthRevocationRequest<'r> {
+    pub token: &'r str,
+    // ignored, the token is looked up as both kinds
+    pub token_type_hint: Option<&'r str>,
+    pub client_id: Option<&'r str>,
+    pub client_secret: Option<&'r str>,
+}
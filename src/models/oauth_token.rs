@@ -0,0 +1,16 @@
+use rocket::serde::Serialize;
+
+use crate::impl_responder_json_for;
+use crate::models::Token;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct OAuthToken {
+    #[serde(flatten)]
+    pub token: Token,
+    // space separated, only sent when it may differ from the requested scope
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+}
+
+impl_responder_json_for!(OAuthToken);
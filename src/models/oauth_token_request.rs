@@ -0,0 +1,13 @@
+// Form encoded as RFC 6749 asks for, which fields are needed depends on `grant_type`
+#[derive(FromForm, Debug, Clone, PartialEq)]
+pub struct OAuthTokenRequest<'r> {
+    // `authorization_code` or `refresh_token`
+    pub grant_type: &'r str,
+    pub code: Option<&'r str>,
+    pub redirect_uri: Option<&'r str>,
+    pub code_verifier: Option<&'r str>,
+    pub refresh_token: Option<&'r str>,
+    pub client_id: Option<&'r str>,
+    // confidential clients only
+    pub client_secret: Option<&'r str>,
+}
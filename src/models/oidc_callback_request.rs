@@ -0,0 +1,13 @@
+use rocket::serde::Deserialize;
+
+use crate::impl_from_data_json_for;
+
+// Both are taken from the query the identity provider redirected the user with
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct OidcCallbackRequest<'a> {
+    pub code: &'a str,
+    pub state: &'a str,
+}
+
+impl_from_data_json_for!(OidcCallbackRequest<'a>);
@@ -0,0 +1,15 @@
+use rocket::serde::Serialize;
+
+use crate::impl_responder_json_for;
+
+// A WebAuthn credential of the user, the key itself never leaves the authenticator
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct Passkey {
+    pub id: uuid::Uuid,
+    pub name: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl_responder_json_for!(Passkey);
@@ -0,0 +1,12 @@
+use rocket::serde::Deserialize;
+
+use crate::impl_from_data_json_for;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct PasskeyLoginOptionsRequest<'a> {
+    // limits the login to the passkeys of this user, any passkey is accepted when missing
+    pub username: Option<&'a str>,
+}
+
+impl_from_data_json_for!(PasskeyLoginOptionsRequest<'a>);
@@ -0,0 +1,18 @@
+use rocket::serde::Deserialize;
+
+use crate::impl_from_data_json_for;
+
+// The `PublicKeyCredential` returned by `navigator.credentials.get()`, buffers in base64url
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct PasskeyLoginRequest<'a> {
+    pub challenge_id: uuid::Uuid,
+    pub credential_id: &'a str,
+    pub client_data_json: &'a str,
+    pub authenticator_data: &'a str,
+    pub signature: &'a str,
+    // the user id the passkey was created for, checked against the owner of the credential when sent
+    pub user_handle: Option<&'a str>,
+}
+
+impl_from_data_json_for!(PasskeyLoginRequest<'a>);
@@ -0,0 +1,41 @@
+use rocket::serde::Serialize;
+
+use crate::impl_responder_json_for;
+
+// What the frontend hands to `navigator.credentials.create()`, every buffer is base64url
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct PasskeyRegistrationOptions {
+    // sent back with the authenticator's response
+    pub challenge_id: uuid::Uuid,
+    pub challenge: String,
+    pub rp_id: String,
+    pub rp_name: String,
+    // the user handle, authenticators return it on login so the user doesn't have to type a name
+    pub user_id: String,
+    pub user_name: String,
+    // COSE algorithms, most preferred first
+    pub algorithms: Vec<i32>,
+    // passkeys the user already has, the authenticator refuses to create a second one
+    pub exclude_credentials: Vec<String>,
+    // "required" or "preferred"
+    pub user_verification: &'static str,
+    pub expires_in: u32,
+}
+
+impl_responder_json_for!(PasskeyRegistrationOptions);
+
+// What the frontend hands to `navigator.credentials.get()`
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct PasskeyLoginOptions {
+    pub challenge_id: uuid::Uuid,
+    pub challenge: String,
+    pub rp_id: String,
+    // empty without a username, the authenticator offers every passkey it holds for `rp_id`
+    pub allow_credentials: Vec<String>,
+    pub user_verification: &'static str,
+    pub expires_in: u32,
+}
+
+impl_responder_json_for!(PasskeyLoginOptions);
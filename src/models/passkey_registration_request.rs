@@ -0,0 +1,17 @@
+use rocket::serde::Deserialize;
+
+use crate::impl_from_data_json_for;
+
+// The `PublicKeyCredential` returned by `navigator.credentials.create()`, buffers in base64url
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct PasskeyRegistrationRequest<'a> {
+    pub challenge_id: uuid::Uuid,
+    // shown in the passkey list, e.g. "Phone"
+    pub name: &'a str,
+    pub credential_id: &'a str,
+    pub client_data_json: &'a str,
+    pub attestation_object: &'a str,
+}
+
+impl_from_data_json_for!(PasskeyRegistrationRequest<'a>);
@@ -0,0 +1,11 @@
+use diesel_derive_enum::DbEnum;
+use rocket::serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize, DbEnum)]
+#[ExistingTypePath = "crate::schema::sql_types::PasswordAlgorithm"]
+pub enum PasswordAlgorithm {
+    // salt || sha256(salt || password), kept only until the next login
+    Sha256,
+    // PHC string, cost parameters included
+    Argon2id,
+}
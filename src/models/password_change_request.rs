@@ -0,0 +1,12 @@
+use rocket::serde::Deserialize;
+
+use crate::impl_from_data_json_for;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct PasswordChangeRequest<'a> {
+    pub old_password: &'a str,
+    pub new_password: &'a str,
+}
+
+impl_from_data_json_for!(PasswordChangeRequest<'a>);
@@ -0,0 +1,13 @@
+use rocket::serde::Deserialize;
+
+use crate::impl_from_data_json_for;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct PasswordResetConfirmRequest<'a> {
+    // as delivered by the notifier
+    pub token: &'a str,
+    pub new_password: &'a str,
+}
+
+impl_from_data_json_for!(PasswordResetConfirmRequest<'a>);
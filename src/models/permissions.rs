@@ -1,16 +1,36 @@
+use std::marker::PhantomData;
+use std::ops::Deref;
+
 use rocket::http::Status;
 use rocket::Request;
-use rocket::request::{FromRequest, Outcome};
+use rocket::request::{FromParam, FromRequest, Outcome};
+use rocket::serde::json::Json;
 use rocket::serde::{Deserialize, Serialize};
 use rocket_db_pools::Connection;
-use crate::database::auth::verify_login_token;
+use crate::database::access_tokens::{AccessTokenDatabase, ACCESS_TOKEN_PREFIX};
+use crate::database::auth::{AuthDatabase, JwtKeys, verify_login_token};
+use crate::database::sessions::SessionDatabase;
 use crate::database::Db;
+use crate::models::{PermissionDenied, SESSION_COOKIE};
+use crate::models::cookie_session::csrf_token_matches;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AuthClaims {
-    pub sub: String,
+    pub sub: uuid::Uuid,
     pub perms: Permissions,
     pub exp: usize,
+    pub jti: uuid::Uuid,
+    // must match users.token_generation, bumping it revokes every token of the user
+    pub gen: i32,
+    // set when the request came with a personal access token instead of a JWT
+    #[serde(skip)]
+    pub access_token: Option<uuid::Uuid>,
+    // the OAuth client the token was issued to, its permissions are capped by the consented scope
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cid: Option<uuid::Uuid>,
+    // the session the token belongs to, personal access tokens have none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<uuid::Uuid>,
 }
 
 #[async_trait]
@@ -18,17 +38,51 @@ impl<'r> FromRequest<'r> for AuthClaims {
     type Error = ();
 
     async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
-        println!("TRY");
-        let token = request.headers().get_one("Authorization");
-        match token {
-            Some(token) => {
-                let token = token.trim_start_matches("BEARER ");
-                match verify_login_token(token) {
-                    Ok(user) => Outcome::Success(user),
-                    Err(_) => Outcome::Error((Status::Unauthorized, ())),
-                }
-            }
-            None => Outcome::Error((Status::Unauthorized, ())),
+        let Some(keys) = request.rocket().state::<JwtKeys>() else {
+            return Outcome::Error((Status::InternalServerError, ()));
+        };
+        // API clients send the header, browser clients in cookie mode the session cookie
+        let token = match request.headers().get_one("Authorization") {
+            Some(token) => token.trim_start_matches("BEARER "),
+            None => match request.cookies().get(SESSION_COOKIE) {
+                // the browser attaches the cookie to requests other sites make, so writes need the CSRF token as well
+                Some(_) if !csrf_token_matches(request) => return Outcome::Error((Status::Forbidden, ())),
+                Some(cookie) => cookie.value(),
+                None => return Outcome::Error((Status::Unauthorized, ())),
+            },
+        };
+
+        let mut db = match request.guard::<Connection<Db>>().await {
+            Outcome::Success(db) => db,
+            _ => return Outcome::Error((Status::InternalServerError, ())),
+        };
+
+        // personal access tokens are looked up instead of verified, deleting the row revokes them
+        if token.starts_with(ACCESS_TOKEN_PREFIX) {
+            return match db.authenticate_access_token(token).await {
+                Ok(Some(claims)) => Outcome::Success(claims),
+                Ok(None) => Outcome::Error((Status::Unauthorized, ())),
+                Err(_) => Outcome::Error((Status::InternalServerError, ())),
+            };
+        }
+
+        let Ok(claims) = verify_login_token(keys, token) else {
+            return Outcome::Error((Status::Unauthorized, ()));
+        };
+        match db.is_token_revoked(&claims).await {
+            Ok(false) => {}
+            Ok(true) => return Outcome::Error((Status::Unauthorized, ())),
+            Err(_) => return Outcome::Error((Status::InternalServerError, ())),
+        }
+
+        // revoking a session ends its access tokens right away, not only its refresh tokens
+        let Some(session_id) = claims.sid else {
+            return Outcome::Success(claims);
+        };
+        match db.touch_session(session_id).await {
+            Ok(true) => Outcome::Success(claims),
+            Ok(false) => Outcome::Error((Status::Unauthorized, ())),
+            Err(_) => Outcome::Error((Status::InternalServerError, ())),
         }
     }
 }
@@ -53,7 +107,10 @@ macro_rules! get_bit {
     ($val:expr, $n:expr) => { $val & (1 << $n) == (1 << $n) };
 }
 
+// `new` and the bool getters predate `Permission`, not every bit is read through them
+#[allow(dead_code)]
 impl Permissions {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         developer: bool,
         identify: bool,
@@ -84,6 +141,18 @@ impl Permissions {
         )
     }
 
+    pub fn from_bits(bits: i32) -> Self { Self(bits) }
+    pub fn bits(&self) -> i32 { self.0 }
+
+    pub fn has(&self, permission: Permission) -> bool { get_bit!(&self.0, permission as i32) }
+    pub fn with(self, permission: Permission) -> Self { Self(self.0 | (1 << permission as i32)) }
+    pub fn without(self, permission: Permission) -> Self { Self(self.0 & !(1 << permission as i32)) }
+    pub fn intersection(self, other: Permissions) -> Self { Self(self.0 & other.0) }
+
+    pub fn granted(&self) -> Vec<Permission> {
+        Permission::ALL.iter().copied().filter(|permission| self.has(*permission)).collect()
+    }
+
     pub fn developer(&self) -> bool { get_bit!(&self.0, 0) }
     pub fn identify(&self) -> bool { get_bit!(&self.0, 1) }
     pub fn get_channels(&self) -> bool { get_bit!(&self.0, 2) }
@@ -96,4 +165,182 @@ impl Permissions {
     pub fn kick_members(&self) -> bool { get_bit!(&self.0, 9) }
     pub fn ban_members(&self) -> bool { get_bit!(&self.0, 10) }
     pub fn modify_members(&self) -> bool { get_bit!(&self.0, 11) }
-}
\ No newline at end of file
+}
+
+impl FromIterator<Permission> for Permissions {
+    fn from_iter<T: IntoIterator<Item = Permission>>(iter: T) -> Self {
+        iter.into_iter().fold(Permissions(0), Permissions::with)
+    }
+}
+
+macro_rules! define_permissions {
+    ($($(#[$marker:meta])* $variant:ident = $bit:literal => $name:literal),* $(,)?) => {
+        // A single bit of `Permissions`, the discriminant is the bit index
+        #[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+        #[serde(crate = "rocket::serde", rename_all = "snake_case")]
+        pub enum Permission {
+            $($variant = $bit,)*
+        }
+
+        impl Permission {
+            pub const ALL: &'static [Permission] = &[$(Permission::$variant,)*];
+
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(Permission::$variant => $name,)*
+                }
+            }
+
+            pub fn denied_message(&self) -> &'static str {
+                match self {
+                    $(Permission::$variant => concat!("Missing permission `", $name, "`"),)*
+                }
+            }
+        }
+
+        // Marker types for `Require`, one per permission
+        pub mod required {
+            $(
+                $(#[$marker])*
+                pub struct $variant;
+
+                impl super::RequiredPermission for $variant {
+                    const PERMISSION: super::Permission = super::Permission::$variant;
+                }
+            )*
+        }
+    };
+}
+
+// Attributes go on the marker type, for permissions no route takes as a guard
+define_permissions! {
+    Developer = 0 => "developer",
+    // nothing checks it yet
+    #[allow(dead_code)]
+    Identify = 1 => "identify",
+    GetChannels = 2 => "get_channels",
+    // leaving and kicking share a route, it checks them through `AuthClaims::require`
+    #[allow(dead_code)]
+    JoinLeaveChannels = 3 => "join_leave_channels",
+    ModifyCreateDeleteChannels = 4 => "modify_create_delete_channels",
+    SeeOtherUsers = 5 => "see_other_users",
+    SeeMessages = 6 => "see_messages",
+    ModifyCreateDeleteMessages = 7 => "modify_create_delete_messages",
+    AddMembers = 8 => "add_members",
+    #[allow(dead_code)]
+    KickMembers = 9 => "kick_members",
+    // there are no ban routes yet
+    #[allow(dead_code)]
+    BanMembers = 10 => "ban_members",
+    ModifyMembers = 11 => "modify_members",
+}
+
+impl<'a> FromParam<'a> for Permission {
+    type Error = &'a str;
+
+    fn from_param(param: &'a str) -> Result<Self, Self::Error> {
+        Permission::ALL.iter()
+            .find(|permission| permission.name() == param)
+            .copied()
+            .ok_or(param)
+    }
+}
+
+pub trait RequiredPermission {
+    const PERMISSION: Permission;
+}
+
+// Valid token that also carries `P`, rejected with 403 otherwise; derefs to the claims
+pub struct Require<P: RequiredPermission> {
+    pub claims: AuthClaims,
+    permission: PhantomData<P>,
+}
+
+impl<P: RequiredPermission> Deref for Require<P> {
+    type Target = AuthClaims;
+
+    fn deref(&self) -> &Self::Target {
+        &self.claims
+    }
+}
+
+#[async_trait]
+impl<'r, P: RequiredPermission> FromRequest<'r> for Require<P> {
+    type Error = PermissionDenied;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let claims = match request.guard::<AuthClaims>().await {
+            Outcome::Success(claims) => claims,
+            Outcome::Error((status, _)) => return Outcome::Error((status, PermissionDenied(P::PERMISSION))),
+            Outcome::Forward(status) => return Outcome::Forward(status),
+        };
+        match claims.require(P::PERMISSION) {
+            Ok(()) => Outcome::Success(Require { claims, permission: PhantomData }),
+            Err(denied) => {
+                // picked up by the `forbidden` catcher, guard errors never reach the response otherwise
+                request.local_cache(|| Some(denied.0));
+                Outcome::Error((Status::Forbidden, denied))
+            }
+        }
+    }
+}
+
+impl AuthClaims {
+    // For routes whose permission depends on the request, e.g. leaving a channel versus kicking someone
+    pub fn require(&self, permission: Permission) -> Result<(), PermissionDenied> {
+        if self.perms.has(permission) {
+            Ok(())
+        } else {
+            warn!("User {} is missing permission {}", self.sub, permission.name());
+            Err(PermissionDenied(permission))
+        }
+    }
+
+    // Tokens handed to other programs must not manage the credentials of the account
+    pub fn is_delegated(&self) -> bool {
+        self.access_token.is_some() || self.cid.is_some()
+    }
+}
+
+#[catch(403)]
+pub fn forbidden(request: &Request<'_>) -> (Status, Json<String>) {
+    let message = match request.local_cache(|| None::<Permission>) {
+        Some(permission) => permission.denied_message(),
+        None => "Forbidden",
+    };
+    (Status::Forbidden, Json(message.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claims(access_token: Option<uuid::Uuid>, cid: Option<uuid::Uuid>) -> AuthClaims {
+        AuthClaims {
+            sub: uuid::Uuid::new_v4(),
+            perms: Permissions::from_bits(0),
+            exp: usize::MAX,
+            jti: uuid::Uuid::new_v4(),
+            gen: 0,
+            access_token,
+            cid,
+            sid: None,
+        }
+    }
+
+    #[test]
+    fn access_tokens_and_oauth_tokens_are_delegated() {
+        assert!(!claims(None, None).is_delegated());
+        assert!(claims(Some(uuid::Uuid::new_v4()), None).is_delegated());
+        assert!(claims(None, Some(uuid::Uuid::new_v4())).is_delegated());
+    }
+
+    #[test]
+    fn intersection_keeps_what_both_hold() {
+        let held = [Permission::GetChannels, Permission::SeeMessages].into_iter().collect::<Permissions>();
+        let asked = [Permission::SeeMessages, Permission::BanMembers].into_iter().collect::<Permissions>();
+        assert_eq!(held.intersection(asked).granted(), vec![Permission::SeeMessages]);
+        assert_eq!(held.intersection(Permissions::from_bits(0)), Permissions::from_bits(0));
+        assert_eq!(held.intersection(Permissions::from_bits(!0)), held);
+    }
+}
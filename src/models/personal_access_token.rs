@@ -0,0 +1,27 @@
+use rocket::serde::Serialize;
+
+use crate::impl_responder_json_for;
+use crate::models::Permission;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct PersonalAccessToken {
+    pub id: uuid::Uuid,
+    pub name: String,
+    pub permissions: Vec<Permission>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    // never expires when missing
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+// returned once on creation, only the hash of `token` is stored
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct NewPersonalAccessToken {
+    pub token: String,
+    #[serde(flatten)]
+    pub details: PersonalAccessToken,
+}
+
+impl_responder_json_for!(NewPersonalAccessToken);
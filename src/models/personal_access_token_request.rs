@@ -0,0 +1,16 @@
+use rocket::serde::Deserialize;
+
+use crate::impl_from_data_json_for;
+use crate::models::Permission;
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct PersonalAccessTokenRequest<'a> {
+    pub name: &'a str,
+    // every one of them has to be held by the owner
+    pub permissions: Vec<Permission>,
+    // seconds, the token never expires when missing
+    pub expires_in: Option<u32>,
+}
+
+impl_from_data_json_for!(PersonalAccessTokenRequest<'a>);
@@ -0,0 +1,78 @@
+use rocket::serde::Serialize;
+use serde::ser::SerializeStruct;
+
+// A rule of the registration policy the submitted username or password breaks
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PolicyViolation {
+    UsernameTooShort,
+    UsernameTooLong,
+    UsernameInvalidCharacters,
+    UsernameMixedScripts,
+    UsernameReserved,
+    UsernameConfusable,
+    PasswordTooShort,
+    PasswordTooLong,
+    PasswordTooWeak,
+    PasswordBreached,
+    PasswordContainsUsername,
+}
+
+impl PolicyViolation {
+    // the request field the violation is about
+    pub fn field(&self) -> &'static str {
+        match self {
+            PolicyViolation::UsernameTooShort
+            | PolicyViolation::UsernameTooLong
+            | PolicyViolation::UsernameInvalidCharacters
+            | PolicyViolation::UsernameMixedScripts
+            | PolicyViolation::UsernameReserved
+            | PolicyViolation::UsernameConfusable => "username",
+            PolicyViolation::PasswordTooShort
+            | PolicyViolation::PasswordTooLong
+            | PolicyViolation::PasswordTooWeak
+            | PolicyViolation::PasswordBreached
+            | PolicyViolation::PasswordContainsUsername => "password",
+        }
+    }
+
+    // stable identifier for clients, the message is for humans
+    pub fn code(&self) -> &'static str {
+        match self {
+            PolicyViolation::UsernameTooShort | PolicyViolation::PasswordTooShort => "too_short",
+            PolicyViolation::UsernameTooLong | PolicyViolation::PasswordTooLong => "too_long",
+            PolicyViolation::UsernameInvalidCharacters => "invalid_characters",
+            PolicyViolation::UsernameMixedScripts => "mixed_scripts",
+            PolicyViolation::UsernameReserved => "reserved",
+            PolicyViolation::UsernameConfusable => "confusable",
+            PolicyViolation::PasswordTooWeak => "too_weak",
+            PolicyViolation::PasswordBreached => "breached",
+            PolicyViolation::PasswordContainsUsername => "contains_username",
+        }
+    }
+
+    pub fn message(&self) -> &'static str {
+        match self {
+            PolicyViolation::UsernameTooShort => "Username is too short",
+            PolicyViolation::UsernameTooLong => "Username is too long",
+            PolicyViolation::UsernameInvalidCharacters => "Username contains characters that are not allowed",
+            PolicyViolation::UsernameMixedScripts => "Username mixes characters of different scripts",
+            PolicyViolation::UsernameReserved => "Username is reserved",
+            PolicyViolation::UsernameConfusable => "Username can be mistaken for an existing one",
+            PolicyViolation::PasswordTooShort => "Password is too short",
+            PolicyViolation::PasswordTooLong => "Password is too long",
+            PolicyViolation::PasswordTooWeak => "Password is too easy to guess",
+            PolicyViolation::PasswordBreached => "Password is known from data breaches",
+            PolicyViolation::PasswordContainsUsername => "Password contains the username",
+        }
+    }
+}
+
+impl Serialize for PolicyViolation {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: serde::Serializer {
+        let mut state = serializer.serialize_struct("PolicyViolation", 3)?;
+        state.serialize_field("field", self.field())?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", self.message())?;
+        state.end()
+    }
+}
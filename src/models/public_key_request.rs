@@ -0,0 +1,12 @@
+use rocket::serde::Deserialize;
+
+use crate::impl_from_data_json_for;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct PublicKeyRequest<'a> {
+    // base64url, 32 byte Ed25519 public key
+    pub public_key: &'a str,
+}
+
+impl_from_data_json_for!(PublicKeyRequest<'a>);
@@ -0,0 +1,12 @@
+use rocket::serde::Serialize;
+
+use crate::impl_responder_json_for;
+
+// shown once, only their hashes are stored
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct RecoveryCodes {
+    pub recovery_codes: Vec<String>,
+}
+
+impl_responder_json_for!(RecoveryCodes);
@@ -0,0 +1,17 @@
+use rocket::serde::Serialize;
+
+// A login of the user on one device, or an OAuth client's authorization
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct Session {
+    pub id: uuid::Uuid,
+    pub device_name: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub client_id: Option<uuid::Uuid>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub last_seen_at: chrono::DateTime<chrono::Utc>,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+    // the session the listing was requested with
+    pub current: bool,
+}
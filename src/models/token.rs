@@ -1,4 +1,3 @@
-use rocket::response::Responder;
 use serde::ser::SerializeStruct;
 use serde::Serialize;
 
@@ -7,17 +6,22 @@ use crate::impl_responder_json_for;
 #[derive(Debug, Clone, PartialEq)]
 pub struct Token {
     pub access_token: String,
+    pub refresh_token: String,
+    // seconds until access_token expires
+    pub expires_in: u32,
 }
 
 impl_responder_json_for!(Token);
 
-impl<'a> Serialize for Token {
+impl Serialize for Token {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
         where
             S: serde::Serializer {
-        let mut state = serializer.serialize_struct("Token", 2)?;
+        let mut state = serializer.serialize_struct("Token", 4)?;
         state.serialize_field("access_token", &self.access_token)?;
         state.serialize_field("token_type", "BEARER")?;
+        state.serialize_field("expires_in", &self.expires_in)?;
+        state.serialize_field("refresh_token", &self.refresh_token)?;
         state.end()
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,13 @@
+use rocket::serde::Serialize;
+
+use crate::impl_responder_json_for;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct TotpEnrollment {
+    // base32, for authenticator apps that can't scan the otpauth url
+    pub secret: String,
+    pub otpauth_url: String,
+}
+
+impl_responder_json_for!(TotpEnrollment);
@@ -7,8 +7,8 @@ use crate::impl_from_data_json_for;
 #[diesel(table_name = crate::schema::users)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 #[serde(crate = "rocket::serde")]
-pub struct Insert<'a> {
-    pub name: &'a str,
+pub struct Insert {
+    pub name: String,
 }
 
-impl_from_data_json_for!(Insert<'a>);
\ No newline at end of file
+impl_from_data_json_for!(Insert);
@@ -4,24 +4,25 @@ use rocket_db_pools::diesel::Queryable;
 use crate::{impl_from_data_json_for, impl_responder_json_for};
 use crate::models::Model;
 
+#[allow(dead_code)]
 pub(crate) mod users;
 pub(crate) mod patch;
 pub(crate) mod insert;
 
-impl<'a> Model for User<'a> {
-    type Patch = patch::Patch<'a>;
-    type Insert = insert::Insert<'a>;
-    type Vector = users::Users<'a>;
+impl Model for User {
+    type Patch = patch::Patch;
+    type Insert = insert::Insert;
+    type Vector = users::Users;
 
     fn to_patch(&self) -> Self::Patch {
         Self::Patch {
-            name: Some(self.name)
+            name: Some(self.name.clone())
         }
     }
 
     fn to_insert(&self) -> Self::Insert {
         Self::Insert {
-            name: self.name
+            name: self.name.clone()
         }
     }
 }
@@ -30,12 +31,25 @@ impl<'a> Model for User<'a> {
 #[diesel(table_name = crate::schema::users)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 #[serde(crate = "rocket::serde")]
-pub struct User<'a> {
+pub struct User {
     pub id: uuid::Uuid,
-    pub name: &'a str,
-    pub is_admin: bool,
+    pub name: String,
+    // bots act on behalf of their owner and can't log in with a password
+    pub bot: bool,
 }
 
-impl_responder_json_for!(User<'a>);
-impl_from_data_json_for!(User<'a>);
+impl_responder_json_for!(User);
+impl_from_data_json_for!(User);
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bots_are_marked_in_the_payload() {
+        let bot = User { id: uuid::Uuid::nil(), name: "ci-bot".to_string(), bot: true };
+        let payload = rocket::serde::json::to_value(&bot).unwrap();
+        assert_eq!(payload["bot"], true);
+        assert_eq!(rocket::serde::json::to_value(User { bot: false, ..bot }).unwrap()["bot"], false);
+    }
+}
@@ -6,9 +6,8 @@ use crate::models::user::User;
 
 #[derive(Debug, Clone, PartialEq, Serialize)]
 #[serde(crate = "rocket::serde")]
-pub struct Users<'a>(Vec<User<'a>>);
+pub struct Users(Vec<User>);
 
-
-impl_deserialize_for_vector_wrapper!(Users<'a>, User);
-impl_responder_json_for!(Users<'a>);
-impl_from_data_json_for!(Users<'a>);
+impl_deserialize_for_vector_wrapper!(Users, User);
+impl_responder_json_for!(Users);
+impl_from_data_json_for!(Users);
@@ -0,0 +1,14 @@
+use rocket::serde::Serialize;
+
+use crate::impl_responder_json_for;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct UserEmail {
+    // always verified, a new address replaces it only once its code is confirmed
+    pub email: Option<String>,
+    // waiting for the code sent to it
+    pub pending: Option<String>,
+}
+
+impl_responder_json_for!(UserEmail);
@@ -0,0 +1,49 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use rocket::serde::Serialize;
+
+use super::{Notification, Notifier, Recipient};
+
+pub(crate) struct FileNotifier {
+    path: PathBuf,
+    // keeps lines of concurrent notifications from interleaving
+    lock: Mutex<()>,
+}
+
+impl FileNotifier {
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self { path, lock: Mutex::new(()) }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct Entry<'a> {
+    sent_at: chrono::DateTime<chrono::Utc>,
+    recipient: &'a Recipient,
+    notification: &'a Notification,
+}
+
+impl Notifier for FileNotifier {
+    fn notify(&self, recipient: &Recipient, notification: &Notification) -> Result<(), ()> {
+        let entry = Entry {
+            sent_at: chrono::Utc::now(),
+            recipient,
+            notification,
+        };
+        let mut line = rocket::serde::json::to_string(&entry).map_err(|_| ())?;
+        line.push('\n');
+
+        let _guard = self.lock.lock().map_err(|_| ())?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|err| error!("Could not open notification file {}: {}", self.path.display(), err))?;
+        file.write_all(line.as_bytes())
+            .map_err(|err| error!("Could not write notification file {}: {}", self.path.display(), err))
+    }
+}
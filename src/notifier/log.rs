@@ -0,0 +1,11 @@
+use super::{Notification, Notifier, Recipient};
+
+// Writes everything, tokens included, to the server log; never use it in production
+pub(crate) struct LogNotifier;
+
+impl Notifier for LogNotifier {
+    fn notify(&self, recipient: &Recipient, notification: &Notification) -> Result<(), ()> {
+        info!("Notification for {} ({}): {:?}", recipient.username, recipient.user_id, notification);
+        Ok(())
+    }
+}
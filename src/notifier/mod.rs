@@ -0,0 +1,48 @@
+mod file;
+mod log;
+
+use std::sync::Arc;
+
+use rocket::serde::Serialize;
+
+use crate::models::NotifierConfig;
+
+pub(crate) use file::FileNotifier;
+pub(crate) use log::LogNotifier;
+
+// Delivers messages meant for a single user, e.g. password reset tokens or login links
+pub(crate) trait Notifier: Send + Sync {
+    fn notify(&self, recipient: &Recipient, notification: &Notification) -> Result<(), ()>;
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct Recipient {
+    pub user_id: uuid::Uuid,
+    pub username: String,
+    // a verified address, or the address being verified; notifiers that mail have nothing to do without one
+    pub email: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde", tag = "kind", rename_all = "snake_case")]
+pub(crate) enum Notification {
+    PasswordReset { token: String, expires_in: u32 },
+    MagicLink { link: String, expires_in: u32 },
+    EmailVerification { code: String, expires_in: u32 },
+}
+
+pub(crate) fn from_config(config: &NotifierConfig) -> Arc<dyn Notifier> {
+    match config {
+        NotifierConfig::Log => Arc::new(LogNotifier),
+        NotifierConfig::File { path } => Arc::new(FileNotifier::new(path.clone())),
+    }
+}
+
+// Notifiers may block on IO, so they run on the blocking pool
+pub(crate) async fn send(notifier: &Arc<dyn Notifier>, recipient: Recipient, notification: Notification) -> Result<(), ()> {
+    let notifier = notifier.clone();
+    rocket::tokio::task::spawn_blocking(move || notifier.notify(&recipient, &notification))
+        .await
+        .map_err(|_| ())?
+}
@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use rocket::fairing::AdHoc;
+use rocket::form::Form;
+use rocket::http::Status;
+use rocket::response::Redirect;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::serde::json::{json, Json, Value};
+use rocket::State;
+
+use crate::database::auth::JwtKeys;
+use crate::database::oauth::verify_code_challenge;
+use crate::database::random_token;
+use crate::models::{JwtAlgorithm, JwtConfig, JwtKeyConfig};
+
+const MOCK_KID: &str = "mock-idp";
+const CODE_TTL_SECONDS: i64 = 60;
+const ID_TOKEN_TTL_SECONDS: i64 = 5 * 60;
+
+// A stand-in identity provider for local testing of the OIDC login, never enable it in production:
+// it logs in whoever is named in `login_hint` without asking for anything
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(crate = "rocket::serde", default)]
+struct MockIdpConfig {
+    enabled: bool,
+    // the URL the mock is reachable at, including the mount point
+    issuer: String,
+    client_id: String,
+    client_secret: String,
+}
+
+struct MockIdpState {
+    config: MockIdpConfig,
+    keys: JwtKeys,
+    codes: Mutex<HashMap<String, PendingCode>>,
+}
+
+struct PendingCode {
+    redirect_uri: String,
+    nonce: Option<String>,
+    code_challenge: Option<String>,
+    subject: String,
+    expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct IdTokenClaims {
+    iss: String,
+    sub: String,
+    aud: String,
+    exp: i64,
+    iat: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nonce: Option<String>,
+    preferred_username: String,
+}
+
+#[derive(FromForm)]
+struct AuthorizeRequest<'r> {
+    response_type: &'r str,
+    client_id: &'r str,
+    redirect_uri: &'r str,
+    state: Option<&'r str>,
+    nonce: Option<&'r str>,
+    code_challenge: Option<&'r str>,
+    // the mock logs in as this subject, `alice` when missing
+    login_hint: Option<&'r str>,
+}
+
+#[derive(FromForm)]
+struct TokenRequest<'r> {
+    grant_type: &'r str,
+    code: &'r str,
+    redirect_uri: &'r str,
+    client_id: &'r str,
+    client_secret: Option<&'r str>,
+    code_verifier: Option<&'r str>,
+}
+
+type MockResult<T> = Result<T, (Status, Json<Value>)>;
+
+fn oauth_error(error: &str) -> (Status, Json<Value>) {
+    (Status::BadRequest, Json(json!({ "error": error })))
+}
+
+pub(crate) trait MockIdp {
+    fn mount_mock_idp(self, base: &'static str) -> Self;
+}
+
+impl MockIdp for rocket::Rocket<rocket::Build> {
+    // mounts nothing unless `mock_idp.enabled` is set
+    fn mount_mock_idp(self, base: &'static str) -> Self {
+        self.attach(AdHoc::try_on_ignite("Mock IdP", move |rocket| async move {
+            let config = match rocket.figment().focus("mock_idp").extract::<MockIdpConfig>() {
+                Ok(config) if config.enabled => config,
+                Ok(_) => return Ok(rocket),
+                Err(err) => {
+                    error!("Invalid mock IdP configuration: {}", err);
+                    return Err(rocket);
+                }
+            };
+            let keys = match generate_keys() {
+                Ok(keys) => keys,
+                Err(err) => {
+                    error!("Could not create mock IdP keys: {}", err);
+                    return Err(rocket);
+                }
+            };
+            warn!("Mock IdP is enabled at {}, anyone can log in through it", config.issuer);
+            let state = MockIdpState { config, keys, codes: Mutex::new(HashMap::new()) };
+            Ok(rocket.manage(state).mount(base, routes![discovery, authorize, token, jwks]))
+        }))
+    }
+}
+
+// A fresh key on every start, ID tokens only have to outlive a login
+fn generate_keys() -> Result<JwtKeys, String> {
+    let private_key = openssl::rsa::Rsa::generate(2048)
+        .and_then(|rsa| rsa.private_key_to_pem())
+        .map_err(|err| err.to_string())?;
+    let private_key = String::from_utf8(private_key).map_err(|err| err.to_string())?;
+
+    JwtKeys::from_config(&JwtConfig {
+        signing_kid: Some(MOCK_KID.to_string()),
+        keys: vec![JwtKeyConfig {
+            kid: MOCK_KID.to_string(),
+            algorithm: JwtAlgorithm::RS256,
+            secret: None,
+            private_key: Some(private_key),
+            public_key: None,
+        }],
+    })
+}
+
+#[get("/.well-known/openid-configuration")]
+fn discovery(idp: &State<MockIdpState>) -> Json<Value> {
+    let issuer = &idp.config.issuer;
+    Json(json!({
+        "issuer": issuer,
+        "authorization_endpoint": format!("{}/authorize", issuer),
+        "token_endpoint": format!("{}/token", issuer),
+        "jwks_uri": format!("{}/jwks", issuer),
+        "response_types_supported": ["code"],
+        "subject_types_supported": ["public"],
+        "id_token_signing_alg_values_supported": ["RS256"],
+        "code_challenge_methods_supported": ["S256"],
+    }))
+}
+
+#[get("/authorize?<request..>")]
+fn authorize(request: AuthorizeRequest<'_>, idp: &State<MockIdpState>) -> MockResult<Redirect> {
+    if request.client_id != idp.config.client_id {
+        return Err(oauth_error("unauthorized_client"));
+    }
+    if request.response_type != "code" {
+        return Err(oauth_error("unsupported_response_type"));
+    }
+
+    let code = random_token().map_err(|_| oauth_error("server_error"))?;
+
+    let ttl = chrono::Duration::try_seconds(CODE_TTL_SECONDS).ok_or_else(|| oauth_error("server_error"))?;
+    let pending = PendingCode {
+        redirect_uri: request.redirect_uri.to_string(),
+        nonce: request.nonce.map(str::to_string),
+        code_challenge: request.code_challenge.map(str::to_string),
+        subject: request.login_hint.unwrap_or("alice").to_string(),
+        expires_at: chrono::Utc::now() + ttl,
+    };
+    let mut codes = idp.codes.lock().map_err(|_| oauth_error("server_error"))?;
+    codes.retain(|_, pending| pending.expires_at > chrono::Utc::now());
+    codes.insert(code.clone(), pending);
+
+    let mut redirect_to = reqwest::Url::parse(request.redirect_uri).map_err(|_| oauth_error("invalid_request"))?;
+    redirect_to.query_pairs_mut().append_pair("code", &code);
+    if let Some(state) = request.state {
+        redirect_to.query_pairs_mut().append_pair("state", state);
+    }
+    Ok(Redirect::to(String::from(redirect_to)))
+}
+
+#[post("/token", format = "form", data = "<request>")]
+fn token(request: Form<TokenRequest<'_>>, idp: &State<MockIdpState>) -> MockResult<Json<Value>> {
+    if request.client_id != idp.config.client_id || request.client_secret != Some(idp.config.client_secret.as_str()) {
+        return Err((Status::Unauthorized, Json(json!({ "error": "invalid_client" }))));
+    }
+    if request.grant_type != "authorization_code" {
+        return Err(oauth_error("unsupported_grant_type"));
+    }
+
+    let pending = idp.codes.lock()
+        .map_err(|_| oauth_error("server_error"))?
+        .remove(request.code)
+        .filter(|pending| pending.expires_at > chrono::Utc::now())
+        .ok_or_else(|| oauth_error("invalid_grant"))?;
+    if pending.redirect_uri != request.redirect_uri {
+        return Err(oauth_error("invalid_grant"));
+    }
+    if let Some(code_challenge) = &pending.code_challenge {
+        if !request.code_verifier.is_some_and(|code_verifier| verify_code_challenge(code_challenge, code_verifier)) {
+            return Err(oauth_error("invalid_grant"));
+        }
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    let claims = IdTokenClaims {
+        iss: idp.config.issuer.clone(),
+        sub: pending.subject.clone(),
+        aud: idp.config.client_id.clone(),
+        exp: now + ID_TOKEN_TTL_SECONDS,
+        iat: now,
+        nonce: pending.nonce,
+        preferred_username: pending.subject,
+    };
+    let id_token = idp.keys.sign(&claims).map_err(|_| oauth_error("server_error"))?;
+
+    Ok(Json(json!({
+        // spiritbox only reads the ID token
+        "access_token": uuid::Uuid::new_v4().to_string(),
+        "token_type": "Bearer",
+        "expires_in": ID_TOKEN_TTL_SECONDS,
+        "id_token": id_token,
+    })))
+}
+
+#[get("/jwks")]
+fn jwks(idp: &State<MockIdpState>) -> Json<jsonwebtoken::jwk::JwkSet> {
+    Json(idp.keys.jwks().clone())
+}
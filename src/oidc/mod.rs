@@ -0,0 +1,347 @@
+mod mock;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use jsonwebtoken::jwk::JwkSet;
+use rocket::serde::Deserialize;
+use rocket::serde::json::Value;
+
+use crate::database::oauth::code_challenge;
+use crate::models::{OidcError, OidcProviderConfig};
+
+pub(crate) use mock::MockIdp;
+
+// providers rotate their keys rarely, unknown kids trigger a refresh anyway
+const DISCOVERY_TTL: Duration = Duration::from_secs(60 * 60);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Talks to the configured identity providers, their discovery documents and keys are cached per provider
+pub(crate) struct OidcClient {
+    http: reqwest::Client,
+    providers: Mutex<HashMap<String, Arc<Discovery>>>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+struct Discovery {
+    metadata: ProviderMetadata,
+    jwks: JwkSet,
+    fetched_at: Instant,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct TokenResponse {
+    id_token: String,
+}
+
+// What spiritbox keeps of a validated ID token
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct OidcIdentity {
+    pub subject: String,
+    // taken from the configured username claim, new accounts are named after it
+    pub username: Option<String>,
+}
+
+impl OidcClient {
+    pub(crate) fn new() -> Result<Self, String> {
+        let http = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|err| format!("could not create the OIDC HTTP client: {}", err))?;
+        Ok(Self { http, providers: Mutex::new(HashMap::new()) })
+    }
+
+    // URL the user is sent to, `state` and `nonce` come back with the callback and the ID token
+    pub(crate) async fn authorization_url(&self, name: &str, config: &OidcProviderConfig, state: &str, nonce: &str, code_verifier: &str) -> Result<String, OidcError> {
+        let discovery = self.discover(name, config, false).await?;
+        let code_challenge = code_challenge(code_verifier).map_err(|_| OidcError::InternalServerError)?;
+        let url = reqwest::Url::parse_with_params(&discovery.metadata.authorization_endpoint, &[
+            ("response_type", "code"),
+            ("client_id", config.client_id.as_str()),
+            ("redirect_uri", config.redirect_uri.as_str()),
+            ("scope", config.scopes.as_str()),
+            ("state", state),
+            ("nonce", nonce),
+            ("code_challenge", code_challenge.as_str()),
+            ("code_challenge_method", "S256"),
+        ]).map_err(|_| OidcError::ProviderUnavailable)?;
+        Ok(url.into())
+    }
+
+    pub(crate) async fn exchange_code(&self, name: &str, config: &OidcProviderConfig, code: &str, code_verifier: &str, nonce: &str) -> Result<OidcIdentity, OidcError> {
+        let discovery = self.discover(name, config, false).await?;
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", config.redirect_uri.as_str()),
+            ("client_id", config.client_id.as_str()),
+            ("code_verifier", code_verifier),
+        ];
+        if let Some(client_secret) = &config.client_secret {
+            form.push(("client_secret", client_secret.as_str()));
+        }
+
+        let response = self.http.post(&discovery.metadata.token_endpoint)
+            .form(&form)
+            .send()
+            .await
+            .map_err(|err| {
+                warn!("Token request to identity provider {} failed: {}", name, err);
+                OidcError::ProviderUnavailable
+            })?;
+        // a rejected code is the user's problem, not the provider's
+        if response.status().is_client_error() {
+            return Err(OidcError::InvalidState);
+        }
+        let response = response.error_for_status()
+            .map_err(|_| OidcError::ProviderUnavailable)?
+            .json::<TokenResponse>()
+            .await
+            .map_err(|_| OidcError::InvalidIdToken)?;
+
+        let identity = match validate_id_token(&discovery, config, &response.id_token, nonce) {
+            Err(IdTokenError::UnknownKey) => {
+                // the provider may have rotated its keys since the last discovery
+                let discovery = self.discover(name, config, true).await?;
+                validate_id_token(&discovery, config, &response.id_token, nonce)
+            }
+            identity => identity,
+        }.map_err(|_| OidcError::InvalidIdToken)?;
+        Ok(identity)
+    }
+
+    async fn discover(&self, name: &str, config: &OidcProviderConfig, refresh: bool) -> Result<Arc<Discovery>, OidcError> {
+        if !refresh {
+            let providers = self.providers.lock().map_err(|_| OidcError::InternalServerError)?;
+            if let Some(discovery) = providers.get(name).filter(|discovery| discovery.fetched_at.elapsed() < DISCOVERY_TTL) {
+                return Ok(discovery.clone());
+            }
+        }
+
+        let url = format!("{}/.well-known/openid-configuration", config.issuer.trim_end_matches('/'));
+        let metadata = self.fetch_json::<ProviderMetadata>(name, &url).await?;
+        // OpenID Connect Discovery 1.0 section 4.3, otherwise another issuer could be impersonated
+        if metadata.issuer != config.issuer {
+            warn!("Identity provider {} announced issuer {} instead of {}", name, metadata.issuer, config.issuer);
+            return Err(OidcError::ProviderUnavailable);
+        }
+        let jwks = self.fetch_json::<JwkSet>(name, &metadata.jwks_uri).await?;
+
+        let discovery = Arc::new(Discovery { metadata, jwks, fetched_at: Instant::now() });
+        self.providers.lock()
+            .map_err(|_| OidcError::InternalServerError)?
+            .insert(name.to_string(), discovery.clone());
+        Ok(discovery)
+    }
+
+    async fn fetch_json<T: rocket::serde::de::DeserializeOwned>(&self, name: &str, url: &str) -> Result<T, OidcError> {
+        let response = self.http.get(url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        match response {
+            Ok(response) => response.json::<T>().await.map_err(|err| {
+                warn!("Identity provider {} sent an invalid response from {}: {}", name, url, err);
+                OidcError::ProviderUnavailable
+            }),
+            Err(err) => {
+                warn!("Could not reach identity provider {} at {}: {}", name, url, err);
+                Err(OidcError::ProviderUnavailable)
+            }
+        }
+    }
+}
+
+enum IdTokenError {
+    UnknownKey,
+    Invalid,
+}
+
+fn validate_id_token(discovery: &Discovery, config: &OidcProviderConfig, id_token: &str, nonce: &str) -> Result<OidcIdentity, IdTokenError> {
+    use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+
+    let header = jsonwebtoken::decode_header(id_token).map_err(|_| IdTokenError::Invalid)?;
+    // HMAC would be keyed with the client secret, only the provider's published keys are trusted
+    if matches!(header.alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512) {
+        return Err(IdTokenError::Invalid);
+    }
+    let jwk = match &header.kid {
+        Some(kid) => discovery.jwks.find(kid),
+        None if discovery.jwks.keys.len() == 1 => discovery.jwks.keys.first(),
+        None => None,
+    }.ok_or(IdTokenError::UnknownKey)?;
+    // the algorithm is pinned by the key when the provider names one
+    if jwk.common.key_algorithm.is_some_and(|algorithm| algorithm.to_string() != format!("{:?}", header.alg)) {
+        return Err(IdTokenError::Invalid);
+    }
+    let key = DecodingKey::from_jwk(jwk).map_err(|_| IdTokenError::Invalid)?;
+
+    let mut validation = Validation::new(header.alg);
+    validation.set_issuer(&[&config.issuer]);
+    validation.set_audience(&[&config.client_id]);
+    validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+
+    let claims = jsonwebtoken::decode::<HashMap<String, Value>>(id_token, &key, &validation)
+        .map(|token| token.claims)
+        .map_err(|_| IdTokenError::Invalid)?;
+
+    // the nonce ties the ID token to this login, a token issued for another one is not accepted
+    let token_nonce = claims.get("nonce").and_then(Value::as_str).unwrap_or_default();
+    if token_nonce.len() != nonce.len() || !openssl::memcmp::eq(token_nonce.as_bytes(), nonce.as_bytes()) {
+        return Err(IdTokenError::Invalid);
+    }
+
+    let subject = claims.get("sub").and_then(Value::as_str).ok_or(IdTokenError::Invalid)?;
+    Ok(OidcIdentity {
+        subject: subject.to_string(),
+        username: claims.get(&config.username_claim).and_then(Value::as_str).map(str::to_string),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::{ContentType, RawStr, Status};
+    use rocket::local::asynchronous::Client;
+    use rocket::serde::json::json;
+
+    use super::*;
+    use crate::oidc::MockIdp;
+
+    const ISSUER: &str = "http://idp.test/mock-idp";
+    const CLIENT_ID: &str = "spiritbox";
+    const CLIENT_SECRET: &str = "mock client secret";
+    const REDIRECT_URI: &str = "http://app.test/oidc/callback";
+
+    fn provider() -> OidcProviderConfig {
+        OidcProviderConfig {
+            issuer: ISSUER.to_string(),
+            client_id: CLIENT_ID.to_string(),
+            client_secret: Some(CLIENT_SECRET.to_string()),
+            redirect_uri: REDIRECT_URI.to_string(),
+            scopes: "openid profile".to_string(),
+            username_claim: "preferred_username".to_string(),
+        }
+    }
+
+    async fn mock_idp() -> Client {
+        let figment = rocket::figment::Figment::from(rocket::Config::debug_default())
+            .merge(("log_level", "off"))
+            .merge(("mock_idp.enabled", true))
+            .merge(("mock_idp.issuer", ISSUER))
+            .merge(("mock_idp.client_id", CLIENT_ID))
+            .merge(("mock_idp.client_secret", CLIENT_SECRET));
+        Client::tracked(rocket::custom(figment).mount_mock_idp("/mock-idp")).await.unwrap()
+    }
+
+    // the local client can't be reached over HTTP, discovery is read through it and cached up front
+    async fn discover(idp: &Client) -> Discovery {
+        let metadata = idp.get("/mock-idp/.well-known/openid-configuration").dispatch().await
+            .into_json::<ProviderMetadata>().await
+            .unwrap();
+        assert_eq!(metadata.issuer, ISSUER);
+        let jwks = idp.get("/mock-idp/jwks").dispatch().await
+            .into_json::<JwkSet>().await
+            .unwrap();
+        Discovery { metadata, jwks, fetched_at: Instant::now() }
+    }
+
+    // The browser's and the callback's part of a login, returns the ID token the code is exchanged for
+    async fn log_in(idp: &Client, oidc: &OidcClient, nonce: &str) -> String {
+        let code_verifier = crate::database::random_token().unwrap();
+        let Ok(authorization_url) = oidc.authorization_url("mock", &provider(), "login state", nonce, &code_verifier).await else {
+            panic!("no authorization URL for the cached provider");
+        };
+        let authorization_url = reqwest::Url::parse(&authorization_url).unwrap();
+        assert!(authorization_url.as_str().starts_with(ISSUER));
+
+        let response = idp.get(format!("{}?{}", authorization_url.path(), authorization_url.query().unwrap())).dispatch().await;
+        assert_eq!(response.status(), Status::SeeOther);
+        let callback = reqwest::Url::parse(response.headers().get_one("Location").unwrap()).unwrap();
+        assert!(callback.as_str().starts_with(REDIRECT_URI));
+        let callback = callback.query_pairs().into_owned().collect::<HashMap<_, _>>();
+        assert_eq!(callback["state"], "login state");
+
+        let form = [
+            ("grant_type", "authorization_code"),
+            ("code", callback["code"].as_str()),
+            ("redirect_uri", REDIRECT_URI),
+            ("client_id", CLIENT_ID),
+            ("client_secret", CLIENT_SECRET),
+            ("code_verifier", code_verifier.as_str()),
+        ].iter().map(|(key, value)| format!("{}={}", key, RawStr::new(value).percent_encode())).collect::<Vec<_>>().join("&");
+        let response = idp.post("/mock-idp/token").header(ContentType::Form).body(form).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        response.into_json::<TokenResponse>().await.unwrap().id_token
+    }
+
+    async fn setup() -> (Client, OidcClient, Arc<Discovery>) {
+        let idp = mock_idp().await;
+        let discovery = Arc::new(discover(&idp).await);
+        let oidc = OidcClient::new().unwrap();
+        oidc.providers.lock().unwrap().insert("mock".to_string(), discovery.clone());
+        (idp, oidc, discovery)
+    }
+
+    #[rocket::async_test]
+    async fn mock_idp_login_yields_the_logged_in_identity() {
+        let (idp, oidc, discovery) = setup().await;
+        let id_token = log_in(&idp, &oidc, "login nonce").await;
+        let identity = validate_id_token(&discovery, &provider(), &id_token, "login nonce").ok();
+        assert_eq!(identity, Some(OidcIdentity { subject: "alice".to_string(), username: Some("alice".to_string()) }));
+    }
+
+    #[rocket::async_test]
+    async fn id_tokens_of_another_login_are_rejected() {
+        let (idp, oidc, discovery) = setup().await;
+        let id_token = log_in(&idp, &oidc, "login nonce").await;
+        assert!(matches!(validate_id_token(&discovery, &provider(), &id_token, "other nonce"), Err(IdTokenError::Invalid)));
+        assert!(matches!(validate_id_token(&discovery, &provider(), &id_token, ""), Err(IdTokenError::Invalid)));
+    }
+
+    #[rocket::async_test]
+    async fn id_tokens_for_another_issuer_or_client_are_rejected() {
+        let (idp, oidc, discovery) = setup().await;
+        let id_token = log_in(&idp, &oidc, "login nonce").await;
+        assert!(validate_id_token(&discovery, &provider(), &id_token, "login nonce").is_ok());
+
+        let other_issuer = OidcProviderConfig { issuer: "http://other.test/mock-idp".to_string(), ..provider() };
+        assert!(matches!(validate_id_token(&discovery, &other_issuer, &id_token, "login nonce"), Err(IdTokenError::Invalid)));
+        let other_client = OidcProviderConfig { client_id: "other".to_string(), ..provider() };
+        assert!(matches!(validate_id_token(&discovery, &other_client, &id_token, "login nonce"), Err(IdTokenError::Invalid)));
+    }
+
+    #[rocket::async_test]
+    async fn hmac_signed_id_tokens_are_rejected() {
+        use jsonwebtoken::{Algorithm, EncodingKey, Header};
+
+        let (_idp, _oidc, discovery) = setup().await;
+        let now = chrono::Utc::now().timestamp();
+        let claims = json!({
+            "iss": ISSUER,
+            "sub": "alice",
+            "aud": CLIENT_ID,
+            "exp": now + 60,
+            "iat": now,
+            "nonce": "login nonce",
+            "preferred_username": "alice",
+        });
+        // signed with the client secret, which spiritbox shares with the provider, under the provider's kid
+        for algorithm in [Algorithm::HS256, Algorithm::HS384, Algorithm::HS512] {
+            let mut header = Header::new(algorithm);
+            header.kid = discovery.jwks.keys[0].common.key_id.clone();
+            let id_token = jsonwebtoken::encode(&header, &claims, &EncodingKey::from_secret(CLIENT_SECRET.as_bytes())).unwrap();
+            assert!(matches!(validate_id_token(&discovery, &provider(), &id_token, "login nonce"), Err(IdTokenError::Invalid)));
+        }
+    }
+}
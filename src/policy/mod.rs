@@ -0,0 +1,102 @@
+mod password;
+mod username;
+
+use std::collections::HashSet;
+
+use crate::models::{PasswordPolicyConfig, PolicyViolation, RegistrationConfig, UsernamePolicyConfig};
+
+pub(crate) use username::{normalize_username, username_skeleton};
+
+// longest name the users table takes
+const MAX_USERNAME_LENGTH: usize = 32;
+
+// The configured registration rules with the word lists they need, loaded once at startup
+pub(crate) struct RegistrationPolicy {
+    username: UsernamePolicyConfig,
+    // skeletons of the reserved names, so look-alikes are reserved too
+    reserved: HashSet<String>,
+    password: PasswordPolicyConfig,
+    // lowercase
+    breached: HashSet<String>,
+}
+
+impl RegistrationPolicy {
+    pub(crate) fn from_config(config: &RegistrationConfig) -> Result<Self, String> {
+        let username = &config.username;
+        if username.min_length == 0 || username.min_length > username.max_length || username.max_length > MAX_USERNAME_LENGTH {
+            return Err(format!("username lengths must satisfy 1 <= min_length <= max_length <= {}", MAX_USERNAME_LENGTH));
+        }
+        if config.password.min_length > config.password.max_length {
+            return Err("password min_length must not exceed max_length".to_string());
+        }
+
+        let breached = match &config.password.breached_passwords {
+            Some(path) => std::fs::read_to_string(path)
+                .map_err(|err| format!("could not read breached passwords from {}: {}", path.display(), err))?
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(str::to_lowercase)
+                .collect(),
+            None => HashSet::new(),
+        };
+
+        Ok(Self {
+            username: username.clone(),
+            reserved: username.reserved.iter().map(|name| username_skeleton(name)).collect(),
+            password: config.password.clone(),
+            breached,
+        })
+    }
+
+    // Every broken rule of both fields is reported at once, on success the username is returned the way it's stored.
+    // Look-alikes of existing accounts are caught by the database, the skeleton column is unique.
+    pub(crate) fn check(&self, username: &str, password: &str) -> Result<String, Vec<PolicyViolation>> {
+        let username = normalize_username(username);
+        let mut violations = username::check(&self.username, &self.reserved, &username);
+        violations.extend(password::check(&self.password, &self.breached, password, Some(&username)));
+
+        if violations.is_empty() {
+            Ok(username)
+        } else {
+            Err(violations)
+        }
+    }
+
+    // For names nobody typed a password with, e.g. bots and accounts provisioned by an identity provider
+    pub(crate) fn check_username(&self, username: &str) -> Result<String, Vec<PolicyViolation>> {
+        let username = normalize_username(username);
+        let violations = username::check(&self.username, &self.reserved, &username);
+        if violations.is_empty() {
+            Ok(username)
+        } else {
+            Err(violations)
+        }
+    }
+
+    pub(crate) fn max_username_length(&self) -> usize {
+        self.username.max_length
+    }
+
+    pub(crate) fn check_password(&self, password: &str) -> Result<(), Vec<PolicyViolation>> {
+        let violations = password::check(&self.password, &self.breached, password, None);
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usernames_without_a_password_follow_the_same_rules() {
+        let policy = RegistrationPolicy::from_config(&RegistrationConfig::default()).unwrap();
+        assert_eq!(policy.check_username("ａｌｉｃｅ"), Ok("alice".to_string()));
+        assert_eq!(policy.check_username("Admin"), Err(vec![PolicyViolation::UsernameReserved]));
+        assert_eq!(policy.check_username("al"), Err(vec![PolicyViolation::UsernameTooShort]));
+    }
+}
@@ -0,0 +1,66 @@
+use std::collections::HashSet;
+
+use crate::models::{PasswordPolicyConfig, PolicyViolation};
+
+// usernames shorter than this show up inside too many passwords by chance
+const MIN_USERNAME_MATCH_LENGTH: usize = 3;
+
+pub(super) fn check(config: &PasswordPolicyConfig, breached: &HashSet<String>, password: &str, username: Option<&str>) -> Vec<PolicyViolation> {
+    let length = password.chars().count();
+    if length > config.max_length {
+        // nothing else is worth looking at
+        return vec![PolicyViolation::PasswordTooLong];
+    }
+
+    let mut violations = Vec::new();
+    if length < config.min_length {
+        violations.push(PolicyViolation::PasswordTooShort);
+    }
+
+    let lowercase = password.to_lowercase();
+    if breached.contains(&lowercase) {
+        violations.push(PolicyViolation::PasswordBreached);
+    }
+    if let Some(username) = username.filter(|username| username.chars().count() >= MIN_USERNAME_MATCH_LENGTH) {
+        if lowercase.contains(&username.to_lowercase()) {
+            violations.push(PolicyViolation::PasswordContainsUsername);
+        }
+    }
+    if estimate_entropy(password) < config.min_entropy {
+        violations.push(PolicyViolation::PasswordTooWeak);
+    }
+    violations
+}
+
+// Bits of a brute force over the character classes the password uses, where a character that
+// repeats or continues a sequence of the previous one (`aaa`, `abc`, `321`) adds a single bit
+fn estimate_entropy(password: &str) -> f64 {
+    let (mut lower, mut upper, mut digit, mut symbol, mut other) = (false, false, false, false, false);
+    for c in password.chars() {
+        match c {
+            'a'..='z' => lower = true,
+            'A'..='Z' => upper = true,
+            '0'..='9' => digit = true,
+            c if c.is_ascii() => symbol = true,
+            _ => other = true,
+        }
+    }
+    let pool = [(lower, 26), (upper, 26), (digit, 10), (symbol, 33), (other, 100)]
+        .iter()
+        .filter(|(used, _)| *used)
+        .map(|(_, size)| size)
+        .sum::<u32>();
+    if pool == 0 {
+        return 0.0;
+    }
+    let bits_per_char = f64::from(pool).log2();
+
+    let mut previous: Option<char> = None;
+    password.chars()
+        .map(|c| {
+            let predictable = previous.is_some_and(|previous| (c as i64 - previous as i64).abs() <= 1);
+            previous = Some(c);
+            if predictable { 1.0 } else { bits_per_char }
+        })
+        .sum()
+}
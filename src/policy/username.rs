@@ -0,0 +1,50 @@
+use std::collections::HashSet;
+
+use unicode_normalization::UnicodeNormalization;
+use unicode_security::{GeneralSecurityProfile, RestrictionLevel, RestrictionLevelDetection};
+
+use crate::models::{PolicyViolation, UsernameCharset, UsernamePolicyConfig};
+
+// allowed between the letters and digits in either charset
+const PUNCTUATION: [char; 3] = ['_', '-', '.'];
+
+// NFKC, so the same name can't be registered twice in different encodings
+pub(crate) fn normalize_username(name: &str) -> String {
+    name.nfkc().collect()
+}
+
+// UTS #39 skeleton of the case folded name, two names with the same skeleton look alike
+pub(crate) fn username_skeleton(name: &str) -> String {
+    let folded = normalize_username(name).to_lowercase();
+    // the confusables map some lowercase letters to uppercase prototypes, e.g. `0` to `O`
+    unicode_security::skeleton(&folded).collect::<String>().to_lowercase()
+}
+
+// `name` is already normalized
+pub(super) fn check(config: &UsernamePolicyConfig, reserved: &HashSet<String>, name: &str) -> Vec<PolicyViolation> {
+    let mut violations = Vec::new();
+
+    let length = name.chars().count();
+    if length < config.min_length {
+        violations.push(PolicyViolation::UsernameTooShort);
+    }
+    if length > config.max_length {
+        violations.push(PolicyViolation::UsernameTooLong);
+    }
+
+    let allowed = |c: char| match config.charset {
+        UsernameCharset::Ascii => c.is_ascii_alphanumeric() || PUNCTUATION.contains(&c),
+        UsernameCharset::Unicode => (c.identifier_allowed() && c.is_alphanumeric()) || PUNCTUATION.contains(&c),
+    };
+    if !name.chars().all(allowed) {
+        violations.push(PolicyViolation::UsernameInvalidCharacters);
+    } else if config.charset == UsernameCharset::Unicode && !name.check_restriction_level(RestrictionLevel::HighlyRestrictive) {
+        // e.g. a Cyrillic `а` among Latin letters
+        violations.push(PolicyViolation::UsernameMixedScripts);
+    }
+
+    if reserved.contains(&username_skeleton(name)) {
+        violations.push(PolicyViolation::UsernameReserved);
+    }
+    violations
+}
@@ -1,9 +1,37 @@
 // @generated automatically by Diesel CLI.
 
 pub mod sql_types {
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "auth_event_kind"))]
+    pub struct AuthEventKind;
+
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "lockout_kind"))]
+    pub struct LockoutKind;
+
     #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
     #[diesel(postgres_type(name = "member_role"))]
     pub struct MemberRole;
+
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "password_algorithm"))]
+    pub struct PasswordAlgorithm;
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::AuthEventKind;
+
+    auth_events (id) {
+        id -> Int8,
+        kind -> AuthEventKind,
+        user_id -> Nullable<Uuid>,
+        username -> Nullable<Text>,
+        ip -> Nullable<Text>,
+        user_agent -> Nullable<Text>,
+        detail -> Nullable<Text>,
+        occurred_at -> Timestamptz,
+    }
 }
 
 diesel::table! {
@@ -21,6 +49,80 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    email_verifications (user_id) {
+        user_id -> Uuid,
+        #[max_length = 254]
+        email -> Varchar,
+        code_hash -> Bytea,
+        attempts -> Int4,
+        created_at -> Timestamptz,
+        expires_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    invite_codes (id) {
+        id -> Uuid,
+        code_hash -> Bytea,
+        created_by -> Nullable<Uuid>,
+        permissions -> Nullable<Int4>,
+        max_uses -> Int4,
+        uses -> Int4,
+        created_at -> Timestamptz,
+        expires_at -> Nullable<Timestamptz>,
+        revoked_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    invite_uses (user_id) {
+        user_id -> Uuid,
+        invite_id -> Uuid,
+        used_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::LockoutKind;
+
+    login_attempts (kind, key) {
+        kind -> LockoutKind,
+        key -> Text,
+        failures -> Int4,
+        last_failure -> Timestamptz,
+        locked_until -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    login_sessions (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        client_id -> Nullable<Uuid>,
+        #[max_length = 64]
+        device_name -> Nullable<Varchar>,
+        user_agent -> Nullable<Text>,
+        ip -> Nullable<Text>,
+        created_at -> Timestamptz,
+        last_seen_at -> Timestamptz,
+        expires_at -> Timestamptz,
+        revoked_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    magic_links (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        token_hash -> Bytea,
+        created_at -> Timestamptz,
+        expires_at -> Timestamptz,
+        used_at -> Nullable<Timestamptz>,
+    }
+}
+
 diesel::table! {
     use diesel::sql_types::*;
     use super::sql_types::MemberRole;
@@ -35,7 +137,7 @@ diesel::table! {
 diesel::table! {
     messages (id) {
         id -> Uuid,
-        user_id -> Uuid,
+        user_id -> Nullable<Uuid>,
         channel_id -> Uuid,
         content -> Text,
         created_at -> Timestamptz,
@@ -43,9 +145,159 @@ diesel::table! {
 }
 
 diesel::table! {
+    oauth_clients (id) {
+        id -> Uuid,
+        owner_id -> Uuid,
+        #[max_length = 64]
+        name -> Varchar,
+        redirect_uris -> Array<Text>,
+        secret_hash -> Nullable<Bytea>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    oauth_codes (id) {
+        id -> Uuid,
+        client_id -> Uuid,
+        user_id -> Uuid,
+        code_hash -> Bytea,
+        redirect_uri -> Text,
+        permissions -> Int4,
+        code_challenge -> Text,
+        expires_at -> Timestamptz,
+        used_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    oidc_identities (provider, subject) {
+        #[max_length = 64]
+        provider -> Varchar,
+        subject -> Text,
+        user_id -> Uuid,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    oidc_logins (state_hash) {
+        state_hash -> Bytea,
+        #[max_length = 64]
+        provider -> Varchar,
+        nonce -> Text,
+        code_verifier -> Text,
+        expires_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    passkey_challenges (id) {
+        id -> Uuid,
+        user_id -> Nullable<Uuid>,
+        challenge -> Bytea,
+        registration -> Bool,
+        expires_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    passkeys (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        credential_id -> Bytea,
+        public_key -> Bytea,
+        algorithm -> Int4,
+        sign_count -> Int8,
+        #[max_length = 64]
+        name -> Varchar,
+        created_at -> Timestamptz,
+        last_used_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    password_resets (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        token_hash -> Bytea,
+        created_at -> Timestamptz,
+        expires_at -> Timestamptz,
+        used_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    personal_access_tokens (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        #[max_length = 64]
+        name -> Varchar,
+        token_hash -> Bytea,
+        permissions -> Int4,
+        created_at -> Timestamptz,
+        expires_at -> Nullable<Timestamptz>,
+        last_used_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    recovery_codes (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        code_hash -> Bytea,
+        used_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    refresh_tokens (id) {
+        id -> Uuid,
+        family_id -> Uuid,
+        user_id -> Uuid,
+        token_hash -> Bytea,
+        created_at -> Timestamptz,
+        expires_at -> Timestamptz,
+        used_at -> Nullable<Timestamptz>,
+        revoked -> Bool,
+        client_id -> Nullable<Uuid>,
+        permissions -> Nullable<Int4>,
+    }
+}
+
+diesel::table! {
+    revoked_tokens (jti) {
+        jti -> Uuid,
+        expires_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::PasswordAlgorithm;
+
     secrets (user_id) {
         user_id -> Uuid,
         salted_hash -> Bytea,
+        algorithm -> PasswordAlgorithm,
+    }
+}
+
+diesel::table! {
+    sessions (user_id, key) {
+        user_id -> Uuid,
+        key -> Bytea,
+        nonce -> Bytea,
+        nonce_expires_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    totp_secrets (user_id) {
+        user_id -> Uuid,
+        secret -> Bytea,
+        enabled -> Bool,
+        last_used_step -> Nullable<Int8>,
     }
 }
 
@@ -55,22 +307,69 @@ diesel::table! {
         #[max_length = 32]
         name -> Varchar,
         developer -> Bool,
+        token_generation -> Int4,
+        permissions -> Int4,
+        bot -> Bool,
+        owner_id -> Nullable<Uuid>,
+        name_skeleton -> Nullable<Text>,
+        #[max_length = 254]
+        email -> Nullable<Varchar>,
     }
 }
 
 diesel::joinable!(bans -> channels (channel_id));
 diesel::joinable!(bans -> users (user_id));
+diesel::joinable!(email_verifications -> users (user_id));
+diesel::joinable!(invite_codes -> users (created_by));
+diesel::joinable!(invite_uses -> invite_codes (invite_id));
+diesel::joinable!(invite_uses -> users (user_id));
+diesel::joinable!(login_sessions -> oauth_clients (client_id));
+diesel::joinable!(login_sessions -> users (user_id));
+diesel::joinable!(magic_links -> users (user_id));
 diesel::joinable!(members -> channels (channel_id));
 diesel::joinable!(members -> users (user_id));
 diesel::joinable!(messages -> channels (channel_id));
 diesel::joinable!(messages -> users (user_id));
+diesel::joinable!(oauth_clients -> users (owner_id));
+diesel::joinable!(oauth_codes -> oauth_clients (client_id));
+diesel::joinable!(oauth_codes -> users (user_id));
+diesel::joinable!(oidc_identities -> users (user_id));
+diesel::joinable!(passkey_challenges -> users (user_id));
+diesel::joinable!(passkeys -> users (user_id));
+diesel::joinable!(password_resets -> users (user_id));
+diesel::joinable!(personal_access_tokens -> users (user_id));
+diesel::joinable!(recovery_codes -> users (user_id));
+diesel::joinable!(refresh_tokens -> oauth_clients (client_id));
+diesel::joinable!(refresh_tokens -> users (user_id));
 diesel::joinable!(secrets -> users (user_id));
+diesel::joinable!(sessions -> users (user_id));
+diesel::joinable!(totp_secrets -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    auth_events,
     bans,
     channels,
+    email_verifications,
+    invite_codes,
+    invite_uses,
+    login_attempts,
+    login_sessions,
+    magic_links,
     members,
     messages,
+    oauth_clients,
+    oauth_codes,
+    oidc_identities,
+    oidc_logins,
+    passkey_challenges,
+    passkeys,
+    password_resets,
+    personal_access_tokens,
+    recovery_codes,
+    refresh_tokens,
+    revoked_tokens,
     secrets,
+    sessions,
+    totp_secrets,
     users,
 );
@@ -0,0 +1,352 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use openssl::pkey::{PKey, Private};
+use rocket::fairing::AdHoc;
+use rocket::http::Status;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::serde::json::{json, Json, Value};
+use rocket::State;
+
+use super::cbor::{self, Value as Cbor};
+use super::{COSE_EDDSA, FLAG_ATTESTED_CREDENTIAL, FLAG_USER_PRESENT, FLAG_USER_VERIFIED};
+
+// A stand-in for a security key or platform authenticator for local testing, never enable it in production:
+// it answers every ceremony without asking anyone. Credentials live in memory and are gone after a restart.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(crate = "rocket::serde", default)]
+struct SoftwareAuthenticatorConfig {
+    enabled: bool,
+}
+
+struct SoftwareAuthenticatorState {
+    credentials: Mutex<HashMap<Vec<u8>, StoredCredential>>,
+}
+
+struct StoredCredential {
+    rp_id: String,
+    user_handle: String,
+    key: PKey<Private>,
+    sign_count: u32,
+}
+
+// What a browser would take from the options and pass to the authenticator
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct CreateRequest<'r> {
+    rp_id: &'r str,
+    origin: &'r str,
+    challenge: &'r str,
+    // the base64url user handle from the registration options
+    user_id: &'r str,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct GetRequest<'r> {
+    rp_id: &'r str,
+    origin: &'r str,
+    challenge: &'r str,
+    // any credential of the relying party is used when empty
+    #[serde(default)]
+    allow_credentials: Vec<String>,
+}
+
+// Field names match the passkey registration and login requests, the output can be posted as is
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct CreateResponse {
+    credential_id: String,
+    client_data_json: String,
+    attestation_object: String,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct GetResponse {
+    credential_id: String,
+    client_data_json: String,
+    authenticator_data: String,
+    signature: String,
+    user_handle: String,
+}
+
+type AuthenticatorResult<T> = Result<T, (Status, Json<Value>)>;
+
+fn authenticator_error(status: Status, error: &str) -> (Status, Json<Value>) {
+    (status, Json(json!({ "error": error })))
+}
+
+pub(crate) trait SoftwareAuthenticator {
+    fn mount_software_authenticator(self, base: &'static str) -> Self;
+}
+
+impl SoftwareAuthenticator for rocket::Rocket<rocket::Build> {
+    // mounts nothing unless `software_authenticator.enabled` is set
+    fn mount_software_authenticator(self, base: &'static str) -> Self {
+        self.attach(AdHoc::try_on_ignite("Software authenticator", move |rocket| async move {
+            match rocket.figment().focus("software_authenticator").extract::<SoftwareAuthenticatorConfig>() {
+                Ok(config) if config.enabled => {}
+                Ok(_) => return Ok(rocket),
+                Err(err) => {
+                    error!("Invalid software authenticator configuration: {}", err);
+                    return Err(rocket);
+                }
+            }
+            warn!("Software authenticator is enabled at {}, it signs whatever it is asked to", base);
+            let state = SoftwareAuthenticatorState::new();
+            Ok(rocket.manage(state).mount(base, routes![create, get]))
+        }))
+    }
+}
+
+#[post("/create", format = "json", data = "<request>")]
+fn create(request: Json<CreateRequest<'_>>, authenticator: &State<SoftwareAuthenticatorState>) -> AuthenticatorResult<Json<CreateResponse>> {
+    authenticator.create(&request).map(Json)
+}
+
+#[post("/get", format = "json", data = "<request>")]
+fn get(request: Json<GetRequest<'_>>, authenticator: &State<SoftwareAuthenticatorState>) -> AuthenticatorResult<Json<GetResponse>> {
+    authenticator.get(&request).map(Json)
+}
+
+impl SoftwareAuthenticatorState {
+    fn new() -> Self {
+        Self { credentials: Mutex::new(HashMap::new()) }
+    }
+
+    fn create(&self, request: &CreateRequest<'_>) -> AuthenticatorResult<CreateResponse> {
+        let server_error = |_| authenticator_error(Status::InternalServerError, "server_error");
+
+        let key = PKey::generate_ed25519().map_err(server_error)?;
+        let public_key = key.raw_public_key().map_err(server_error)?;
+        let mut credential_id = vec![0; 16];
+        openssl::rand::rand_bytes(&mut credential_id).map_err(server_error)?;
+
+        // COSE_Key of an Ed25519 key: kty OKP, alg EdDSA, crv Ed25519, x
+        let cose_key = cbor::encode(&Cbor::Map(vec![
+            (Cbor::Integer(1), Cbor::Integer(1)),
+            (Cbor::Integer(3), Cbor::Integer(COSE_EDDSA.into())),
+            (Cbor::Integer(-1), Cbor::Integer(6)),
+            (Cbor::Integer(-2), Cbor::Bytes(public_key)),
+        ]));
+        // an all zero AAGUID, like any authenticator that doesn't attest
+        let mut attested = vec![0; 16];
+        attested.extend_from_slice(&(credential_id.len() as u16).to_be_bytes());
+        attested.extend_from_slice(&credential_id);
+        attested.extend_from_slice(&cose_key);
+        let auth_data = authenticator_data(request.rp_id, FLAG_ATTESTED_CREDENTIAL, 0, &attested);
+
+        let attestation_object = cbor::encode(&Cbor::Map(vec![
+            (Cbor::Text("fmt".to_string()), Cbor::Text("none".to_string())),
+            (Cbor::Text("attStmt".to_string()), Cbor::Map(Vec::new())),
+            (Cbor::Text("authData".to_string()), Cbor::Bytes(auth_data)),
+        ]));
+        let client_data_json = client_data("webauthn.create", request.challenge, request.origin);
+
+        self.credentials.lock()
+            .map_err(|_| authenticator_error(Status::InternalServerError, "server_error"))?
+            .insert(credential_id.clone(), StoredCredential {
+                rp_id: request.rp_id.to_string(),
+                user_handle: request.user_id.to_string(),
+                key,
+                sign_count: 0,
+            });
+
+        Ok(CreateResponse {
+            credential_id: encode(&credential_id),
+            client_data_json: encode(client_data_json.as_bytes()),
+            attestation_object: encode(&attestation_object),
+        })
+    }
+
+    fn get(&self, request: &GetRequest<'_>) -> AuthenticatorResult<GetResponse> {
+        use openssl::sign::Signer;
+
+        let server_error = |_| authenticator_error(Status::InternalServerError, "server_error");
+
+        let mut credentials = self.credentials.lock()
+            .map_err(|_| authenticator_error(Status::InternalServerError, "server_error"))?;
+        let (credential_id, credential) = credentials.iter_mut()
+            .filter(|(_, credential)| credential.rp_id == request.rp_id)
+            .find(|(credential_id, _)| request.allow_credentials.is_empty() || request.allow_credentials.contains(&encode(credential_id)))
+            .ok_or_else(|| authenticator_error(Status::NotFound, "no_credential"))?;
+
+        credential.sign_count += 1;
+        let auth_data = authenticator_data(request.rp_id, 0, credential.sign_count, &[]);
+        let client_data_json = client_data("webauthn.get", request.challenge, request.origin);
+
+        let signed = [auth_data.as_slice(), &openssl::sha::sha256(client_data_json.as_bytes())].concat();
+        let signature = Signer::new_without_digest(&credential.key)
+            .and_then(|mut signer| signer.sign_oneshot_to_vec(&signed))
+            .map_err(server_error)?;
+
+        Ok(GetResponse {
+            credential_id: encode(credential_id),
+            client_data_json: encode(client_data_json.as_bytes()),
+            authenticator_data: encode(&auth_data),
+            signature: encode(&signature),
+            user_handle: credential.user_handle.clone(),
+        })
+    }
+}
+
+// the user is always present and verified, nobody is asked
+fn authenticator_data(rp_id: &str, flags: u8, sign_count: u32, attested: &[u8]) -> Vec<u8> {
+    let mut data = openssl::sha::sha256(rp_id.as_bytes()).to_vec();
+    data.push(flags | FLAG_USER_PRESENT | FLAG_USER_VERIFIED);
+    data.extend_from_slice(&sign_count.to_be_bytes());
+    data.extend_from_slice(attested);
+    data
+}
+
+fn client_data(kind: &str, challenge: &str, origin: &str) -> String {
+    json!({ "type": kind, "challenge": challenge, "origin": origin }).to_string()
+}
+
+fn encode(bytes: &[u8]) -> String {
+    use base64::Engine;
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::WebAuthnConfig;
+    use crate::webauthn::{sign_count_advanced, verify_assertion, verify_registration, CeremonyError, RegisteredCredential};
+
+    const USER_HANDLE: &str = "dXNlcg";
+
+    fn decode(value: &str) -> Vec<u8> {
+        use base64::Engine;
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(value).unwrap()
+    }
+
+    fn create(authenticator: &SoftwareAuthenticatorState, config: &WebAuthnConfig, challenge: &[u8]) -> CreateResponse {
+        let challenge = encode(challenge);
+        authenticator.create(&CreateRequest {
+            rp_id: &config.rp_id,
+            origin: &config.origins[0],
+            challenge: &challenge,
+            user_id: USER_HANDLE,
+        }).ok().unwrap()
+    }
+
+    fn get(authenticator: &SoftwareAuthenticatorState, config: &WebAuthnConfig, challenge: &[u8]) -> GetResponse {
+        let challenge = encode(challenge);
+        authenticator.get(&GetRequest {
+            rp_id: &config.rp_id,
+            origin: &config.origins[0],
+            challenge: &challenge,
+            allow_credentials: Vec::new(),
+        }).ok().unwrap()
+    }
+
+    fn register(config: &WebAuthnConfig, challenge: &[u8], response: &CreateResponse) -> Result<RegisteredCredential, CeremonyError> {
+        verify_registration(
+            config,
+            challenge,
+            &decode(&response.credential_id),
+            &decode(&response.client_data_json),
+            &decode(&response.attestation_object),
+        )
+    }
+
+    fn assert(config: &WebAuthnConfig, challenge: &[u8], credential: &RegisteredCredential, response: &GetResponse) -> Result<u32, CeremonyError> {
+        verify_assertion(
+            config,
+            challenge,
+            &credential.public_key,
+            credential.algorithm,
+            &decode(&response.client_data_json),
+            &decode(&response.authenticator_data),
+            &decode(&response.signature),
+        )
+    }
+
+    #[test]
+    fn passes_both_ceremonies() {
+        let config = WebAuthnConfig::default();
+        let authenticator = SoftwareAuthenticatorState::new();
+
+        let created = create(&authenticator, &config, &[1; 32]);
+        let credential = register(&config, &[1; 32], &created).unwrap();
+        assert_eq!(credential.credential_id, decode(&created.credential_id));
+        assert_eq!(credential.algorithm, COSE_EDDSA);
+        assert_eq!(credential.sign_count, 0);
+
+        let first = get(&authenticator, &config, &[2; 32]);
+        assert_eq!(first.credential_id, created.credential_id);
+        assert_eq!(first.user_handle, USER_HANDLE);
+        let first_count = assert(&config, &[2; 32], &credential, &first).unwrap();
+        assert!(sign_count_advanced(credential.sign_count.into(), first_count));
+
+        let second = get(&authenticator, &config, &[3; 32]);
+        let second_count = assert(&config, &[3; 32], &credential, &second).unwrap();
+        assert!(sign_count_advanced(first_count.into(), second_count));
+        // replaying the first response after the second is caught by the counter
+        assert!(!sign_count_advanced(second_count.into(), first_count));
+    }
+
+    #[test]
+    fn registration_is_bound_to_challenge_origin_and_relying_party() {
+        let config = WebAuthnConfig::default();
+        let authenticator = SoftwareAuthenticatorState::new();
+        let created = create(&authenticator, &config, &[1; 32]);
+
+        assert_eq!(register(&config, &[9; 32], &created), Err(CeremonyError::Invalid));
+
+        let other_origin = WebAuthnConfig { origins: vec!["https://evil.example".to_string()], ..config.clone() };
+        assert_eq!(register(&other_origin, &[1; 32], &created), Err(CeremonyError::Invalid));
+
+        let other_rp = WebAuthnConfig { rp_id: "example.com".to_string(), ..config.clone() };
+        assert_eq!(register(&other_rp, &[1; 32], &created), Err(CeremonyError::Invalid));
+
+        // the credential id has to be the one inside the attestation
+        let mut wrong_id = created;
+        wrong_id.credential_id = encode(&[0; 16]);
+        assert_eq!(register(&config, &[1; 32], &wrong_id), Err(CeremonyError::Invalid));
+    }
+
+    #[test]
+    fn assertions_are_bound_to_challenge_key_and_signature() {
+        let config = WebAuthnConfig::default();
+        let authenticator = SoftwareAuthenticatorState::new();
+        let credential = register(&config, &[1; 32], &create(&authenticator, &config, &[1; 32])).unwrap();
+        let response = get(&authenticator, &config, &[2; 32]);
+
+        assert_eq!(assert(&config, &[9; 32], &credential, &response), Err(CeremonyError::Invalid));
+
+        let mut signature = decode(&response.signature);
+        signature[0] ^= 1;
+        let tampered = GetResponse { signature: encode(&signature), ..response };
+        assert_eq!(assert(&config, &[2; 32], &credential, &tampered), Err(CeremonyError::Invalid));
+
+        // a different authenticator's key doesn't verify the signature
+        let other = SoftwareAuthenticatorState::new();
+        let other_credential = register(&config, &[1; 32], &create(&other, &config, &[1; 32])).unwrap();
+        let response = get(&authenticator, &config, &[3; 32]);
+        assert_eq!(assert(&config, &[3; 32], &other_credential, &response), Err(CeremonyError::Invalid));
+
+        // the authenticator data is signed, it can't be swapped for another one
+        let later = get(&authenticator, &config, &[3; 32]);
+        let swapped = GetResponse { authenticator_data: later.authenticator_data, ..response };
+        assert_eq!(assert(&config, &[3; 32], &credential, &swapped), Err(CeremonyError::Invalid));
+    }
+
+    #[test]
+    fn only_answers_for_known_credentials() {
+        let config = WebAuthnConfig::default();
+        let authenticator = SoftwareAuthenticatorState::new();
+        create(&authenticator, &config, &[1; 32]);
+
+        let challenge = encode(&[2; 32]);
+        let unknown = authenticator.get(&GetRequest {
+            rp_id: &config.rp_id,
+            origin: &config.origins[0],
+            challenge: &challenge,
+            allow_credentials: vec![encode(&[0; 16])],
+        });
+        assert_eq!(unknown.err().map(|(status, _)| status), Some(Status::NotFound));
+    }
+}
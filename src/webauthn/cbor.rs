@@ -0,0 +1,265 @@
+// Just enough CBOR (RFC 8949) for attestation objects and COSE keys:
+// definite lengths only, no floats, nesting is capped so hostile input can't exhaust the stack
+const MAX_DEPTH: usize = 16;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    Integer(i128),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+    Bool(bool),
+    Null,
+}
+
+impl Value {
+    // map lookup by integer key, COSE keys are labelled that way
+    pub(crate) fn get_int(&self, key: i128) -> Option<&Value> {
+        self.get(|k| *k == Value::Integer(key))
+    }
+
+    pub(crate) fn get_text(&self, key: &str) -> Option<&Value> {
+        self.get(|k| matches!(k, Value::Text(text) if text == key))
+    }
+
+    fn get(&self, matches: impl Fn(&Value) -> bool) -> Option<&Value> {
+        match self {
+            Value::Map(entries) => entries.iter().find(|(key, _)| matches(key)).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_integer(&self) -> Option<i128> {
+        match self {
+            Value::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Bytes(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+}
+
+// Decodes the first item and returns it with the number of bytes it took,
+// authenticator data carries a COSE key followed by more data
+pub(crate) fn decode(input: &[u8]) -> Result<(Value, usize), ()> {
+    let mut position = 0;
+    let value = decode_item(input, &mut position, 0)?;
+    Ok((value, position))
+}
+
+fn decode_item(input: &[u8], position: &mut usize, depth: usize) -> Result<Value, ()> {
+    if depth > MAX_DEPTH {
+        return Err(());
+    }
+    let initial = *input.get(*position).ok_or(())?;
+    *position += 1;
+    let major = initial >> 5;
+    let additional = initial & 0x1f;
+
+    if major == 7 {
+        return match additional {
+            20 => Ok(Value::Bool(false)),
+            21 => Ok(Value::Bool(true)),
+            22 => Ok(Value::Null),
+            _ => Err(()),
+        };
+    }
+
+    let argument = read_argument(input, position, additional)?;
+    match major {
+        0 => Ok(Value::Integer(argument.into())),
+        1 => Ok(Value::Integer(-1 - i128::from(argument))),
+        2 => Ok(Value::Bytes(read_bytes(input, position, argument)?.to_vec())),
+        3 => {
+            let text = std::str::from_utf8(read_bytes(input, position, argument)?).map_err(|_| ())?;
+            Ok(Value::Text(text.to_string()))
+        }
+        4 => {
+            // every item takes at least a byte, longer claims can only be lies
+            let length = bounded_length(input, *position, argument)?;
+            let mut items = Vec::with_capacity(length);
+            for _ in 0..length {
+                items.push(decode_item(input, position, depth + 1)?);
+            }
+            Ok(Value::Array(items))
+        }
+        5 => {
+            let length = bounded_length(input, *position, argument)?;
+            let mut entries = Vec::with_capacity(length);
+            for _ in 0..length {
+                let key = decode_item(input, position, depth + 1)?;
+                let value = decode_item(input, position, depth + 1)?;
+                entries.push((key, value));
+            }
+            Ok(Value::Map(entries))
+        }
+        // tags are not used by WebAuthn
+        _ => Err(()),
+    }
+}
+
+fn read_argument(input: &[u8], position: &mut usize, additional: u8) -> Result<u64, ()> {
+    let length = match additional {
+        0..=23 => return Ok(additional.into()),
+        24 => 1,
+        25 => 2,
+        26 => 4,
+        27 => 8,
+        // indefinite lengths and reserved values
+        _ => return Err(()),
+    };
+    let bytes = read_bytes(input, position, length)?;
+    Ok(bytes.iter().fold(0, |value, byte| (value << 8) | u64::from(*byte)))
+}
+
+fn read_bytes<'a>(input: &'a [u8], position: &mut usize, length: u64) -> Result<&'a [u8], ()> {
+    let length = usize::try_from(length).map_err(|_| ())?;
+    let end = position.checked_add(length).ok_or(())?;
+    let bytes = input.get(*position..end).ok_or(())?;
+    *position = end;
+    Ok(bytes)
+}
+
+fn bounded_length(input: &[u8], position: usize, length: u64) -> Result<usize, ()> {
+    let length = usize::try_from(length).map_err(|_| ())?;
+    if length > input.len().saturating_sub(position) {
+        return Err(());
+    }
+    Ok(length)
+}
+
+pub(crate) fn encode(value: &Value) -> Vec<u8> {
+    let mut output = Vec::new();
+    encode_into(value, &mut output);
+    output
+}
+
+fn encode_into(value: &Value, output: &mut Vec<u8>) {
+    match value {
+        Value::Integer(value) if *value >= 0 => write_head(0, *value as u64, output),
+        Value::Integer(value) => write_head(1, (-1 - *value) as u64, output),
+        Value::Bytes(bytes) => {
+            write_head(2, bytes.len() as u64, output);
+            output.extend_from_slice(bytes);
+        }
+        Value::Text(text) => {
+            write_head(3, text.len() as u64, output);
+            output.extend_from_slice(text.as_bytes());
+        }
+        Value::Array(items) => {
+            write_head(4, items.len() as u64, output);
+            items.iter().for_each(|item| encode_into(item, output));
+        }
+        Value::Map(entries) => {
+            write_head(5, entries.len() as u64, output);
+            for (key, value) in entries {
+                encode_into(key, output);
+                encode_into(value, output);
+            }
+        }
+        Value::Bool(false) => output.push(0xf4),
+        Value::Bool(true) => output.push(0xf5),
+        Value::Null => output.push(0xf6),
+    }
+}
+
+// shortest form, as the canonical encoding wants it
+fn write_head(major: u8, argument: u64, output: &mut Vec<u8>) {
+    let major = major << 5;
+    match argument {
+        0..=23 => output.push(major | argument as u8),
+        24..=0xff => output.extend_from_slice(&[major | 24, argument as u8]),
+        0x100..=0xffff => {
+            output.push(major | 25);
+            output.extend_from_slice(&(argument as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            output.push(major | 26);
+            output.extend_from_slice(&(argument as u32).to_be_bytes());
+        }
+        _ => {
+            output.push(major | 27);
+            output.extend_from_slice(&argument.to_be_bytes());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_cose_keys() {
+        let key = Value::Map(vec![
+            (Value::Integer(1), Value::Integer(2)),
+            (Value::Integer(3), Value::Integer(-7)),
+            (Value::Integer(-1), Value::Integer(1)),
+            (Value::Integer(-2), Value::Bytes(vec![0xab; 32])),
+            (Value::Text("flags".to_string()), Value::Array(vec![Value::Bool(true), Value::Null])),
+        ]);
+        let encoded = encode(&key);
+        assert_eq!(decode(&encoded), Ok((key.clone(), encoded.len())));
+        assert_eq!(key.get_int(3).and_then(Value::as_integer), Some(-7));
+        assert_eq!(key.get_int(-2).and_then(Value::as_bytes), Some(&[0xab; 32][..]));
+    }
+
+    #[test]
+    fn integers_use_the_shortest_head() {
+        assert_eq!(encode(&Value::Integer(23)), [0x17]);
+        assert_eq!(encode(&Value::Integer(24)), [0x18, 0x18]);
+        assert_eq!(encode(&Value::Integer(-257)), [0x39, 0x01, 0x00]);
+        assert_eq!(encode(&Value::Integer(0x1_0000)), [0x1a, 0x00, 0x01, 0x00, 0x00]);
+        assert_eq!(decode(&[0x3b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]), Ok((Value::Integer(-1 - i128::from(u64::MAX)), 9)));
+    }
+
+    #[test]
+    fn reports_where_the_first_item_ends() {
+        assert_eq!(decode(&[0x01, 0x02, 0x03]), Ok((Value::Integer(1), 1)));
+    }
+
+    #[test]
+    fn rejects_truncated_input() {
+        for input in [&[][..], &[0x18], &[0x19, 0x01], &[0x43, 0x01, 0x02], &[0x82, 0x01], &[0xa1, 0x01], &[0x62, b'a']] {
+            assert_eq!(decode(input), Err(()), "{:02x?}", input);
+        }
+    }
+
+    #[test]
+    fn rejects_lengths_longer_than_the_input() {
+        // byte and text strings, arrays and maps claiming up to 2^64 - 1 entries
+        for major in [0x40, 0x60, 0x80, 0xa0] {
+            let mut input = vec![major | 27];
+            input.extend_from_slice(&u64::MAX.to_be_bytes());
+            assert_eq!(decode(&input), Err(()));
+            let mut input = vec![major | 26];
+            input.extend_from_slice(&u32::MAX.to_be_bytes());
+            input.push(0);
+            assert_eq!(decode(&input), Err(()));
+        }
+    }
+
+    #[test]
+    fn rejects_deep_nesting() {
+        let mut input = vec![0x81; MAX_DEPTH + 1];
+        input.push(0x00);
+        assert_eq!(decode(&input), Err(()));
+
+        let mut input = vec![0x81; MAX_DEPTH];
+        input.push(0x00);
+        assert!(decode(&input).is_ok());
+    }
+
+    #[test]
+    fn rejects_what_webauthn_does_not_use() {
+        // indefinite lengths, reserved additional info, tags, floats, undefined and invalid UTF-8
+        for input in [&[0x5f, 0xff][..], &[0x9f, 0xff], &[0x1c], &[0xc0, 0x00], &[0xf9, 0x00, 0x00], &[0xf7], &[0x61, 0xff]] {
+            assert_eq!(decode(input), Err(()), "{:02x?}", input);
+        }
+    }
+}
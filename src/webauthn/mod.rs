@@ -0,0 +1,295 @@
+mod authenticator;
+pub(crate) mod cbor;
+
+use rocket::serde::Deserialize;
+
+use crate::models::WebAuthnConfig;
+use cbor::Value;
+
+pub(crate) use authenticator::SoftwareAuthenticator;
+
+// COSE algorithms passkeys may use, in the order they are offered to authenticators
+pub(crate) const COSE_EDDSA: i32 = -8;
+pub(crate) const COSE_ES256: i32 = -7;
+pub(crate) const COSE_RS256: i32 = -257;
+pub(crate) const SUPPORTED_ALGORITHMS: [i32; 3] = [COSE_EDDSA, COSE_ES256, COSE_RS256];
+
+// authenticator data flags
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL: u8 = 0x40;
+
+// rpIdHash, flags and signCount come before anything else
+const AUTHENTICATOR_DATA_HEADER: usize = 32 + 1 + 4;
+const AAGUID_LENGTH: usize = 16;
+// WebAuthn level 3 caps credential ids at 1023 bytes
+const MAX_CREDENTIAL_ID_LENGTH: usize = 1023;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CeremonyError {
+    // anything that doesn't check out, the client is not told which part
+    Invalid,
+    UnsupportedAlgorithm,
+}
+
+// What is kept of a new credential
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct RegisteredCredential {
+    pub credential_id: Vec<u8>,
+    // DER SubjectPublicKeyInfo, openssl reads it back for every assertion
+    pub public_key: Vec<u8>,
+    pub algorithm: i32,
+    pub sign_count: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct ClientData {
+    #[serde(rename = "type")]
+    kind: String,
+    challenge: String,
+    origin: String,
+}
+
+struct AuthenticatorData<'a> {
+    rp_id_hash: &'a [u8],
+    flags: u8,
+    sign_count: u32,
+    // only after registration: credential id and the rest of the data, starting with the COSE key
+    attested: Option<(&'a [u8], &'a [u8])>,
+}
+
+// Attestation statements are not checked, spiritbox asks for `none` and trusts any authenticator
+pub(crate) fn verify_registration(
+    config: &WebAuthnConfig,
+    challenge: &[u8],
+    credential_id: &[u8],
+    client_data_json: &[u8],
+    attestation_object: &[u8],
+) -> Result<RegisteredCredential, CeremonyError> {
+    verify_client_data(config, "webauthn.create", challenge, client_data_json)?;
+
+    let (attestation, _) = cbor::decode(attestation_object).map_err(|_| CeremonyError::Invalid)?;
+    let auth_data = attestation.get_text("authData")
+        .and_then(Value::as_bytes)
+        .ok_or(CeremonyError::Invalid)?;
+    let auth_data = parse_authenticator_data(auth_data)?;
+    verify_authenticator_data(config, &auth_data)?;
+
+    let (attested_id, cose_key) = auth_data.attested.ok_or(CeremonyError::Invalid)?;
+    if attested_id != credential_id {
+        return Err(CeremonyError::Invalid);
+    }
+    let (cose_key, _) = cbor::decode(cose_key).map_err(|_| CeremonyError::Invalid)?;
+    let (algorithm, public_key) = parse_cose_key(&cose_key)?;
+
+    Ok(RegisteredCredential {
+        credential_id: attested_id.to_vec(),
+        public_key,
+        algorithm,
+        sign_count: auth_data.sign_count,
+    })
+}
+
+// Returns the new signature counter, the caller checks that it moved forward
+pub(crate) fn verify_assertion(
+    config: &WebAuthnConfig,
+    challenge: &[u8],
+    public_key: &[u8],
+    algorithm: i32,
+    client_data_json: &[u8],
+    authenticator_data: &[u8],
+    signature: &[u8],
+) -> Result<u32, CeremonyError> {
+    verify_client_data(config, "webauthn.get", challenge, client_data_json)?;
+
+    let auth_data = parse_authenticator_data(authenticator_data)?;
+    verify_authenticator_data(config, &auth_data)?;
+
+    let client_data_hash = openssl::sha::sha256(client_data_json);
+    let signed = [authenticator_data, &client_data_hash].concat();
+    if !verify_signature(public_key, algorithm, &signed, signature)? {
+        return Err(CeremonyError::Invalid);
+    }
+    Ok(auth_data.sign_count)
+}
+
+// Authenticators without a counter always send 0, any other counter has to move forward or the credential was cloned
+pub(crate) fn sign_count_advanced(stored: i64, received: u32) -> bool {
+    let received = i64::from(received);
+    (stored == 0 && received == 0) || received > stored
+}
+
+fn verify_client_data(config: &WebAuthnConfig, kind: &str, challenge: &[u8], client_data_json: &[u8]) -> Result<(), CeremonyError> {
+    use base64::Engine;
+
+    let client_data = rocket::serde::json::from_slice::<ClientData>(client_data_json).map_err(|_| CeremonyError::Invalid)?;
+    let sent_challenge = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(client_data.challenge.trim_end_matches('='))
+        .map_err(|_| CeremonyError::Invalid)?;
+
+    // the origin is what ties the response to the frontend, another site can't replay it
+    if client_data.kind != kind
+        || sent_challenge.len() != challenge.len()
+        || !openssl::memcmp::eq(&sent_challenge, challenge)
+        || !config.origins.contains(&client_data.origin) {
+        return Err(CeremonyError::Invalid);
+    }
+    Ok(())
+}
+
+fn verify_authenticator_data(config: &WebAuthnConfig, auth_data: &AuthenticatorData<'_>) -> Result<(), CeremonyError> {
+    if auth_data.rp_id_hash != openssl::sha::sha256(config.rp_id.as_bytes()).as_slice() {
+        return Err(CeremonyError::Invalid);
+    }
+    if auth_data.flags & FLAG_USER_PRESENT == 0 {
+        return Err(CeremonyError::Invalid);
+    }
+    if config.user_verification && auth_data.flags & FLAG_USER_VERIFIED == 0 {
+        return Err(CeremonyError::Invalid);
+    }
+    Ok(())
+}
+
+fn parse_authenticator_data(data: &[u8]) -> Result<AuthenticatorData<'_>, CeremonyError> {
+    if data.len() < AUTHENTICATOR_DATA_HEADER {
+        return Err(CeremonyError::Invalid);
+    }
+    let flags = data[32];
+    let sign_count = u32::from_be_bytes([data[33], data[34], data[35], data[36]]);
+
+    let attested = if flags & FLAG_ATTESTED_CREDENTIAL != 0 {
+        let rest = &data[AUTHENTICATOR_DATA_HEADER..];
+        let length_at = AAGUID_LENGTH;
+        let length = rest.get(length_at..length_at + 2).ok_or(CeremonyError::Invalid)?;
+        let length = usize::from(u16::from_be_bytes([length[0], length[1]]));
+        if length == 0 || length > MAX_CREDENTIAL_ID_LENGTH {
+            return Err(CeremonyError::Invalid);
+        }
+        let id_at = length_at + 2;
+        let credential_id = rest.get(id_at..id_at + length).ok_or(CeremonyError::Invalid)?;
+        Some((credential_id, &rest[id_at + length..]))
+    } else {
+        None
+    };
+
+    Ok(AuthenticatorData { rp_id_hash: &data[..32], flags, sign_count, attested })
+}
+
+// COSE_Key (RFC 9053) to DER, only the algorithms spiritbox offers are accepted
+fn parse_cose_key(key: &Value) -> Result<(i32, Vec<u8>), CeremonyError> {
+    use openssl::bn::BigNum;
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::nid::Nid;
+    use openssl::pkey::{Id, PKey};
+    use openssl::rsa::Rsa;
+
+    let int = |label| key.get_int(label).and_then(Value::as_integer);
+    let bytes = |label| key.get_int(label).and_then(Value::as_bytes).ok_or(CeremonyError::Invalid);
+    let invalid = |_| CeremonyError::Invalid;
+
+    let algorithm = int(3)
+        .and_then(|algorithm| i32::try_from(algorithm).ok())
+        .ok_or(CeremonyError::Invalid)?;
+    // kty 1 is OKP, 2 is EC2 and 3 is RSA; crv 6 is Ed25519 and 1 is P-256
+    let public_key = match (algorithm, int(1), int(-1)) {
+        (COSE_EDDSA, Some(1), Some(6)) => PKey::public_key_from_raw_bytes(bytes(-2)?, Id::ED25519).map_err(invalid)?,
+        (COSE_ES256, Some(2), Some(1)) => {
+            let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).map_err(invalid)?;
+            let x = BigNum::from_slice(bytes(-2)?).map_err(invalid)?;
+            let y = BigNum::from_slice(bytes(-3)?).map_err(invalid)?;
+            let ec_key = EcKey::from_public_key_affine_coordinates(&group, &x, &y).map_err(invalid)?;
+            ec_key.check_key().map_err(invalid)?;
+            PKey::from_ec_key(ec_key).map_err(invalid)?
+        }
+        (COSE_RS256, Some(3), _) => {
+            let n = BigNum::from_slice(bytes(-1)?).map_err(invalid)?;
+            let e = BigNum::from_slice(bytes(-2)?).map_err(invalid)?;
+            let rsa = Rsa::from_public_components(n, e).map_err(invalid)?;
+            if rsa.size() < 256 {
+                return Err(CeremonyError::UnsupportedAlgorithm);
+            }
+            PKey::from_rsa(rsa).map_err(invalid)?
+        }
+        (COSE_EDDSA | COSE_ES256 | COSE_RS256, _, _) => return Err(CeremonyError::Invalid),
+        _ => return Err(CeremonyError::UnsupportedAlgorithm),
+    };
+    Ok((algorithm, public_key.public_key_to_der().map_err(invalid)?))
+}
+
+fn verify_signature(public_key: &[u8], algorithm: i32, message: &[u8], signature: &[u8]) -> Result<bool, CeremonyError> {
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::PKey;
+    use openssl::sign::Verifier;
+
+    let public_key = PKey::public_key_from_der(public_key).map_err(|_| CeremonyError::Invalid)?;
+    let verifier = match algorithm {
+        COSE_EDDSA => Verifier::new_without_digest(&public_key),
+        // ES256 signatures are DER encoded, RS256 ones use PKCS#1 v1.5 padding, both are openssl's defaults
+        COSE_ES256 | COSE_RS256 => Verifier::new(MessageDigest::sha256(), &public_key),
+        _ => return Err(CeremonyError::UnsupportedAlgorithm),
+    };
+    // malformed signatures are just wrong signatures
+    Ok(verifier
+        .and_then(|mut verifier| verifier.verify_oneshot(signature, message))
+        .unwrap_or(false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counters_have_to_move_forward() {
+        assert!(sign_count_advanced(0, 0));
+        assert!(sign_count_advanced(0, 1));
+        assert!(sign_count_advanced(41, 42));
+        assert!(!sign_count_advanced(42, 42));
+        assert!(!sign_count_advanced(42, 7));
+        // a counter that was in use can't drop back to "no counter"
+        assert!(!sign_count_advanced(42, 0));
+    }
+
+    #[test]
+    fn short_authenticator_data_is_rejected() {
+        assert!(parse_authenticator_data(&[0; AUTHENTICATOR_DATA_HEADER - 1]).is_err());
+    }
+
+    #[test]
+    fn attested_credential_ids_are_bounded() {
+        let mut data = vec![0; 32];
+        data.push(FLAG_USER_PRESENT | FLAG_ATTESTED_CREDENTIAL);
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(&[0; AAGUID_LENGTH]);
+
+        let mut too_long = data.clone();
+        too_long.extend_from_slice(&((MAX_CREDENTIAL_ID_LENGTH + 1) as u16).to_be_bytes());
+        too_long.extend_from_slice(&vec![0; MAX_CREDENTIAL_ID_LENGTH + 1]);
+        assert!(parse_authenticator_data(&too_long).is_err());
+
+        // claims more bytes than there are
+        let mut truncated = data.clone();
+        truncated.extend_from_slice(&16u16.to_be_bytes());
+        truncated.extend_from_slice(&[1; 8]);
+        assert!(parse_authenticator_data(&truncated).is_err());
+    }
+
+    #[test]
+    fn malformed_attestation_objects_are_rejected() {
+        use base64::Engine;
+
+        let config = WebAuthnConfig::default();
+        let challenge = [1; 32];
+        let client_data = format!(
+            r#"{{"type":"webauthn.create","challenge":"{}","origin":"{}"}}"#,
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(challenge),
+            config.origins[0],
+        );
+        for attestation_object in [&[][..], &[0xa1], &[0xa1, 0x68], &[0x5f, 0xff], &[0xbf, 0xff]] {
+            assert_eq!(
+                verify_registration(&config, &challenge, &[1], client_data.as_bytes(), attestation_object),
+                Err(CeremonyError::Invalid),
+            );
+        }
+    }
+}